@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use respeaker::params::{ParamType, Value};
+use respeaker::respeaker_device::decode_response;
+
+fuzz_target!(|buffer: [u8; 8]| {
+    let float = decode_response(&ParamType::FloatRange { min: 0.0, max: 1.0 }, buffer);
+    match float {
+        Value::Float(f) => assert!(f.is_finite(), "{buffer:?} decoded to {f}"),
+        Value::Int(_) => panic!("Float param decoded to int"),
+    }
+
+    let int = decode_response(&ParamType::IntRange { min: 0, max: 1 }, buffer);
+    match int {
+        Value::Int(i) => assert!(i32::try_from(i).is_ok(), "{buffer:?} decoded to {i}"),
+        Value::Float(_) => panic!("Int param decoded to float"),
+    }
+});
@@ -0,0 +1,74 @@
+use std::process::Command;
+
+use eyre::bail;
+
+use crate::params::{ParamKind, Value};
+
+/// Whether a change of `param` from `old` to `new` is worth an alert.
+///
+/// Int parameters (e.g. the binary SPEECHDETECTED and VOICEACTIVITY) alert on every change, floats only
+/// if they moved by more than `threshold`.
+#[must_use]
+pub fn should_alert(param: &ParamKind, old: &Value, new: &Value, threshold: Option<f32>) -> bool {
+    match (old, new) {
+        (Value::Float(old), Value::Float(new)) if !param.def().param_type.is_int() => {
+            (new - old).abs() > threshold.unwrap_or(0.0)
+        }
+        _ => old != new,
+    }
+}
+
+/// Shows a desktop notification with `notify-send` (Linux, libnotify) or `osascript` (macOS).
+pub fn send_notification(summary: &str, body: &str) -> eyre::Result<()> {
+    #[cfg(target_os = "macos")]
+    let status = Command::new("osascript")
+        .arg("-e")
+        .arg(format!(
+            "display notification {body:?} with title {summary:?}"
+        ))
+        .status()?;
+    #[cfg(not(target_os = "macos"))]
+    let status = Command::new("notify-send").args([summary, body]).status()?;
+
+    if !status.success() {
+        bail!("Notification command failed with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::should_alert;
+    use crate::params::{ParamKind, Value};
+
+    #[rstest]
+    #[case(ParamKind::SPEECHDETECTED, Value::Int(0), Value::Int(1), None, true)]
+    #[case(
+        ParamKind::VOICEACTIVITY,
+        Value::Int(1),
+        Value::Int(0),
+        Some(5.0),
+        true
+    )]
+    #[case(ParamKind::VOICEACTIVITY, Value::Int(1), Value::Int(1), None, false)]
+    #[case(ParamKind::RT60, Value::Float(0.4), Value::Float(0.45), None, true)]
+    #[case(
+        ParamKind::RT60,
+        Value::Float(0.4),
+        Value::Float(0.45),
+        Some(0.1),
+        false
+    )]
+    #[case(ParamKind::RT60, Value::Float(0.4), Value::Float(0.2), Some(0.1), true)]
+    fn alert(
+        #[case] param: ParamKind,
+        #[case] old: Value,
+        #[case] new: Value,
+        #[case] threshold: Option<f32>,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(should_alert(&param, &old, &new, threshold), expected);
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Local, TimeDelta};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use tabled::{Table, Tabled};
+
+use crate::csv::CsvReader;
+use crate::params::{ParamKind, Value};
+
+/// Width of a [`DoaSector`] in degrees.
+const SECTOR_WIDTH: f32 = 45.0;
+/// How far DOAANGLE may move past a sector boundary before [`DoaSector::with_hysteresis`] switches.
+const SECTOR_HYSTERESIS: f32 = 5.0;
+
+/// One of eight 45° sectors of DOAANGLE. Front is centered on 0°, the angle increases counterclockwise
+/// (Left is 90°, Back 180°, Right 270°).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum DoaSector {
+    Front,
+    FrontLeft,
+    Left,
+    BackLeft,
+    Back,
+    BackRight,
+    Right,
+    FrontRight,
+}
+
+impl DoaSector {
+    /// Like `From<u16>`, but stays in `previous` until the angle is more than 5° past its boundary, so
+    /// an angle jittering around a boundary doesn't flip between two sectors.
+    #[must_use]
+    pub fn with_hysteresis(angle: u16, previous: Option<Self>) -> Self {
+        let sector = Self::from(angle);
+        match previous {
+            Some(previous)
+                if previous != sector
+                    && angle_distance(f32::from(angle), previous.center())
+                        <= SECTOR_WIDTH / 2.0 + SECTOR_HYSTERESIS =>
+            {
+                previous
+            }
+            _ => sector,
+        }
+    }
+
+    /// Center of the sector in degrees.
+    #[must_use]
+    pub fn center(self) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        let index = Self::iter().position(|s| s == self).unwrap_or_default() as f32;
+        index * SECTOR_WIDTH
+    }
+
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Front => "Front",
+            Self::FrontLeft => "Front left",
+            Self::Left => "Left",
+            Self::BackLeft => "Back left",
+            Self::Back => "Back",
+            Self::BackRight => "Back right",
+            Self::Right => "Right",
+            Self::FrontRight => "Front right",
+        }
+    }
+
+    /// Arrow pointing in the direction of the sector, front is up.
+    #[must_use]
+    pub const fn icon(self) -> char {
+        match self {
+            Self::Front => '↑',
+            Self::FrontLeft => '↖',
+            Self::Left => '←',
+            Self::BackLeft => '↙',
+            Self::Back => '↓',
+            Self::BackRight => '↘',
+            Self::Right => '→',
+            Self::FrontRight => '↗',
+        }
+    }
+}
+
+/// Maps DOAANGLE (degrees, values of 360 and more wrap around) to its sector without hysteresis.
+impl From<u16> for DoaSector {
+    fn from(angle: u16) -> Self {
+        let shifted = (f32::from(angle) + SECTOR_WIDTH / 2.0).rem_euclid(360.0);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let index = (shifted / SECTOR_WIDTH) as usize;
+        Self::iter().nth(index).unwrap_or(Self::Front)
+    }
+}
+
+/// Distance between two angles in degrees, at most 180.
+fn angle_distance(a: f32, b: f32) -> f32 {
+    let diff = (a - b).rem_euclid(360.0);
+    diff.min(360.0 - diff)
+}
+
+/// One utterance of a recording, a contiguous span of rows with VOICEACTIVITY=1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeechSegment {
+    /// Timestamp of the first row with VOICEACTIVITY=1.
+    pub start: DateTime<Local>,
+    /// Timestamp of the last row with VOICEACTIVITY=1.
+    pub end: DateTime<Local>,
+    /// Circular mean of DOAANGLE in degrees, `None` if the recording has no DOAANGLE column.
+    pub mean_doa: Option<f32>,
+    pub mean_rt60: Option<f32>,
+    /// Whether SPEECHDETECTED was 1 in any row of the segment.
+    pub speech_detected: bool,
+    /// Number of times AECPATHCHANGE went to 1.
+    pub aec_path_changes: usize,
+}
+
+impl SpeechSegment {
+    #[must_use]
+    pub fn duration(&self) -> TimeDelta {
+        self.end - self.start
+    }
+}
+
+#[derive(Default)]
+struct OpenSegment {
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+    doa_sin: f32,
+    doa_cos: f32,
+    doa_count: u32,
+    rt60_sum: f32,
+    rt60_count: u32,
+    speech_detected: bool,
+    aec_path_changes: usize,
+    aec_path_change: bool,
+}
+
+impl OpenSegment {
+    #[allow(clippy::cast_precision_loss)]
+    fn add(&mut self, timestamp: DateTime<Local>, row: &HashMap<ParamKind, Value>) {
+        self.start.get_or_insert(timestamp);
+        self.end = Some(timestamp);
+        if let Some(Value::Int(doa)) = row.get(&ParamKind::DOAANGLE) {
+            let radians = (*doa as f32).to_radians();
+            self.doa_sin += radians.sin();
+            self.doa_cos += radians.cos();
+            self.doa_count += 1;
+        }
+        if let Some(Value::Float(rt60)) = row.get(&ParamKind::RT60) {
+            self.rt60_sum += rt60;
+            self.rt60_count += 1;
+        }
+        self.speech_detected |= row.get(&ParamKind::SPEECHDETECTED) == Some(&Value::Int(1));
+        let aec_path_change = row.get(&ParamKind::AECPATHCHANGE) == Some(&Value::Int(1));
+        if aec_path_change && !self.aec_path_change {
+            self.aec_path_changes += 1;
+        }
+        self.aec_path_change = aec_path_change;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn close(self) -> Option<SpeechSegment> {
+        Some(SpeechSegment {
+            start: self.start?,
+            end: self.end?,
+            // Circular mean, the mean of 350° and 10° is 0°
+            mean_doa: (self.doa_count > 0).then(|| {
+                self.doa_sin
+                    .atan2(self.doa_cos)
+                    .to_degrees()
+                    .rem_euclid(360.0)
+            }),
+            mean_rt60: (self.rt60_count > 0).then(|| self.rt60_sum / self.rt60_count as f32),
+            speech_detected: self.speech_detected,
+            aec_path_changes: self.aec_path_changes,
+        })
+    }
+}
+
+/// Splits a recording into [`SpeechSegment`]s. Rows without a VOICEACTIVITY value (e.g. the RW refresh
+/// rows) neither extend nor close a segment.
+pub fn extract_segments(reader: &mut CsvReader) -> eyre::Result<Vec<SpeechSegment>> {
+    let mut segments = vec![];
+    let mut open: Option<OpenSegment> = None;
+    for row in reader.rows() {
+        let row = row?;
+        let Some(voice_activity) = row.values.get(&ParamKind::VOICEACTIVITY) else {
+            continue;
+        };
+        if *voice_activity == Value::Int(1) {
+            // timestamp_before_read is RW_REFRESH in refresh rows, the after timestamp is always set
+            let timestamp =
+                DateTime::parse_from_rfc3339(&row.timestamp_after)?.with_timezone(&Local);
+            open.get_or_insert_with(OpenSegment::default)
+                .add(timestamp, &row.values);
+        } else if let Some(segment) = open.take().and_then(OpenSegment::close) {
+            segments.push(segment);
+        }
+    }
+    segments.extend(open.and_then(OpenSegment::close));
+    Ok(segments)
+}
+
+#[derive(Tabled)]
+struct SegmentRecord {
+    start: String,
+    end: String,
+    duration_s: String,
+    mean_doa: String,
+    mean_rt60: String,
+    speech_detected: bool,
+    aec_path_changes: usize,
+}
+
+impl From<&SpeechSegment> for SegmentRecord {
+    #[allow(clippy::cast_precision_loss)]
+    fn from(segment: &SpeechSegment) -> Self {
+        let optional = |value: Option<f32>, precision: usize| {
+            value.map_or_else(String::new, |v| format!("{v:.precision$}"))
+        };
+        Self {
+            start: segment.start.format("%+").to_string(),
+            end: segment.end.format("%+").to_string(),
+            duration_s: format!(
+                "{:.3}",
+                segment.duration().num_milliseconds() as f64 / 1000.0
+            ),
+            mean_doa: optional(segment.mean_doa, 1),
+            mean_rt60: optional(segment.mean_rt60, 3),
+            speech_detected: segment.speech_detected,
+            aec_path_changes: segment.aec_path_changes,
+        }
+    }
+}
+
+#[must_use]
+pub fn segments_table(segments: &[SpeechSegment]) -> String {
+    Table::new(segments.iter().map(SegmentRecord::from)).to_string()
+}
+
+/// Writes one CSV row per segment, the columns are the same as in [`segments_table`].
+pub fn write_segments_csv(path: &Path, segments: &[SpeechSegment]) -> eyre::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "start",
+        "end",
+        "duration_s",
+        "mean_doa",
+        "mean_rt60",
+        "speech_detected",
+        "aec_path_changes",
+    ])?;
+    for segment in segments {
+        let record = SegmentRecord::from(segment);
+        writer.write_record([
+            record.start,
+            record.end,
+            record.duration_s,
+            record.mean_doa,
+            record.mean_rt60,
+            record.speech_detected.to_string(),
+            record.aec_path_changes.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::DoaSector;
+
+    #[rstest]
+    #[case(0, DoaSector::Front)]
+    #[case(22, DoaSector::Front)]
+    #[case(23, DoaSector::FrontLeft)]
+    #[case(90, DoaSector::Left)]
+    #[case(180, DoaSector::Back)]
+    #[case(270, DoaSector::Right)]
+    #[case(337, DoaSector::FrontRight)]
+    #[case(338, DoaSector::Front)]
+    #[case(359, DoaSector::Front)]
+    #[case(360, DoaSector::Front)]
+    fn sector_from_angle(#[case] angle: u16, #[case] expected: DoaSector) {
+        assert_eq!(DoaSector::from(angle), expected);
+    }
+
+    #[rstest]
+    #[case(25, Some(DoaSector::Front), DoaSector::Front)]
+    #[case(27, Some(DoaSector::Front), DoaSector::Front)]
+    #[case(28, Some(DoaSector::Front), DoaSector::FrontLeft)]
+    #[case(20, Some(DoaSector::FrontLeft), DoaSector::FrontLeft)]
+    #[case(355, Some(DoaSector::FrontLeft), DoaSector::Front)]
+    #[case(25, None, DoaSector::FrontLeft)]
+    fn sector_hysteresis(
+        #[case] angle: u16,
+        #[case] previous: Option<DoaSector>,
+        #[case] expected: DoaSector,
+    ) {
+        assert_eq!(DoaSector::with_hysteresis(angle, previous), expected);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    SampleFormat, Stream,
+};
+use eyre::{bail, OptionExt};
+use hound::{WavSpec, WavWriter};
+use tracing::{info, warn};
+
+type SharedWavWriter = Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>;
+
+/// Records the microphone input of the device to a 16 bit WAV file until [`AudioCapture::finish`] is called.
+pub struct AudioCapture {
+    stream: Stream,
+    writer: SharedWavWriter,
+    started_at: Arc<OnceLock<DateTime<Local>>>,
+}
+
+impl AudioCapture {
+    pub fn start(wav_path: &Path) -> eyre::Result<Self> {
+        let host = cpal::default_host();
+        let device = host
+            .input_devices()?
+            .find(|d| d.name().is_ok_and(|name| name.contains("ReSpeaker")))
+            .ok_or_eyre("No ReSpeaker audio input found")?;
+        let config = device.default_input_config()?;
+        info!(
+            "Recording {} channels at {} Hz from {}",
+            config.channels(),
+            config.sample_rate().0,
+            device.name()?
+        );
+
+        let spec = WavSpec {
+            channels: config.channels(),
+            sample_rate: config.sample_rate().0,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer: SharedWavWriter =
+            Arc::new(Mutex::new(Some(WavWriter::create(wav_path, spec)?)));
+        let started_at = Arc::new(OnceLock::new());
+
+        let stream = {
+            let writer = writer.clone();
+            let started_at = started_at.clone();
+            let write = move |samples: &mut dyn Iterator<Item = i16>| {
+                started_at.get_or_init(Local::now);
+                if let Some(writer) = writer.lock().expect("Lock failed").as_mut() {
+                    for sample in samples {
+                        if let Err(e) = writer.write_sample(sample) {
+                            warn!("Could not write audio sample: {e}");
+                            break;
+                        }
+                    }
+                }
+            };
+            let on_error = |e| warn!("Audio stream error: {e}");
+            match config.sample_format() {
+                SampleFormat::I16 => device.build_input_stream(
+                    &config.into(),
+                    move |data: &[i16], _: &_| write(&mut data.iter().copied()),
+                    on_error,
+                    None,
+                )?,
+                SampleFormat::F32 => device.build_input_stream(
+                    &config.into(),
+                    move |data: &[f32], _: &_| {
+                        #[allow(clippy::cast_possible_truncation)]
+                        write(
+                            &mut data
+                                .iter()
+                                .map(|s| (s.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16),
+                        );
+                    },
+                    on_error,
+                    None,
+                )?,
+                format => bail!("Unsupported sample format {format}"),
+            }
+        };
+        stream.play()?;
+
+        Ok(Self {
+            stream,
+            writer,
+            started_at,
+        })
+    }
+
+    /// Local time at which the first audio samples arrived, i.e. the start of the WAV timeline.
+    pub fn wait_started(&self, timeout: Duration) -> eyre::Result<DateTime<Local>> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some(started_at) = self.started_at.get() {
+                return Ok(*started_at);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        bail!("No audio received within {timeout:?}")
+    }
+
+    /// Stops the stream and finalizes the WAV header.
+    pub fn finish(self) -> eyre::Result<()> {
+        drop(self.stream);
+        let writer = self.writer.lock().expect("Lock failed").take();
+        if let Some(writer) = writer {
+            writer.finalize()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+use std::{fmt::Write as _, time::Duration};
+
+use clap::ValueEnum;
+
+use crate::{
+    build_string,
+    params::ParamKind,
+    respeaker_device::{ReSpeakerDevice, TransferTiming},
+};
+
+const HISTOGRAM_BUCKETS: usize = 10;
+const HISTOGRAM_BAR_WIDTH: usize = 40;
+/// Transfers taking more than this multiple of the median probably waited for NAK retries.
+const RETRY_FACTOR: u32 = 2;
+/// Growth of the mean latency from the first to the last quarter of the run which hints at a saturated bus.
+const SATURATION_TREND_PERCENT: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum BenchmarkProfile {
+    /// Mean, min and max of complete parameter reads, including decoding and the cache update.
+    #[default]
+    Latency,
+    /// Timing distribution of the raw control transfers, short transfers, suspected NAK retries and
+    /// whether back-to-back reads get slower.
+    Usb,
+}
+
+/// Reads `param` `iterations` times back-to-back and returns the report of `profile`.
+pub fn run_benchmark(
+    device: &ReSpeakerDevice,
+    profile: BenchmarkProfile,
+    param: &ParamKind,
+    iterations: usize,
+) -> eyre::Result<String> {
+    match profile {
+        BenchmarkProfile::Latency => {
+            let mut durations = Vec::with_capacity(iterations);
+            for _ in 0..iterations {
+                let start = std::time::Instant::now();
+                device.read(param)?;
+                durations.push(start.elapsed());
+            }
+            Ok(latency_report(param, &durations))
+        }
+        BenchmarkProfile::Usb => {
+            let timings = (0..iterations)
+                .map(|_| device.time_read_control(param))
+                .collect::<eyre::Result<Vec<_>>>()?;
+            Ok(UsbProfile::new(&timings).report(param))
+        }
+    }
+}
+
+fn latency_report(param: &ParamKind, durations: &[Duration]) -> String {
+    let mut sorted = durations.to_vec();
+    sorted.sort();
+    format!(
+        "{} reads of {param:?}: mean {:?}, min {:?}, max {:?}\n",
+        durations.len(),
+        mean(durations),
+        sorted.first().copied().unwrap_or_default(),
+        sorted.last().copied().unwrap_or_default(),
+    )
+}
+
+/// Statistics of back-to-back control transfers, see [`BenchmarkProfile::Usb`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsbProfile {
+    pub transfers: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    pub std_dev: Duration,
+    /// Transfers which took more than twice the median, most likely because the device answered with a
+    /// NAK and the host controller retried in a later frame.
+    pub suspected_retries: usize,
+    /// Transfers which returned fewer bytes than requested.
+    pub short_transfers: usize,
+    /// Change of the mean latency from the first to the last quarter of the run in percent. A clear
+    /// increase hints at a saturated bus.
+    pub trend_percent: f64,
+    /// `(from, to, count)` of [`HISTOGRAM_BUCKETS`] equally wide latency buckets between min and max.
+    pub histogram: Vec<(Duration, Duration, usize)>,
+}
+
+impl UsbProfile {
+    #[must_use]
+    pub fn new(timings: &[TransferTiming]) -> Self {
+        let durations = timings.iter().map(|t| t.elapsed).collect::<Vec<_>>();
+        let mut sorted = durations.clone();
+        sorted.sort();
+        let median = percentile(&sorted, 50);
+        let quarter = durations.len() / 4;
+        let trend_percent = if quarter == 0 {
+            0.0
+        } else {
+            let first = mean(&durations[..quarter]).as_secs_f64();
+            let last = mean(&durations[durations.len() - quarter..]).as_secs_f64();
+            if first > 0.0 {
+                (last / first - 1.0) * 100.0
+            } else {
+                0.0
+            }
+        };
+        Self {
+            transfers: timings.len(),
+            min: sorted.first().copied().unwrap_or_default(),
+            median,
+            mean: mean(&durations),
+            p99: percentile(&sorted, 99),
+            max: sorted.last().copied().unwrap_or_default(),
+            std_dev: std_dev(&durations),
+            suspected_retries: durations
+                .iter()
+                .filter(|d| **d > median * RETRY_FACTOR)
+                .count(),
+            short_transfers: timings.iter().filter(|t| t.returned < t.requested).count(),
+            trend_percent,
+            histogram: histogram(&durations, HISTOGRAM_BUCKETS),
+        }
+    }
+
+    /// Whether back-to-back transfers got noticeably slower over the run.
+    #[must_use]
+    pub fn is_saturated(&self) -> bool {
+        self.trend_percent > SATURATION_TREND_PERCENT
+    }
+
+    #[must_use]
+    pub fn report(&self, param: &ParamKind) -> String {
+        build_string(|out| {
+            writeln!(
+                out,
+                "{} control transfers reading {param:?}",
+                self.transfers
+            )?;
+            writeln!(
+                out,
+                "  min {:?}, median {:?}, mean {:?}, p99 {:?}, max {:?}, std dev {:?}",
+                self.min, self.median, self.mean, self.p99, self.max, self.std_dev
+            )?;
+            writeln!(
+                out,
+                "  suspected NAK retries: {} (> {RETRY_FACTOR}x median)",
+                self.suspected_retries
+            )?;
+            writeln!(
+                out,
+                "  short transfers: {} of {}",
+                self.short_transfers, self.transfers
+            )?;
+            writeln!(
+                out,
+                "  latency first -> last quarter: {:+.1}%{}",
+                self.trend_percent,
+                if self.is_saturated() {
+                    " (bus saturation?)"
+                } else {
+                    ""
+                }
+            )?;
+            writeln!(
+                out,
+                "  poll intervals below the p99 latency ({:?}) will fall behind",
+                self.p99
+            )?;
+            writeln!(out)?;
+            let largest = self.histogram.iter().map(|(_, _, c)| *c).max().unwrap_or(0);
+            for (from, to, count) in &self.histogram {
+                let width = if largest == 0 {
+                    0
+                } else {
+                    count * HISTOGRAM_BAR_WIDTH / largest
+                };
+                writeln!(
+                    out,
+                    "  {:>10} .. {:<10} {:<HISTOGRAM_BAR_WIDTH$} {count}",
+                    format!("{from:.1?}"),
+                    format!("{to:.1?}"),
+                    "█".repeat(width),
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn mean(durations: &[Duration]) -> Duration {
+    let count = u32::try_from(durations.len()).unwrap_or(u32::MAX);
+    if count == 0 {
+        return Duration::ZERO;
+    }
+    durations.iter().sum::<Duration>() / count
+}
+
+fn std_dev(durations: &[Duration]) -> Duration {
+    if durations.is_empty() {
+        return Duration::ZERO;
+    }
+    let mean = mean(durations).as_secs_f64();
+    #[allow(clippy::cast_precision_loss)]
+    let variance = durations
+        .iter()
+        .map(|d| (d.as_secs_f64() - mean).powi(2))
+        .sum::<f64>()
+        / durations.len() as f64;
+    Duration::from_secs_f64(variance.sqrt())
+}
+
+/// Nearest-rank percentile of ascending `sorted` durations.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Splits `min..=max` of `durations` into `buckets` equally wide buckets and counts the durations in each.
+fn histogram(durations: &[Duration], buckets: usize) -> Vec<(Duration, Duration, usize)> {
+    let (Some(min), Some(max)) = (durations.iter().min(), durations.iter().max()) else {
+        return vec![];
+    };
+    let range = (*max - *min).as_nanos();
+    let bucket_of = |d: &Duration| {
+        if range == 0 {
+            return 0;
+        }
+        let offset = (*d - *min).as_nanos();
+        usize::try_from(offset * buckets as u128 / range)
+            .unwrap_or(buckets)
+            .min(buckets - 1)
+    };
+    let mut counts = vec![0; buckets];
+    for d in durations {
+        counts[bucket_of(d)] += 1;
+    }
+    let width = (*max - *min) / u32::try_from(buckets).unwrap_or(u32::MAX);
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let i = u32::try_from(i).unwrap_or(u32::MAX);
+            (*min + width * i, *min + width * (i + 1), count)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::{histogram, percentile, UsbProfile};
+    use crate::respeaker_device::TransferTiming;
+
+    fn us(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[rstest]
+    #[case(&[], 50, 0)]
+    #[case(&[10], 99, 10)]
+    #[case(&[10, 20, 30, 40], 50, 20)]
+    #[case(&[10, 20, 30, 40], 99, 40)]
+    fn nearest_rank_percentile(
+        #[case] sorted: &[u64],
+        #[case] percent: usize,
+        #[case] expected: u64,
+    ) {
+        let sorted = sorted.iter().copied().map(us).collect::<Vec<_>>();
+        assert_eq!(percentile(&sorted, percent), us(expected));
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let durations = [100, 100, 105, 150, 199, 200].map(us);
+
+        let histogram = histogram(&durations, 10);
+
+        assert_eq!(histogram.len(), 10);
+        assert_eq!(histogram[0], (us(100), us(110), 3));
+        assert_eq!(histogram[5].2, 1);
+        // The maximum belongs to the last bucket
+        assert_eq!(histogram[9], (us(190), us(200), 2));
+        assert_eq!(histogram.iter().map(|(_, _, c)| c).sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn histogram_of_equal_durations() {
+        let histogram = histogram(&[us(50); 3], 10);
+
+        assert_eq!(histogram[0].2, 3);
+    }
+
+    #[test]
+    fn usb_profile() {
+        let timing = |micros, returned| TransferTiming {
+            elapsed: us(micros),
+            requested: 8,
+            returned,
+        };
+        let timings = [
+            timing(100, 8),
+            timing(100, 8),
+            timing(110, 8),
+            timing(120, 4),
+            timing(130, 8),
+            timing(140, 8),
+            timing(300, 8),
+            timing(400, 8),
+        ];
+
+        let profile = UsbProfile::new(&timings);
+
+        assert_eq!(profile.transfers, 8);
+        assert_eq!(profile.median, us(120));
+        assert_eq!(profile.suspected_retries, 2);
+        assert_eq!(profile.short_transfers, 1);
+        // 100 / 350 µs in the first / last quarter
+        assert!((profile.trend_percent - 250.0).abs() < 1e-6);
+        assert!(profile.is_saturated());
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use eyre::{bail, Context};
+use strum::IntoEnumIterator;
+
+use crate::params::{ParamKind, Value};
+
+const MAGIC: &[u8; 4] = b"RSPK";
+const VERSION: u8 = 1;
+/// Far more than a row with all parameters, a larger length means the file is corrupt.
+const MAX_ROW_LEN: u32 = 64 * 1024;
+
+/// Timestamp in nanoseconds since the Unix epoch and (parameter index, raw value) pairs.
+type RawRow = (u64, Vec<(u8, u32)>);
+
+/// Writes recordings in a compact binary format. A path of `-` writes to stdout instead of a file.
+///
+/// The file starts with `RSPK` and a version byte. Each row is the length of the bincode encoded
+/// [`RawRow`] (u32 LE), the row itself and its CRC32 (u32 LE). Parameters are stored by their
+/// declaration index, ints as u32 and floats as their IEEE 754 bits.
+pub struct BinaryRowWriter {
+    writer: Box<dyn Write>,
+}
+
+impl BinaryRowWriter {
+    pub fn new(file_path: &Path) -> eyre::Result<Self> {
+        let mut writer: Box<dyn Write> = if file_path.as_os_str() == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(file_path)?)
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.flush()?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_row(
+        &mut self,
+        timestamp_unix_ns: u64,
+        values: &HashMap<ParamKind, Value>,
+    ) -> eyre::Result<()> {
+        let raw_values = ParamKind::iter()
+            .zip(0u8..)
+            .filter_map(|(param, index)| {
+                let raw = match values.get(&param)? {
+                    Value::Int(i) => u32::try_from(*i).ok()?,
+                    Value::Float(f) => f.to_bits(),
+                };
+                Some((index, raw))
+            })
+            .collect();
+        let row: RawRow = (timestamp_unix_ns, raw_values);
+        let bytes = bincode::serialize(&row)?;
+
+        self.writer
+            .write_all(&u32::try_from(bytes.len())?.to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.writer
+            .write_all(&crc32fast::hash(&bytes).to_le_bytes())?;
+        // Rows should show up immediately when piped
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// One row of a binary recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryRow {
+    pub timestamp_unix_ns: u64,
+    pub values: HashMap<ParamKind, Value>,
+}
+
+/// Reads recordings written by [`BinaryRowWriter`]. A row with a wrong checksum is an error.
+pub struct BinaryReader {
+    reader: BufReader<File>,
+}
+
+impl BinaryReader {
+    pub fn new(file_path: &Path) -> eyre::Result<Self> {
+        let file =
+            File::open(file_path).with_context(|| format!("Could not open {file_path:?}"))?;
+        let mut reader = BufReader::new(file);
+        let mut header = [0u8; 5];
+        reader
+            .read_exact(&mut header)
+            .context("Not a binary recording")?;
+        if &header[..4] != MAGIC {
+            bail!("Not a binary recording, {file_path:?} does not start with RSPK");
+        }
+        if header[4] != VERSION {
+            bail!("Unsupported binary recording version {}", header[4]);
+        }
+        Ok(Self { reader })
+    }
+
+    /// `None` only at the end of the file after a complete row, a partial row is an error.
+    fn read_row(&mut self) -> eyre::Result<Option<BinaryRow>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut len = [0u8; 4];
+        self.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+        if len > MAX_ROW_LEN {
+            bail!("Row length {len} is too large, the recording is corrupt");
+        }
+        let mut bytes = vec![0u8; len as usize];
+        self.read_exact(&mut bytes)?;
+        let mut crc = [0u8; 4];
+        self.read_exact(&mut crc)?;
+        if crc32fast::hash(&bytes) != u32::from_le_bytes(crc) {
+            bail!("Checksum mismatch, the recording is corrupt");
+        }
+
+        let (timestamp_unix_ns, raw_values): RawRow = bincode::deserialize(&bytes)?;
+        let mut values = HashMap::new();
+        for (index, raw) in raw_values {
+            let Some(param) = ParamKind::iter().nth(usize::from(index)) else {
+                bail!("Unknown parameter index {index}");
+            };
+            let value = if param.def().param_type.is_int() {
+                Value::Int(raw as usize)
+            } else {
+                Value::Float(f32::from_bits(raw))
+            };
+            values.insert(param, value);
+        }
+        Ok(Some(BinaryRow {
+            timestamp_unix_ns,
+            values,
+        }))
+    }
+}
+
+impl BinaryReader {
+    fn read_exact(&mut self, buf: &mut [u8]) -> eyre::Result<()> {
+        match self.reader.read_exact(buf) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                bail!("The last row is truncated, the recording is incomplete")
+            }
+            result => Ok(result?),
+        }
+    }
+}
+
+impl Iterator for BinaryReader {
+    type Item = eyre::Result<BinaryRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_row().transpose()
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use eyre::{bail, Context};
+use strum::IntoEnumIterator;
+
+use crate::params::{Access, ParamKind, ParamState, Value};
+
+/// `<config dir>/respeaker`, created by `respeaker init`.
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("respeaker"))
+}
+
+/// `<config dir>/respeaker/config.toml`, the parameter defaults and the [`CliSettings`] written by
+/// `respeaker init`.
+#[must_use]
+pub fn config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// `<config dir>/respeaker/presets`, configs (see [`save_config`]) which can be loaded by name.
+#[must_use]
+pub fn presets_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("presets"))
+}
+
+/// Settings of the command line tool, the `[cli]` table of [`config_file`]. Unlike the parameters they
+/// are not applied to the device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CliSettings {
+    /// Default file of `write --log-change`.
+    pub change_log: Option<PathBuf>,
+}
+
+impl CliSettings {
+    /// Loads the settings from [`config_file`], the defaults if there is none.
+    pub fn load() -> eyre::Result<Self> {
+        let Some(path) = config_file().filter(|path| path.exists()) else {
+            return Ok(Self::default());
+        };
+        let toml = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read settings from {path:?}"))?;
+        Self::from_toml(&toml).with_context(|| format!("Invalid settings {path:?}"))
+    }
+
+    /// Parses the `[cli]` table of a config, the parameters are ignored.
+    pub fn from_toml(toml: &str) -> eyre::Result<Self> {
+        let mut table: toml::Table = toml.parse()?;
+        let mut settings = Self::default();
+        let Some(cli) = table.remove("cli") else {
+            return Ok(settings);
+        };
+        let toml::Value::Table(cli) = cli else {
+            bail!("[cli] is not a table");
+        };
+        for (key, value) in cli {
+            match (key.as_str(), value) {
+                ("change_log", toml::Value::String(path)) => {
+                    settings.change_log = Some(PathBuf::from(path));
+                }
+                (key, value) => bail!("Unknown setting {key} = {value}"),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Saves the RW parameters of `state` as a TOML file of `PARAM = value` pairs.
+pub fn save_config(path: &Path, state: &ParamState) -> eyre::Result<()> {
+    fs::write(path, config_to_toml(state)?)
+        .with_context(|| format!("Could not write config to {path:?}"))
+}
+
+pub fn load_config(path: &Path) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let toml =
+        fs::read_to_string(path).with_context(|| format!("Could not read config from {path:?}"))?;
+    config_from_toml(&toml).with_context(|| format!("Invalid config {path:?}"))
+}
+
+pub fn config_to_toml(state: &ParamState) -> eyre::Result<String> {
+    Ok(params_to_table(state, Access::ReadWrite)?.to_string())
+}
+
+/// A commented config with every RW parameter at its factory default, see [`ParamDef::default_value`],
+/// followed by the [`CliSettings`] in a `[cli]` table.
+///
+/// Parameters without a documented default and unset settings are commented out. [`config_from_toml`]
+/// and [`CliSettings::from_toml`] can read it.
+///
+/// [`ParamDef::default_value`]: crate::params::ParamDef::default_value
+pub fn default_config_toml() -> eyre::Result<String> {
+    let mut toml = String::from(
+        "# ReSpeaker config with the factory defaults, written by `respeaker init`.\n\
+         # Parameters without a documented default are commented out.\n",
+    );
+    for param in ParamKind::sorted_by_name()
+        .into_iter()
+        .filter(|p| p.def().access == Access::ReadWrite)
+    {
+        let def = param.def();
+        writeln!(
+            toml,
+            "\n# {} [{}..{}]",
+            def.description.trim(),
+            def.min(),
+            def.max()
+        )?;
+        match def.default_value() {
+            Some(value) => writeln!(toml, "{param:?} = {}", toml_value(&value)?)?,
+            None => writeln!(toml, "# {param:?} =")?,
+        }
+    }
+    toml.push_str(
+        "\n# Settings of the command line tool, they are not applied to the device.\n\
+         [cli]\n\
+         \n\
+         # Default file of `write --log-change`\n\
+         # change_log = \"/path/to/changes.log\"\n",
+    );
+    Ok(toml)
+}
+
+/// Parses a config written by [`config_to_toml`]. Only RW parameters are allowed, the `[cli]` table
+/// ([`CliSettings`]) is skipped.
+pub fn config_from_toml(toml: &str) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let mut table: toml::Table = toml.parse()?;
+    table.remove("cli");
+    let mut params = HashMap::new();
+    for (key, value) in table {
+        let param = ParamKind::from_str(&key, false)
+            .map_err(|e| eyre::eyre!("Unknown parameter {key}: {e}"))?;
+        if param.def().access != Access::ReadWrite {
+            bail!("Parameter {key} is read-only");
+        }
+        params.insert(param.clone(), value_from_toml(&param, &value)?);
+    }
+    validated(params)
+}
+
+/// Saves all parameters of `state`, RW and RO, for diagnostics. Unlike a config, a snapshot can't be
+/// applied to a device.
+pub fn save_snapshot(path: &Path, state: &ParamState) -> eyre::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, snapshot_to_toml(state)?)
+        .with_context(|| format!("Could not write snapshot to {path:?}"))
+}
+
+pub fn load_snapshot(path: &Path) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let toml = fs::read_to_string(path)
+        .with_context(|| format!("Could not read snapshot from {path:?}"))?;
+    snapshot_from_toml(&toml).with_context(|| format!("Invalid snapshot {path:?}"))
+}
+
+/// Like [`config_to_toml`] but with all parameters, RW ones in a `[read_write]` and RO ones in a
+/// `[read_only]` section.
+pub fn snapshot_to_toml(state: &ParamState) -> eyre::Result<String> {
+    let mut table = toml::Table::new();
+    for (section, access) in SNAPSHOT_SECTIONS {
+        table.insert(
+            section.to_string(),
+            toml::Value::Table(params_to_table(state, access)?),
+        );
+    }
+    Ok(table.to_string())
+}
+
+/// Parses a snapshot written by [`snapshot_to_toml`]. Parameters have to be in the section matching
+/// their access.
+pub fn snapshot_from_toml(toml: &str) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let mut table: toml::Table = toml.parse()?;
+    let mut params = HashMap::new();
+    for (section, access) in SNAPSHOT_SECTIONS {
+        let Some(values) = table.remove(section) else {
+            continue;
+        };
+        let toml::Value::Table(values) = values else {
+            bail!("[{section}] is not a table");
+        };
+        for (key, value) in values {
+            let param = ParamKind::from_str(&key, false)
+                .map_err(|e| eyre::eyre!("Unknown parameter {key}: {e}"))?;
+            if param.def().access != access {
+                bail!("Parameter {key} does not belong to [{section}]");
+            }
+            params.insert(param.clone(), value_from_toml(&param, &value)?);
+        }
+    }
+    if let Some(key) = table.keys().next() {
+        bail!("Unknown section {key}");
+    }
+    validated(params)
+}
+
+/// Parameters whose values differ between two snapshots, in declaration order. `None` if the parameter
+/// is missing in that snapshot.
+#[must_use]
+pub fn diff_snapshots<S: BuildHasher>(
+    a: &HashMap<ParamKind, Value, S>,
+    b: &HashMap<ParamKind, Value, S>,
+) -> Vec<(ParamKind, Option<Value>, Option<Value>)> {
+    ParamKind::iter()
+        .filter(|p| a.get(p) != b.get(p))
+        .map(|p| {
+            let (old, new) = (a.get(&p).cloned(), b.get(&p).cloned());
+            (p, old, new)
+        })
+        .collect()
+}
+
+const SNAPSHOT_SECTIONS: [(&str, Access); 2] = [
+    ("read_write", Access::ReadWrite),
+    ("read_only", Access::ReadOnly),
+];
+
+fn params_to_table(state: &ParamState, access: Access) -> eyre::Result<toml::Table> {
+    let mut table = toml::Table::new();
+    for param in ParamKind::iter().filter(|p| p.def().access == access) {
+        if let Some(value) = state.current_params.get(&param) {
+            table.insert(format!("{param:?}"), toml_value(value)?);
+        }
+    }
+    Ok(table)
+}
+
+fn toml_value(value: &Value) -> eyre::Result<toml::Value> {
+    Ok(match value {
+        Value::Int(i) => toml::Value::Integer(i64::try_from(*i)?),
+        // Via the string to keep the shortest representation, e.g. 0.45 instead of 0.44999998807907104
+        Value::Float(f) => toml::Value::Float(f.to_string().parse()?),
+    })
+}
+
+/// Rejects values outside of the parameter ranges, see [`ParamState::validate_against_defs`].
+fn validated(params: HashMap<ParamKind, Value>) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let mut state = ParamState::default();
+    state.current_params = params;
+    state.ensure_valid()?;
+    Ok(state.current_params)
+}
+
+fn value_from_toml(param: &ParamKind, value: &toml::Value) -> eyre::Result<Value> {
+    let value = match value {
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        _ => bail!("Invalid value {value} for parameter {param:?}"),
+    };
+    param
+        .parse_value(&value)
+        .with_context(|| format!("Invalid value {value} for parameter {param:?}"))
+}
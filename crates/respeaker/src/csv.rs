@@ -1,27 +1,431 @@
-use std::{collections::HashMap, fs::File, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use csv::Writer;
+use chrono::{DateTime, Local, TimeDelta};
+use clap::ValueEnum;
+use csv::{ReaderBuilder, StringRecord, Writer};
+use eyre::{bail, OptionExt};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hound::WavReader;
+use tracing::{debug, info};
 
 use crate::params::{ParamKind, Value};
 
+/// Information about a recording, stored as `# key=value` rows in front of the CSV header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordingMetadata {
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    pub recorded_at: String,
+    pub tool_version: String,
+    /// Local time of the first sample of the accompanying WAV file, see `audio-capture`.
+    pub audio_started_at: Option<String>,
+}
+
+impl RecordingMetadata {
+    fn rows(&self) -> Vec<(&'static str, &str)> {
+        let mut rows = vec![];
+        if let Some(serial) = &self.serial {
+            rows.push(("device_serial", serial.as_str()));
+        }
+        if let Some(firmware) = &self.firmware {
+            rows.push(("firmware_version", firmware.as_str()));
+        }
+        rows.push(("recorded_at", &self.recorded_at));
+        rows.push(("respeaker_rs_version", &self.tool_version));
+        if let Some(audio_started_at) = &self.audio_started_at {
+            rows.push(("audio_started_at", audio_started_at.as_str()));
+        }
+        rows
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "device_serial" => self.serial = Some(value.to_string()),
+            "firmware_version" => self.firmware = Some(value.to_string()),
+            "recorded_at" => value.clone_into(&mut self.recorded_at),
+            "respeaker_rs_version" => value.clone_into(&mut self.tool_version),
+            "audio_started_at" => self.audio_started_at = Some(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// Name of the column added by [`CsvWriterOptions::audio`].
+pub const AUDIO_SAMPLE_OFFSET_COLUMN: &str = "audio_sample_offset";
+
+/// The first recorded row and the start of the audio may be at most this far apart.
+const MAX_AUDIO_START_DIFFERENCE: Duration = Duration::from_millis(100);
+
+/// Timeline of a WAV file recorded at the same time as the parameters, see `record --interleave-audio`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioTimeline {
+    /// Local time of the first sample.
+    pub started_at: DateTime<Local>,
+    pub sample_rate: u32,
+    /// Length of the audio when it was read, the file may still grow.
+    pub duration: Duration,
+}
+
+impl AudioTimeline {
+    /// Reads the sample rate and duration from the WAV header. WAV files have no start time, it is taken
+    /// from the creation time of the file or, where the file system doesn't record one, the modification
+    /// time minus the duration.
+    pub fn from_wav(wav_path: &Path) -> eyre::Result<Self> {
+        let reader = WavReader::open(wav_path)?;
+        let spec = reader.spec();
+        let duration =
+            Duration::from_secs_f64(f64::from(reader.duration()) / f64::from(spec.sample_rate));
+        let file = fs::metadata(wav_path)?;
+        let started_at = if let Ok(created) = file.created() {
+            created.into()
+        } else {
+            debug!("{wav_path:?} has no creation time, using the modification time");
+            DateTime::<Local>::from(file.modified()?) - TimeDelta::from_std(duration)?
+        };
+        info!(
+            "Audio {wav_path:?}: {} Hz, {duration:.1?}, started at {}",
+            spec.sample_rate,
+            started_at.format("%+")
+        );
+        Ok(Self {
+            started_at,
+            sample_rate: spec.sample_rate,
+            duration,
+        })
+    }
+
+    /// Index of the audio sample (per channel) at `timestamp`, an ISO 8601 timestamp of the recording.
+    /// Negative before the start of the audio, `None` if `timestamp` is not a time, e.g. `RW_REFRESH`.
+    #[must_use]
+    pub fn sample_offset(&self, timestamp: &str) -> Option<i64> {
+        let time = DateTime::parse_from_rfc3339(timestamp).ok()?;
+        let since_start = time.signed_duration_since(self.started_at);
+        let micros = since_start.num_microseconds()?;
+        Some(micros * i64::from(self.sample_rate) / 1_000_000)
+    }
+
+    /// Fails if the first row of a recording at `timestamp` is more than 100 ms away from the start of
+    /// the audio, in which case the offsets would hardly be useful.
+    pub fn check_aligned(&self, timestamp: &str) -> eyre::Result<()> {
+        let time = DateTime::parse_from_rfc3339(timestamp)?;
+        let difference = time.signed_duration_since(self.started_at).abs().to_std()?;
+        if difference > MAX_AUDIO_START_DIFFERENCE {
+            bail!(
+                "The recording starts {difference:.1?} away from the audio (started at {}), start both \
+                 within {MAX_AUDIO_START_DIFFERENCE:?}",
+                self.started_at.format("%+")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Column order of the parameters in a recording. Fixed orders help tools which address columns by
+/// index, e.g. MATLAB or pandas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ParamColumnOrder {
+    /// RW before RO, ints before floats, see [`ParamKind::sorted`].
+    #[default]
+    #[value(name = "default")]
+    SortedDefault,
+    Alphabetical,
+    /// Grouped by category, alphabetical within a category.
+    #[value(name = "category")]
+    ByCategory,
+    /// By `(index, cmd)`, see [`ParamKind::sorted_by_firmware_id`].
+    #[value(name = "firmware-id")]
+    ByFirmwareId,
+}
+
+impl ParamColumnOrder {
+    #[must_use]
+    pub fn columns(self) -> Vec<ParamKind> {
+        match self {
+            Self::SortedDefault => ParamKind::sorted(),
+            Self::Alphabetical => ParamKind::sorted_by_name(),
+            Self::ByCategory => ParamKind::sorted_by_category(),
+            Self::ByFirmwareId => ParamKind::sorted_by_firmware_id(),
+        }
+    }
+}
+
+/// Writes recordings. A path of `-` writes to stdout instead of a file, paths ending with `.gz` are
+/// gzip compressed.
+///
+/// Files are buffered, call [`Self::finish`] to write the rest and see errors, dropping the writer
+/// ignores them.
 pub struct CsvWriter {
-    writer: Writer<File>,
+    writer: Output,
+    columns: Vec<ParamKind>,
+    float_precision: Option<usize>,
+    rotation: Option<Rotation>,
+    audio: Option<AudioTimeline>,
+}
+
+/// Where a [`CsvWriter`] writes to.
+enum Output {
+    Stdout(io::Stdout),
+    File(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    /// Between closing a file and opening the next one, see [`CsvWriter::rotate`].
+    Closed,
+}
+
+impl Output {
+    fn create(path: &Path, compress: bool) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(if compress {
+            Self::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Self::File(file)
+        })
+    }
+
+    /// Flushes the buffers and writes the gzip trailer.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Stdout(mut stdout) => stdout.flush(),
+            Self::File(mut file) => file.flush(),
+            Self::Gzip(encoder) => encoder.finish()?.flush(),
+            Self::Closed => Ok(()),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout(stdout) => stdout.write(buf),
+            Self::File(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Closed => Err(io::Error::other("The recording file is closed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout(stdout) => stdout.flush(),
+            Self::File(file) => file.flush(),
+            // Forces a sync flush, which makes the output larger
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Closed => Ok(()),
+        }
+    }
+}
+
+/// State of a [`CsvWriter`] which starts a new file once the current one reaches `max_size`.
+struct Rotation {
+    path: PathBuf,
+    metadata: Option<RecordingMetadata>,
+    max_size: u64,
+    /// Number of the current file, starting at 1.
+    part: u32,
+    /// Bytes written to the current file.
+    size: u64,
+}
+
+/// How [`CsvWriter::with_options`] opens the file and what it writes in front of the first row.
+#[derive(Debug, Clone, Default)]
+pub struct CsvWriterOptions {
+    /// Written as `# key=value` rows in front of the header.
+    pub metadata: Option<RecordingMetadata>,
+    /// Gzip compress the output regardless of the file extension.
+    pub compress: bool,
+    /// Append to an existing file, which is created if missing. Not supported for compressed files.
+    pub append: bool,
+    /// Skip the metadata and header rows, e.g. when appending to a file which already has them.
+    pub no_header: bool,
+    /// Start a new file once the current one has this many bytes, see [`CsvWriter::rotate`]. Not supported
+    /// for stdout, compressed and appended files.
+    pub max_file_size: Option<u64>,
+    /// Parameters left out of the header and the rows.
+    pub excluded: Vec<ParamKind>,
+    pub column_order: ParamColumnOrder,
+    /// Add an [`AUDIO_SAMPLE_OFFSET_COLUMN`] with the audio sample at `timestamp_before_read`.
+    pub audio: Option<AudioTimeline>,
 }
 
 impl CsvWriter {
-    pub fn new(file_path: &PathBuf) -> eyre::Result<Self> {
-        let params: Vec<ParamKind> = ParamKind::sorted();
-        let mut writer = Writer::from_writer(File::create(file_path)?);
+    pub fn new(file_path: &Path) -> eyre::Result<Self> {
+        Self::with_options(file_path, &CsvWriterOptions::default())
+    }
 
-        let mut headers = vec![
-            "timestamp_before_read".to_string(),
-            "timestamp_after_read".to_string(),
-        ];
-        headers.extend(params.iter().map(|p| format!("{p:?}")));
-        writer.write_record(&headers)?;
-        writer.flush()?;
+    pub fn new_with_order(file_path: &Path, order: ParamColumnOrder) -> eyre::Result<Self> {
+        Self::with_options(
+            file_path,
+            &CsvWriterOptions {
+                column_order: order,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_metadata_header(
+        file_path: &Path,
+        metadata: &RecordingMetadata,
+    ) -> eyre::Result<Self> {
+        Self::with_options(
+            file_path,
+            &CsvWriterOptions {
+                metadata: Some(metadata.clone()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Like [`Self::new`] but without the header row, for tools which provide their own.
+    pub fn new_no_header(file_path: &Path) -> eyre::Result<Self> {
+        Self::with_options(
+            file_path,
+            &CsvWriterOptions {
+                no_header: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Appends rows to an existing file, which is created if missing. The header is only written with
+    /// `header`, a second header in a file which already has one breaks [`CsvReader`].
+    pub fn appending(file_path: &Path, header: bool) -> eyre::Result<Self> {
+        Self::with_options(
+            file_path,
+            &CsvWriterOptions {
+                append: true,
+                no_header: !header,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Like [`Self::new`] but starts a new file whenever the current one reaches `max_file_size` bytes,
+    /// see [`Self::rotate`].
+    pub fn with_max_file_size(file_path: &Path, max_file_size: Option<u64>) -> eyre::Result<Self> {
+        Self::with_options(
+            file_path,
+            &CsvWriterOptions {
+                max_file_size,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_options(file_path: &Path, options: &CsvWriterOptions) -> eyre::Result<Self> {
+        if options.max_file_size.is_some()
+            && (file_path.as_os_str() == "-"
+                || options.append
+                || options.compress
+                || is_gzip(file_path))
+        {
+            bail!("A maximum file size is not supported for stdout, appended or compressed recordings");
+        }
+        let columns = options
+            .column_order
+            .columns()
+            .into_iter()
+            .filter(|p| !options.excluded.contains(p))
+            .collect::<Vec<_>>();
+        let columns = if options.append && file_path.as_os_str() != "-" {
+            appended_columns(file_path, columns, options.audio.is_some())?
+        } else {
+            columns
+        };
+        let mut file = if file_path.as_os_str() == "-" {
+            Output::Stdout(io::stdout())
+        } else if options.append {
+            if options.compress || is_gzip(file_path) {
+                bail!("Appending to compressed recordings is not supported");
+            }
+            Output::File(BufWriter::new(
+                OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(file_path)?,
+            ))
+        } else {
+            Output::create(file_path, options.compress || is_gzip(file_path))?
+        };
+
+        let size = if options.no_header {
+            0
+        } else {
+            let header = header_bytes(options.metadata.as_ref(), &columns, options.audio.as_ref())?;
+            file.write_all(&header)?;
+            header.len() as u64
+        };
+        if let Output::Stdout(stdout) = &mut file {
+            stdout.flush()?;
+        }
+
+        Ok(Self {
+            writer: file,
+            columns,
+            float_precision: None,
+            rotation: options.max_file_size.map(|max_size| Rotation {
+                path: file_path.to_path_buf(),
+                metadata: options.metadata.clone(),
+                max_size,
+                part: 1,
+                size,
+            }),
+            audio: options.audio.clone(),
+        })
+    }
 
-        Ok(Self { writer })
+    /// Number of the current file, 1 until the first [`Self::rotate`].
+    #[must_use]
+    pub fn part(&self) -> u32 {
+        self.rotation.as_ref().map_or(1, |r| r.part)
+    }
+
+    /// Closes the current file and continues in the next one, with the metadata and the header.
+    ///
+    /// `session.csv` is renamed to `session_001.csv` on the first rotation, the following files are
+    /// `session_002.csv`, `session_003.csv` and so on. Does nothing without a maximum file size.
+    pub fn rotate(&mut self) -> eyre::Result<()> {
+        let Some(rotation) = &mut self.rotation else {
+            return Ok(());
+        };
+        // Close the file before renaming it, Windows doesn't allow renaming open files
+        std::mem::replace(&mut self.writer, Output::Closed).finish()?;
+        if rotation.part == 1 {
+            fs::rename(&rotation.path, part_path(&rotation.path, "_001"))?;
+        }
+        rotation.part += 1;
+        let path = part_path(&rotation.path, &format!("_{:03}", rotation.part));
+        let header = header_bytes(
+            rotation.metadata.as_ref(),
+            &self.columns,
+            self.audio.as_ref(),
+        )?;
+        let mut file = Output::create(&path, false)?;
+        file.write_all(&header)?;
+        rotation.size = header.len() as u64;
+        self.writer = file;
+        info!("Continuing the recording in {}", path.display());
+        Ok(())
+    }
+
+    /// Writes `bytes` and rotates the file if it reached the maximum size.
+    fn write_counted(&mut self, bytes: &[u8]) -> eyre::Result<()> {
+        self.writer.write_all(bytes)?;
+        // Rows should show up immediately when piped. Files are only flushed when finished, every
+        // flush of a gzip stream makes it larger.
+        if let Output::Stdout(stdout) = &mut self.writer {
+            stdout.flush()?;
+        }
+        if let Some(rotation) = &mut self.rotation {
+            rotation.size += bytes.len() as u64;
+            if rotation.size >= rotation.max_size {
+                self.rotate()?;
+            }
+        }
+        Ok(())
     }
 
     pub fn write_row(
@@ -30,16 +434,296 @@ impl CsvWriter {
         timestamp_after: &str,
         values: &HashMap<ParamKind, Value>,
     ) -> eyre::Result<()> {
-        let params: Vec<ParamKind> = ParamKind::sorted();
-        let mut record = vec![timestamp_before.to_string(), timestamp_after.to_string()];
+        let row = row_bytes(
+            timestamp_before,
+            timestamp_after,
+            values,
+            &self.columns,
+            self.float_precision,
+            self.audio.as_ref(),
+        )?;
+        self.write_counted(&row)
+    }
 
-        record.extend(
-            params
-                .iter()
-                .map(|param| values.get(param).map_or_else(String::new, Value::to_string)),
-        );
+    /// Significant digits of floats in the following rows, see [`Value::to_csv_string_with_precision`].
+    /// Without a precision floats are written with [`Value::to_csv_string`].
+    pub const fn set_float_precision(&mut self, precision: usize) {
+        self.float_precision = Some(precision);
+    }
 
-        self.writer.write_record(&record)?;
+    /// Writes the buffered rows and, for compressed files, the gzip trailer.
+    pub fn finish(self) -> eyre::Result<()> {
+        self.writer.finish()?;
         Ok(())
     }
+
+    /// Writes `# <comment>` as its own line. [`CsvReader`] skips these lines and keeps them in
+    /// [`CsvReader::comments`], tools like pandas (`comment="#"`) can skip them as well. Line breaks in
+    /// `comment` are replaced with spaces.
+    pub fn write_comment(&mut self, comment: &str) -> eyre::Result<()> {
+        let line = format!("# {}\n", comment.replace(['\r', '\n'], " "));
+        self.write_counted(line.as_bytes())
+    }
+}
+
+/// `session.csv` with `suffix` `_002` is `session_002.csv`.
+pub(crate) fn part_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    let part_path = path.with_file_name(name);
+    match path.extension() {
+        Some(extension) => part_path.with_extension(extension),
+        None => part_path,
+    }
+}
+
+/// The optional metadata rows followed by the CSV header, as written by [`CsvWriter`].
+pub(crate) fn header_bytes(
+    metadata: Option<&RecordingMetadata>,
+    columns: &[ParamKind],
+    audio: Option<&AudioTimeline>,
+) -> eyre::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    if let Some(metadata) = metadata {
+        for (key, value) in metadata.rows() {
+            writeln!(bytes, "# {key}={value}")?;
+        }
+    }
+    let mut headers = vec![
+        "timestamp_before_read".to_string(),
+        "timestamp_after_read".to_string(),
+    ];
+    headers.extend(columns.iter().map(|p| format!("{p:?}")));
+    if audio.is_some() {
+        headers.push(AUDIO_SAMPLE_OFFSET_COLUMN.to_string());
+    }
+    let mut writer = Writer::from_writer(bytes);
+    writer.write_record(&headers)?;
+    Ok(writer.into_inner()?)
+}
+
+/// The parameter columns in the order of the header of an existing recording, so appended rows match it
+/// regardless of the column order. `columns` if the file is missing or has no header. Fails if the
+/// header has other columns.
+fn appended_columns(
+    file_path: &Path,
+    columns: Vec<ParamKind>,
+    audio: bool,
+) -> eyre::Result<Vec<ParamKind>> {
+    let header = match File::open(file_path) {
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .find(|line| !line.as_ref().is_ok_and(|line| line.starts_with('#')))
+            .transpose()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let Some(header) = header.filter(|header| header.starts_with("timestamp_before_read,")) else {
+        return Ok(columns);
+    };
+    let mut names = header.trim_end().split(',').skip(2).collect::<Vec<_>>();
+    let has_audio = names.last() == Some(&AUDIO_SAMPLE_OFFSET_COLUMN);
+    if has_audio {
+        names.pop();
+    }
+    let existing = names
+        .iter()
+        .map(|name| ParamKind::from_str(name, false).ok())
+        .collect::<Option<Vec<_>>>();
+    match existing {
+        Some(existing)
+            if has_audio == audio
+                && existing.len() == columns.len()
+                && existing.iter().all(|p| columns.contains(p)) =>
+        {
+            Ok(existing)
+        }
+        _ => bail!(
+            "{} has other columns than this recording, append with the same excluded parameters",
+            file_path.display()
+        ),
+    }
+}
+
+/// One CSV row, as written by [`CsvWriter::write_row`].
+pub(crate) fn row_bytes(
+    timestamp_before: &str,
+    timestamp_after: &str,
+    values: &HashMap<ParamKind, Value>,
+    columns: &[ParamKind],
+    float_precision: Option<usize>,
+    audio: Option<&AudioTimeline>,
+) -> eyre::Result<Vec<u8>> {
+    let mut record = vec![timestamp_before.to_string(), timestamp_after.to_string()];
+    record.extend(columns.iter().map(|param| {
+        values.get(param).map_or_else(String::new, |value| {
+            float_precision.map_or_else(
+                || value.to_csv_string(),
+                |precision| value.to_csv_string_with_precision(precision),
+            )
+        })
+    }));
+    if let Some(audio) = audio {
+        record.push(
+            audio
+                .sample_offset(timestamp_before)
+                .map(|offset| offset.to_string())
+                .unwrap_or_default(),
+        );
+    }
+    let mut writer = Writer::from_writer(vec![]);
+    writer.write_record(&record)?;
+    Ok(writer.into_inner()?)
+}
+
+/// One data row of a recording.
+#[derive(Debug, Clone)]
+pub struct CsvRow {
+    pub timestamp_before: String,
+    pub timestamp_after: String,
+    pub values: HashMap<ParamKind, Value>,
+}
+
+/// Reads recordings written by [`CsvWriter`]. Metadata rows starting with `#` are skipped and available
+/// via [`CsvReader::metadata`], comments after the header via [`CsvReader::comments`].
+///
+/// `.gz` files are decompressed. The file is read once, the comments are collected while reading the rows.
+pub struct CsvReader {
+    metadata: RecordingMetadata,
+    columns: Vec<Option<ParamKind>>,
+    reader: csv::Reader<CommentFilter>,
+}
+
+impl CsvReader {
+    pub fn new(file_path: &Path) -> eyre::Result<Self> {
+        let mut input = BufReader::new(open_input(file_path)?);
+        let mut metadata = RecordingMetadata::default();
+        let mut line = vec![];
+        let mut line_number = 0;
+        // Only the metadata block in front of the header, the header line stays in `line`
+        while input.read_until(b'\n', &mut line)? > 0 {
+            line_number += 1;
+            let Some(comment) = line.strip_prefix(b"#") else {
+                break;
+            };
+            if let Some((key, value)) = String::from_utf8_lossy(comment).trim().split_once('=') {
+                metadata.set(key, value);
+            }
+            line.clear();
+        }
+
+        let mut reader = ReaderBuilder::new().from_reader(CommentFilter {
+            input,
+            line,
+            pos: 0,
+            line_number,
+            comments: vec![],
+        });
+        let headers = reader.headers()?.clone();
+        if headers.get(0) != Some("timestamp_before_read")
+            || headers.get(1) != Some("timestamp_after_read")
+        {
+            bail!("{file_path:?} is not a ReSpeaker recording");
+        }
+        let columns = headers
+            .iter()
+            .skip(2)
+            .map(|name| ParamKind::from_str(name, false).ok())
+            .collect();
+
+        Ok(Self {
+            metadata,
+            columns,
+            reader,
+        })
+    }
+
+    #[must_use]
+    pub const fn metadata(&self) -> &RecordingMetadata {
+        &self.metadata
+    }
+
+    /// `(line number, text)` of the `#` lines after the header, see [`CsvWriter::write_comment`]. Line
+    /// numbers start at 1. Only complete once [`Self::rows`] has been read to the end.
+    #[must_use]
+    pub fn comments(&self) -> &[(usize, String)] {
+        &self.reader.get_ref().comments
+    }
+
+    /// Iterates over all data rows. Empty cells and unknown columns are left out of [`CsvRow::values`].
+    pub fn rows(&mut self) -> impl Iterator<Item = eyre::Result<CsvRow>> + '_ {
+        let columns = &self.columns;
+        self.reader
+            .records()
+            .map(move |record| parse_row(columns, &record?))
+    }
+}
+
+/// Passes the lines of a recording on to the CSV parser, except for the `#` lines, which are kept as
+/// comments.
+struct CommentFilter {
+    input: BufReader<Box<dyn Read>>,
+    /// The current line, of which `pos` bytes have been passed on.
+    line: Vec<u8>,
+    pos: usize,
+    line_number: usize,
+    comments: Vec<(usize, String)>,
+}
+
+impl Read for CommentFilter {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            if self.input.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+            self.line_number += 1;
+            if let Some(comment) = self.line.strip_prefix(b"#") {
+                let comment = String::from_utf8_lossy(comment).trim().to_string();
+                self.comments.push((self.line_number, comment));
+                self.line.clear();
+            }
+        }
+        let len = buf.len().min(self.line.len() - self.pos);
+        buf[..len].copy_from_slice(&self.line[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn is_gzip(file_path: &Path) -> bool {
+    file_path.extension().is_some_and(|ext| ext == "gz")
+}
+
+fn open_input(file_path: &Path) -> eyre::Result<Box<dyn Read>> {
+    let file = File::open(file_path)?;
+    Ok(if is_gzip(file_path) {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    })
+}
+
+fn parse_row(columns: &[Option<ParamKind>], record: &StringRecord) -> eyre::Result<CsvRow> {
+    let mut values = HashMap::new();
+    for (param, cell) in columns.iter().zip(record.iter().skip(2)) {
+        if let Some(param) = param {
+            if !cell.is_empty() {
+                values.insert(param.clone(), param.parse_value(cell)?);
+            }
+        }
+    }
+
+    Ok(CsvRow {
+        timestamp_before: record
+            .get(0)
+            .ok_or_eyre("Missing timestamp_before_read")?
+            .to_string(),
+        timestamp_after: record
+            .get(1)
+            .ok_or_eyre("Missing timestamp_after_read")?
+            .to_string(),
+        values,
+    })
 }
@@ -0,0 +1,409 @@
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use strum::IntoEnumIterator;
+
+use crate::{
+    build_string,
+    csv::CsvReader,
+    mat::{write_mat, MatArray},
+    params::{Access, DeviceModel, ParamKind, ParamType, Value},
+    parquet::{write_parquet, ParquetColumn},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportTarget {
+    /// MQTT entities for the Home Assistant `configuration.yaml`.
+    #[value(name = "homeassistant")]
+    HomeAssistant,
+    /// Python script (pyusb) which replays the RW parameter changes of a recording.
+    Python,
+    /// MATLAB/Octave `.mat` file converted from a CSV recording.
+    Matlab,
+    /// Parquet file converted from a CSV recording, for pandas, Spark or `DuckDB`.
+    Parquet,
+    /// CSV file converted from a binary recording.
+    #[cfg(feature = "bincode")]
+    Csv,
+}
+
+/// Generates the `mqtt:` block of a Home Assistant `configuration.yaml`.
+///
+/// RO parameters become sensors (VOICEACTIVITY and SPEECHDETECTED binary sensors), RW parameters become
+/// switches (ON/OFF parameters) or numbers. Values are expected on `<prefix>/<PARAM>`, commands are sent to
+/// `<prefix>/<PARAM>/set`.
+#[must_use]
+pub fn home_assistant_yaml(prefix: &str, device_name: &str) -> String {
+    build_string(|yaml| {
+        let prefix = prefix.trim_end_matches('/');
+        let mut sensors = String::new();
+        let mut binary_sensors = String::new();
+        let mut numbers = String::new();
+        let mut switches = String::new();
+
+        for param in ParamKind::iter() {
+            let def = param.def();
+            let name = format!("{param:?}");
+            let unique_id = format!(
+                "{}_{}",
+                device_name.to_lowercase().replace(' ', "_"),
+                name.to_lowercase()
+            );
+
+            let mut entity = String::new();
+            writeln!(entity, "    - name: \"{device_name} {name}\"")?;
+            writeln!(entity, "      unique_id: \"{unique_id}\"")?;
+            writeln!(entity, "      state_topic: \"{prefix}/{name}\"")?;
+
+            if def.access == Access::ReadOnly {
+                if matches!(param, ParamKind::VOICEACTIVITY | ParamKind::SPEECHDETECTED) {
+                    writeln!(entity, "      payload_on: \"1\"")?;
+                    writeln!(entity, "      payload_off: \"0\"")?;
+                    writeln!(entity, "      icon: \"{}\"", icon(&param))?;
+                    binary_sensors.push_str(&entity);
+                } else {
+                    if let Some(unit) = def.unit {
+                        writeln!(entity, "      unit_of_measurement: \"{unit}\"")?;
+                    }
+                    writeln!(entity, "      icon: \"{}\"", icon(&param))?;
+                    sensors.push_str(&entity);
+                }
+                continue;
+            }
+
+            writeln!(entity, "      command_topic: \"{prefix}/{name}/set\"")?;
+            match def.param_type {
+                ParamType::IntDiscete { min: 0, max: 1 } => {
+                    writeln!(entity, "      payload_on: \"1\"")?;
+                    writeln!(entity, "      payload_off: \"0\"")?;
+                    writeln!(entity, "      state_on: \"1\"")?;
+                    writeln!(entity, "      state_off: \"0\"")?;
+                    writeln!(entity, "      icon: \"mdi:toggle-switch\"")?;
+                    switches.push_str(&entity);
+                }
+                ParamType::IntDiscete { min, max } | ParamType::IntRange { min, max } => {
+                    writeln!(entity, "      min: {min}")?;
+                    writeln!(entity, "      max: {max}")?;
+                    writeln!(entity, "      step: 1")?;
+                    writeln!(entity, "      icon: \"mdi:tune\"")?;
+                    numbers.push_str(&entity);
+                }
+                ParamType::FloatRange { min, max } => {
+                    writeln!(entity, "      min: {min}")?;
+                    writeln!(entity, "      max: {max}")?;
+                    writeln!(entity, "      step: {}", (max - min) / 1000.0)?;
+                    if let Some(unit) = def.unit {
+                        writeln!(entity, "      unit_of_measurement: \"{unit}\"")?;
+                    }
+                    writeln!(entity, "      icon: \"mdi:tune\"")?;
+                    numbers.push_str(&entity);
+                }
+            }
+        }
+
+        write!(
+            yaml,
+            "mqtt:\n  sensor:\n{sensors}  binary_sensor:\n{binary_sensors}  number:\n{numbers}  switch:\n{switches}"
+        )
+    })
+}
+
+const fn icon(param: &ParamKind) -> &'static str {
+    match param {
+        ParamKind::DOAANGLE => "mdi:compass-outline",
+        ParamKind::RT60 => "mdi:timer-outline",
+        ParamKind::VOICEACTIVITY => "mdi:account-voice",
+        ParamKind::SPEECHDETECTED => "mdi:microphone-message",
+        ParamKind::AECSILENCEMODE => "mdi:volume-off",
+        _ => "mdi:information-outline",
+    }
+}
+
+/// Generates a Python script which replays the RW parameter writes of a recording with the same delays.
+///
+/// The first row writes all RW parameters, later rows only the changed ones. The script only needs
+/// `pyusb`, `--dry-run` prints the writes without opening the device.
+pub fn python_replay_script(recording: &mut CsvReader) -> eyre::Result<String> {
+    let metadata = recording.metadata().clone();
+    let mut writes = String::new();
+    let mut last_values: HashMap<ParamKind, Value> = HashMap::new();
+    let mut last_write_at: Option<DateTime<FixedOffset>> = None;
+    let mut count = 0;
+
+    for row in recording.rows() {
+        let row = row?;
+        // timestamp_before_read is RW_REFRESH in refresh rows, the after timestamp is always set
+        let timestamp = DateTime::parse_from_rfc3339(&row.timestamp_after)?;
+        let changed: Vec<_> = ParamKind::iter()
+            .filter(|p| p.def().access == Access::ReadWrite)
+            .filter_map(|p| {
+                let value = row.values.get(&p)?;
+                (last_values.get(&p) != Some(value)).then(|| (p, value.clone()))
+            })
+            .collect();
+        if changed.is_empty() {
+            continue;
+        }
+
+        if let Some(last_write_at) = last_write_at {
+            let delay = (timestamp - last_write_at).to_std().unwrap_or_default();
+            writeln!(writes, "    time.sleep({:.3})", delay.as_secs_f32())?;
+        }
+        last_write_at = Some(timestamp);
+        for (param, value) in changed {
+            let def = param.def();
+            writeln!(writes, "    # {param:?} = {value}")?;
+            writeln!(
+                writes,
+                "    write(dev, {}, {}, {}, {value}, {})",
+                python_string(&format!("{param:?}")),
+                def.index,
+                def.cmd,
+                if def.param_type.is_int() {
+                    "True"
+                } else {
+                    "False"
+                }
+            )?;
+            last_values.insert(param, value);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        writes.push_str("    pass\n");
+    }
+
+    let mut docstring = String::new();
+    writeln!(
+        docstring,
+        "Replays {count} parameter writes of a ReSpeaker recording, generated by respeaker-rs."
+    )?;
+    writeln!(docstring)?;
+    for (key, value) in [
+        ("Recorded at", Some(&metadata.recorded_at)),
+        ("Device serial", metadata.serial.as_ref()),
+        ("Firmware version", metadata.firmware.as_ref()),
+        ("respeaker-rs version", Some(&metadata.tool_version)),
+    ] {
+        writeln!(
+            docstring,
+            "{key}: {}",
+            value.map_or("unknown", String::as_str)
+        )?;
+    }
+
+    let product_ids = [DeviceModel::MicArrayV2, DeviceModel::MicLinear4]
+        .map(|m| format!("0x{:04X}", m.product_id()))
+        .join(", ");
+    Ok(include_str!("replay_template.py")
+        .replace("@DOCSTRING@", &python_string(&docstring))
+        .replace("@VENDOR_ID@", &format!("0x{:04X}", DeviceModel::VENDOR_ID))
+        .replace("@PRODUCT_IDS@", &product_ids)
+        .replace("@WRITES@", &writes))
+}
+
+/// `value` as a double-quoted Python string literal, so recording metadata can't end the string and
+/// inject code into the replay script.
+fn python_string(value: &str) -> String {
+    build_string(|literal| {
+        literal.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => literal.push_str("\\\""),
+                '\\' => literal.push_str("\\\\"),
+                '\n' => literal.push_str("\\n"),
+                '\r' => literal.push_str("\\r"),
+                '\t' => literal.push_str("\\t"),
+                c if c.is_control() => {
+                    write!(literal, "\\u{:04x}", u32::from(c))?;
+                }
+                c => literal.push(c),
+            }
+        }
+        literal.push('"');
+        Ok(())
+    })
+}
+
+/// Converts a CSV recording to a MATLAB/Octave `.mat` file and returns the number of rows.
+///
+/// The timestamps become `ts_before` and `ts_after` (Unix seconds as doubles, `ts_before` is NaN in RW
+/// refresh rows), every parameter column a variable of the same name (int32 or single). Missing values are
+/// -1 in int columns and NaN in float columns. `metadata` is a struct with the recording info.
+pub fn csv_to_mat(recording: &mut CsvReader, output: &Path) -> eyre::Result<usize> {
+    let metadata = recording.metadata().clone();
+    let mut ts_before = vec![];
+    let mut ts_after = vec![];
+    let mut rows = vec![];
+    for row in recording.rows() {
+        let row = row?;
+        ts_before.push(unix_seconds(&row.timestamp_before).unwrap_or(f64::NAN));
+        ts_after.push(unix_seconds(&row.timestamp_after)?);
+        rows.push(row.values);
+    }
+
+    let mut variables = vec![
+        ("ts_before".to_string(), MatArray::Double(ts_before)),
+        ("ts_after".to_string(), MatArray::Double(ts_after)),
+    ];
+    for param in ParamKind::iter().filter(|p| rows.iter().any(|r| r.contains_key(p))) {
+        let array = if param.def().param_type.is_int() {
+            MatArray::Int32(
+                rows.iter()
+                    .map(|r| match r.get(&param) {
+                        Some(Value::Int(i)) => i32::try_from(*i).unwrap_or(i32::MAX),
+                        _ => -1,
+                    })
+                    .collect(),
+            )
+        } else {
+            MatArray::Single(
+                rows.iter()
+                    .map(|r| match r.get(&param) {
+                        Some(Value::Float(f)) => *f,
+                        _ => f32::NAN,
+                    })
+                    .collect(),
+            )
+        };
+        variables.push((format!("{param:?}"), array));
+    }
+    let text = |value: Option<String>| MatArray::Char(value.unwrap_or_default());
+    variables.push((
+        "metadata".to_string(),
+        MatArray::Struct(vec![
+            ("recorded_at".to_string(), text(Some(metadata.recorded_at))),
+            ("device_serial".to_string(), text(metadata.serial)),
+            ("firmware_version".to_string(), text(metadata.firmware)),
+            (
+                "respeaker_rs_version".to_string(),
+                text(Some(metadata.tool_version)),
+            ),
+            (
+                "audio_started_at".to_string(),
+                text(metadata.audio_started_at),
+            ),
+        ]),
+    ));
+
+    write_mat(output, &variables)?;
+    Ok(rows.len())
+}
+
+/// Converts a CSV recording to a Parquet file and returns the number of rows.
+///
+/// `ts_before` and `ts_after` become UTC timestamps (Unix nanoseconds), INT parameters int32 and FLOAT
+/// parameters float32 columns. Like for [`csv_to_mat`] only parameters with values are included, missing
+/// values are null. INT values that don't fit into an int32 are an error. The recording metadata and
+/// the parameter descriptions (`description:<PARAM>`) are stored in the key-value metadata of the file.
+pub fn csv_to_parquet(recording: &mut CsvReader, output: &Path) -> eyre::Result<usize> {
+    let metadata = recording.metadata().clone();
+    let mut ts_before = vec![];
+    let mut ts_after = vec![];
+    let mut rows = vec![];
+    for row in recording.rows() {
+        let row = row?;
+        ts_before.push(unix_nanos(&row.timestamp_before).ok());
+        ts_after.push(Some(unix_nanos(&row.timestamp_after)?));
+        rows.push(row.values);
+    }
+
+    let mut columns = vec![
+        (
+            "ts_before".to_string(),
+            ParquetColumn::TimestampNanos(ts_before),
+        ),
+        (
+            "ts_after".to_string(),
+            ParquetColumn::TimestampNanos(ts_after),
+        ),
+    ];
+    let mut key_values = [
+        ("device_serial", metadata.serial),
+        ("firmware_version", metadata.firmware),
+        ("recorded_at", Some(metadata.recorded_at)),
+        ("respeaker_rs_version", Some(metadata.tool_version)),
+        ("audio_started_at", metadata.audio_started_at),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key.to_string(), value?)))
+    .collect::<Vec<_>>();
+    for param in ParamKind::iter().filter(|p| rows.iter().any(|r| r.contains_key(p))) {
+        let column = if param.def().param_type.is_int() {
+            ParquetColumn::Int32(
+                rows.iter()
+                    .map(|r| match r.get(&param) {
+                        Some(Value::Int(i)) => i32::try_from(*i).map(Some).map_err(|_| {
+                            eyre::eyre!("{param:?} value {i} does not fit into an int32 column")
+                        }),
+                        _ => Ok(None),
+                    })
+                    .collect::<eyre::Result<_>>()?,
+            )
+        } else {
+            ParquetColumn::Float(
+                rows.iter()
+                    .map(|r| match r.get(&param) {
+                        Some(Value::Float(f)) => Some(*f),
+                        _ => None,
+                    })
+                    .collect(),
+            )
+        };
+        columns.push((format!("{param:?}"), column));
+        key_values.push((
+            format!("description:{param:?}"),
+            param.def().description.trim().to_string(),
+        ));
+    }
+
+    write_parquet(output, &columns, &key_values)?;
+    Ok(rows.len())
+}
+
+fn unix_nanos(timestamp: &str) -> eyre::Result<i64> {
+    DateTime::parse_from_rfc3339(timestamp)?
+        .timestamp_nanos_opt()
+        .ok_or_else(|| eyre::eyre!("Timestamp {timestamp} is out of range"))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn unix_seconds(timestamp: &str) -> eyre::Result<f64> {
+    let timestamp = DateTime::parse_from_rfc3339(timestamp)?;
+    Ok(timestamp.timestamp() as f64 + f64::from(timestamp.timestamp_subsec_nanos()) / 1e9)
+}
+
+/// Converts a binary recording to CSV. Both timestamp columns get the row's timestamp in local time.
+#[cfg(feature = "bincode")]
+pub fn binary_to_csv(recording: &std::path::Path, csv_path: &std::path::Path) -> eyre::Result<u64> {
+    let mut writer = crate::csv::CsvWriter::new(csv_path)?;
+    let mut rows = 0;
+    for row in crate::binary::BinaryReader::new(recording)? {
+        let row = row?;
+        let timestamp = DateTime::from_timestamp_nanos(i64::try_from(row.timestamp_unix_ns)?)
+            .with_timezone(&chrono::Local)
+            .format("%+")
+            .to_string();
+        writer.write_row(&timestamp, &timestamp, &row.values)?;
+        rows += 1;
+    }
+    writer.finish()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::python_string;
+
+    #[rstest]
+    #[case("MOCK", r#""MOCK""#)]
+    #[case("a\"b\nc", r#""a\"b\nc""#)]
+    #[case(r#"""" + x + """"#, r#""\"\"\" + x + \"\"\"""#)]
+    #[case("back\\slash\u{7}", r#""back\\slash\u0007""#)]
+    fn python_string_literal(#[case] value: &str, #[case] expected: &str) {
+        assert_eq!(python_string(value), expected);
+    }
+}
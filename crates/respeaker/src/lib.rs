@@ -0,0 +1,53 @@
+//! Unofficial library for the `ReSpeaker` Mic Array v2.0.
+//!
+//! Parameters are written with [`respeaker_device::ReSpeakerDevice::write`], which rejects read-only
+//! parameters at runtime. Library code can get the same check from the type system instead:
+//!
+//! ```no_run
+//! # use std::sync::{Arc, Mutex};
+//! # use respeaker::params::{ParamKind, ParamState, Value};
+//! # use respeaker::respeaker_device::ReSpeakerDevice;
+//! # fn main() -> eyre::Result<()> {
+//! let device = ReSpeakerDevice::open(None, Arc::new(Mutex::new(ParamState::default())))?;
+//! // `None` for read-only parameters like DOAANGLE
+//! if let Some(agc) = ParamKind::AGCONOFF.as_writeable() {
+//!     device.write_checked(&agc, &Value::Int(1))?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod alert;
+pub mod analysis;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod benchmark;
+#[cfg(feature = "bincode")]
+pub mod binary;
+pub mod config;
+pub mod csv;
+pub mod export;
+pub mod mat;
+pub mod mock;
+pub mod monitor;
+#[cfg(feature = "serde")]
+pub mod ndjson;
+#[cfg(feature = "debug")]
+pub mod packet_dump;
+pub mod params;
+pub mod parquet;
+pub mod pool;
+pub mod recorder;
+pub mod respeaker_device;
+#[cfg(feature = "tokio")]
+pub mod session;
+pub mod tune;
+pub mod ui;
+
+/// Builds a `String` with `write!`/`writeln!` and `?`, the only place which has to handle the
+/// `fmt::Result` of writing to a `String`.
+pub(crate) fn build_string(build: impl FnOnce(&mut String) -> std::fmt::Result) -> String {
+    let mut text = String::new();
+    build(&mut text).expect("Writing to a String can't fail");
+    text
+}
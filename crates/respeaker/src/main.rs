@@ -1,5 +1,10 @@
-use std::collections::HashMap;
 use std::fmt::Write;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+#[cfg(feature = "tokio")]
+use std::net::IpAddr;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
@@ -7,27 +12,61 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
-use clap::{command, Parser, Subcommand};
+use chrono::{DateTime, Local, Utc};
+use clap::{command, ArgAction, Args, Parser, Subcommand, ValueEnum};
 use eyre::eyre;
 use eyre::Ok;
 use eyre::Result;
-use params::ParamKind;
-use params::ParamState;
-use recorder::record_respeaker_parameters;
-use respeaker_device::ReSpeakerDevice;
+use respeaker::analysis::{extract_segments, segments_table, write_segments_csv};
+use respeaker::benchmark::{run_benchmark, BenchmarkProfile};
+use respeaker::config::{
+    config_dir, config_file, config_to_toml, default_config_toml, diff_snapshots, load_snapshot,
+    presets_dir, save_snapshot, CliSettings,
+};
+use respeaker::csv::{AudioTimeline, CsvReader, ParamColumnOrder};
+#[cfg(feature = "bincode")]
+use respeaker::export::binary_to_csv;
+use respeaker::export::{
+    csv_to_mat, csv_to_parquet, home_assistant_yaml, python_replay_script, ExportTarget,
+};
+#[cfg(debug_assertions)]
+use respeaker::mock::MockDevice;
+use respeaker::monitor::{run_compare, run_list_watch, run_listen, run_monitor, run_watch};
+#[cfg(feature = "debug")]
+use respeaker::packet_dump::packet_dump;
+use respeaker::params::Access;
+use respeaker::params::AuditEntry;
+use respeaker::params::ParamKind;
+use respeaker::params::ParamSortOrder;
+use respeaker::params::ParamState;
+use respeaker::params::Value;
+#[cfg(feature = "audio")]
+use respeaker::recorder::record_with_audio;
+use respeaker::recorder::{
+    record_respeaker_parameters, record_speech_segments, OnError, OutputDirFormat, RecordFormat,
+    RecordingOptions, TriggerCondition, TriggerDirection,
+};
+use respeaker::respeaker_device::{list_devices, ExportFormat, ReSpeakerDevice};
+#[cfg(feature = "tokio")]
+use respeaker::session::{run_session, session_summary, SessionOptions};
+use respeaker::tune::{run_tune, Scenario};
+use respeaker::ui::run_ui;
 
-use tracing::info;
+use tabled::Table;
 use tracing::Level;
-use ui::run_ui;
+use tracing::{debug, info, warn};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
 
-mod csv;
-mod params;
-mod recorder;
-mod respeaker_device;
-mod ui;
+const SNAPSHOT_DIR: &str = "./snapshots";
 
 /// Unofficial CLI & UI for the Re-Speaker Mic Array v2.0
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
 struct Arguments {
@@ -36,34 +75,465 @@ struct Arguments {
 
     #[clap(short = 'i')]
     device_index: Option<usize>,
+
+    /// List all connected devices with their `-i` index and exit. Does not open any device.
+    #[clap(long)]
+    list_devices: bool,
+
+    /// If the device is busy (e.g. opened by PulseAudio/PipeWire), retry every second instead of failing.
+    #[clap(long)]
+    wait_for_device: bool,
+
+    /// Give up waiting for a busy device (--wait-for-device) after this many seconds.
+    #[clap(long, default_value_t = 60, requires = "wait_for_device")]
+    wait_timeout_secs: u64,
+
+    /// Before running the command, check that the firmware responds with values in the expected ranges.
+    #[clap(long)]
+    check_firmware: bool,
+
+    /// More log output (-v = debug, -vv = trace). `RUST_LOG` overrides this. At trace level the duration
+    /// of every device operation is logged, e.g. `RUST_LOG=respeaker=trace respeaker list`.
+    #[clap(short = 'v', long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Timeout for USB control transfers in milliseconds.
+    #[clap(long, default_value_t = 2000, global = true)]
+    timeout_ms: u64,
+
+    /// Only log warnings and errors.
+    #[clap(short = 'q', long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    #[clap(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+
+    /// Append logs to this file instead of writing them to stderr.
+    #[clap(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Append every parameter write of this run to this file, with the old and the new value.
+    #[clap(long, global = true)]
+    audit_log_file: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    Text,
+    /// One JSON object per line, for log collectors like Loki or Logstash.
+    Json,
+}
+
+impl Arguments {
+    const fn log_level(&self) -> Level {
+        // identify, status and env-export output is meant to be captured by scripts
+        if matches!(
+            self.command,
+            Some(Command::Identify | Command::Status { .. } | Command::EnvExport { .. })
+        ) {
+            return Level::ERROR;
+        }
+        match (self.quiet, self.verbose) {
+            (true, _) => Level::WARN,
+            (false, 0) => Level::INFO,
+            (false, 1) => Level::DEBUG,
+            (false, _) => Level::TRACE,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
 #[clap(flatten_help = true)]
 enum Command {
     /// List all available parameters and their current values (RW and RO).
-    List,
-    /// Read the value of specific parameters.
+    List {
+        /// Only list read-only (ro) or read-write (rw) parameters. Lists all parameters if omitted.
+        #[clap(long, value_enum)]
+        filter_access: Option<Access>,
+        /// Order of the parameters. Declaration order if omitted.
+        #[clap(long, value_enum)]
+        sort: Option<ParamSortOrder>,
+        /// Output format. JSON and CSV have one object / row per parameter, TOML is a snapshot, Markdown a
+        /// table for documentation and compact one line per parameter for narrow terminals.
+        #[clap(long, value_enum, default_value_t = ExportFormat::Table, conflicts_with = "watch")]
+        format: ExportFormat,
+        /// Keep refreshing the table in place until Ctrl-C is pressed. Changed rows are bold.
+        #[clap(long)]
+        watch: bool,
+        /// Refresh interval of --watch in milliseconds.
+        #[clap(long, default_value_t = 1000, requires = "watch")]
+        interval_ms: u64,
+    },
+    /// Read the value of specific parameters. Without parameters, reads DOAANGLE, VOICEACTIVITY,
+    /// SPEECHDETECTED, AGCGAIN and RT60.
     Read {
-        #[clap(short = 'c', default_value_t = true)]
+        /// Keep reading until Ctrl-C is pressed, the default. `--continuous=false` reads once.
+        #[clap(
+            short = 'c',
+            long,
+            default_value_t = true,
+            num_args = 0..=1,
+            default_missing_value = "true",
+            require_equals = true,
+            action = ArgAction::Set
+        )]
         continuous: bool,
+        /// Maximum number of read rounds per second in continuous mode.
+        #[clap(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+        rate_limit_hz: u32,
         params: Vec<ParamKind>,
     },
     /// Write the value of a specific parameter.
-    Write { param: ParamKind, value: String },
+    Write(WriteArgs),
+    /// Revert the firmware to the factory image. Irreversible without re-flashing, asks for confirmation.
+    RevertFactory,
     /// Perform a device reset.
-    Reset,
+    Reset {
+        /// Poll until the device is back instead of waiting a fixed 2 s.
+        #[clap(long)]
+        wait_ready: bool,
+        /// How long --wait-ready waits for the device at most.
+        #[clap(long, default_value_t = 10, requires = "wait_ready")]
+        timeout_secs: u64,
+        /// Reset even if the DFU state machine is not idle.
+        #[clap(long)]
+        force: bool,
+    },
     /// Continously record parameters to CSV file during the provided amount of seconds.
-    /// The RW parameters are only read once at the start.
-    Record {
-        #[clap(short = 's')]
+    /// The RW parameters are only read once at the start, see --rw-refresh-interval-secs.
+    Record(RecordArgs),
+    /// Print parameters whenever their value changes until Ctrl-C is pressed.
+    Watch {
+        /// Parameters to watch. DOAANGLE, VOICEACTIVITY, SPEECHDETECTED, AGCGAIN and RT60 if omitted.
+        params: Vec<ParamKind>,
+        /// Poll interval in milliseconds.
+        #[clap(long, default_value_t = 100)]
+        interval_ms: u64,
+        /// Show a desktop notification (notify-send on Linux, osascript on macOS) when the value
+        /// changes. Rings the terminal bell if notifications are not available.
+        #[clap(long)]
+        alert_on_change: bool,
+        /// Only alert if a float parameter changed by more than this since the last alert.
+        #[clap(long, requires = "alert_on_change")]
+        alert_threshold: Option<f32>,
+    },
+    /// Print a line like `[12:34:56.789] VOICEACTIVITY: 0 -> 1` whenever a parameter changes, e.g.
+    /// `respeaker listen VOICEACTIVITY SPEECHDETECTED | while read line; do ...; done`.
+    Listen {
+        /// Parameters to listen to. All RO parameters of the device if omitted.
+        params: Vec<ParamKind>,
+        /// Poll interval in milliseconds.
+        #[clap(long, default_value_t = 100)]
+        poll_ms: u64,
+        /// Ignore float changes up to this size, e.g. noise in RT60.
+        #[clap(long, default_value_t = 0.01)]
+        float_threshold: f32,
+        /// Also print changes of the DOA sector (Front, Front left, Left, ...) as `DOASECTOR` lines.
+        #[clap(long)]
+        sector: bool,
+    },
+    /// Record to CSV and serve Prometheus metrics at the same time until Ctrl-C is pressed or --seconds
+    /// have passed, then print a summary.
+    #[cfg(feature = "tokio")]
+    Session(SessionArgs),
+    /// Live dashboard of DOA, voice activity, speech detection and RT60.
+    Monitor {
+        /// Refresh interval in milliseconds.
+        #[clap(long, default_value_t = 50)]
+        interval_ms: u64,
+    },
+    /// Read the same parameters from two devices and show them side by side with their difference.
+    /// Ignores `-i`.
+    Compare {
+        /// Index of the first device, see `--list-devices`.
+        #[clap(long)]
+        index_a: usize,
+        /// Index of the second device.
+        #[clap(long)]
+        index_b: usize,
+        /// Comma-separated parameters to compare.
+        #[clap(long, value_delimiter = ',', default_value = "DOAANGLE,VOICEACTIVITY")]
+        params: Vec<ParamKind>,
+        /// Refresh interval in milliseconds.
+        #[clap(long, default_value_t = 200)]
+        poll_ms: u64,
+    },
+    /// Print the writes of the current session. Every run is its own session, so this always fails,
+    /// use `--audit-log-file` instead. Does not need a device.
+    AuditLog,
+    /// Print a one-line summary of the device, e.g. for bug reports.
+    Identify,
+    /// Read a parameter back-to-back and report the latency, e.g. to choose a poll interval.
+    Benchmark(BenchmarkArgs),
+    /// Run read/write cycles on harmless parameters and count failures, e.g. to qualify a new USB host
+    /// or hub. Fails if the error rate is above --max-error-rate-percent.
+    StressTest(StressTestArgs),
+    /// Print DOA, voice activity, speech detection, RT60 and AGC gain on one line, e.g. for a tmux
+    /// status bar.
+    Status {
+        #[clap(long, value_enum, default_value_t = StatusFormat::Human)]
+        format: StatusFormat,
+    },
+    /// Check that the device is reachable and all parameters can be read.
+    Doctor,
+    /// Create `<config dir>/respeaker` with a default `config.toml` and a `presets` directory, and save
+    /// the current RW parameters of a connected device as `presets/current.toml`. Existing files are
+    /// never overwritten.
+    Init,
+    /// Step-by-step wizard which explains and writes the recommended settings for a use case.
+    Tune {
+        #[clap(long, value_enum, default_value_t = Scenario::VoiceAssistant)]
+        scenario: Scenario,
+    },
+    /// Print shell commands which export the RW parameters as `RESPEAKER_<PARAM>` environment variables,
+    /// e.g. `eval "$(respeaker env-export)"`.
+    EnvExport {
+        #[clap(long, value_enum, default_value_t = Shell::Bash)]
+        shell: Shell,
+    },
+    /// Save all parameters (RW and RO) to `./snapshots/<timestamp>.toml` and print the path.
+    #[clap(args_conflicts_with_subcommands = true)]
+    Snapshot {
+        #[command(subcommand)]
+        action: Option<SnapshotAction>,
+        /// Save to this file instead.
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate integration configuration for other tools or a replay script for a recording.
+    /// Does not need a device.
+    Export {
+        #[clap(long, value_enum)]
+        format: ExportTarget,
+        /// Recording to replay (`--format python`, CSV) or convert (`--format matlab` and
+        /// `--format parquet`, CSV, or `--format csv`, binary).
+        recording: Option<PathBuf>,
+        /// Output file for `--format csv`, `--format matlab` and `--format parquet`.
+        output: Option<PathBuf>,
+        /// MQTT topic prefix.
+        #[clap(long, default_value = "respeaker")]
+        prefix: String,
+        #[clap(long, default_value = "ReSpeaker")]
+        device_name: String,
+    },
+    /// Print everything known about a parameter: type, range, unit, default, valid values, related
+    /// parameters and the firmware version which introduced it. Does not need a device.
+    ParamInfo { param: ParamKind },
+    /// Print the speech segments (VOICEACTIVITY=1 spans) of a CSV recording with their mean DOAANGLE and
+    /// RT60. Does not need a device.
+    Segment {
+        recording: PathBuf,
+        /// Write the segments to this CSV file (e.g. `segments.csv`) instead of printing a table.
+        #[clap(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
+    /// Print all USB control transfers to the device (captured with Linux usbmon, needs root).
+    #[cfg(feature = "debug")]
+    PacketDump,
+    /// Record parameters to `<output>.csv` and the microphone audio to `<output>.wav` at the same time.
+    #[cfg(feature = "audio")]
+    AudioCapture {
+        #[clap(short = 's', long)]
         seconds: Option<f32>,
-        csv_path: Option<PathBuf>,
+        #[clap(short = 'o', long)]
+        output: PathBuf,
     },
 }
 
+#[cfg(feature = "tokio")]
+#[derive(Args, Debug)]
+struct SessionArgs {
+    #[clap(long)]
+    record_path: PathBuf,
+    /// Serve the current values as Prometheus metrics on `http://<bind>:<port>/metrics`.
+    #[clap(long)]
+    serve_port: Option<u16>,
+    /// Address of the metrics server. Use `0.0.0.0` to expose it on every interface.
+    #[clap(long, default_value = "127.0.0.1", requires = "serve_port")]
+    bind: IpAddr,
+    #[clap(short = 's', long)]
+    seconds: Option<f32>,
+}
+
+#[derive(Args, Debug)]
+struct WriteArgs {
+    #[clap(required_unless_present = "from_stdin")]
+    param: Option<ParamKind>,
+    #[clap(required_unless_present = "from_stdin")]
+    value: Option<String>,
+    /// Show the current value and ask for confirmation before writing.
+    #[clap(long)]
+    interactive: bool,
+    /// Read `PARAM=value` lines from stdin instead. Empty lines and lines starting with `#` are
+    /// skipped. All lines are checked before the first value is written.
+    #[clap(long, conflicts_with_all = ["param", "value", "interactive"])]
+    from_stdin: bool,
+    /// Wait until this many milliseconds have passed since the previous write, also between the
+    /// lines of --from-stdin and across runs (the last write time is kept in
+    /// `<runtime dir>/respeaker/last_write`). Some firmware versions become unstable under rapid
+    /// writes.
+    #[clap(long, default_value_t = 0)]
+    throttle_write_ms: u64,
+    /// Append `[<time>] PARAM: old -> new` to this file for every write (`--log-change=PATH`). Without
+    /// a path, `change_log` in the `[cli]` table of `<config dir>/respeaker/config.toml` is used.
+    #[clap(long, value_name = "PATH", require_equals = true)]
+    #[allow(clippy::option_option)] // Flag with an optional value
+    log_change: Option<Option<PathBuf>>,
+}
+
+#[derive(Args, Debug)]
+struct StressTestArgs {
+    #[clap(
+        short = 'n',
+        long,
+        default_value_t = 1000,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    iterations: usize,
+    /// Failed and mismatching cycles in percent which are still acceptable, 0 to 100.
+    #[clap(long, default_value_t = 1.0, value_parser = parse_percent)]
+    max_error_rate_percent: f64,
+}
+
+#[derive(Args, Debug)]
+struct BenchmarkArgs {
+    /// `usb` times the raw control transfers and prints a histogram of their latency.
+    #[clap(long, value_enum, default_value_t = BenchmarkProfile::Latency)]
+    profile: BenchmarkProfile,
+    /// Number of reads.
+    #[clap(short = 'n', long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+    /// Parameter to read.
+    #[clap(long, default_value = "DOAANGLE")]
+    param: ParamKind,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug)]
+struct RecordArgs {
+    #[clap(short = 's')]
+    seconds: Option<f32>,
+    /// Output file, `-` for stdout. Defaults to `./recordings/<timestamp>.csv` (or `.jsonl`).
+    #[clap(conflicts_with = "split_on_speech")]
+    csv_path: Option<PathBuf>,
+    /// File format of the recording.
+    #[clap(long, value_enum, default_value_t = RecordFormat::Csv, conflicts_with = "split_on_speech")]
+    output: RecordFormat,
+    /// Write one CSV file per speech segment (VOICEACTIVITY=1) into this directory.
+    #[clap(long)]
+    split_on_speech: Option<PathBuf>,
+    /// How long VOICEACTIVITY has to stay 0 before a speech segment is closed.
+    #[clap(long, default_value_t = 500)]
+    silence_grace_ms: u64,
+    /// What to do if reading a parameter fails.
+    #[clap(long, value_enum, default_value_t = OnError::Fail, conflicts_with = "split_on_speech")]
+    on_error: OnError,
+    /// Delay before retrying a failed read with --on-error retry.
+    #[clap(long, default_value_t = 100)]
+    retry_delay_ms: u64,
+    /// Gzip compress the CSV file and add `.gz` to its name. Implied by a `.csv.gz` path.
+    #[clap(long, conflicts_with = "split_on_speech")]
+    compress: bool,
+    /// Re-read the RW parameters every N seconds and write them as a row with
+    /// `timestamp_before_read=RW_REFRESH`.
+    #[clap(long, value_parser = parse_positive_secs, conflicts_with = "split_on_speech")]
+    rw_refresh_interval_secs: Option<Duration>,
+    /// Only start recording once this parameter crosses --trigger-threshold.
+    #[clap(
+        long,
+        requires = "trigger_threshold",
+        conflicts_with = "split_on_speech"
+    )]
+    trigger_param: Option<ParamKind>,
+    #[clap(long, requires = "trigger_param")]
+    trigger_threshold: Option<String>,
+    /// Trigger when the parameter is above the threshold (default).
+    #[clap(long, requires = "trigger_param")]
+    trigger_above: bool,
+    /// Trigger when the parameter is below the threshold.
+    #[clap(long, requires = "trigger_param", conflicts_with = "trigger_above")]
+    trigger_below: bool,
+    /// Append to an existing CSV file instead of replacing it.
+    #[clap(long, conflicts_with_all = ["split_on_speech", "compress"])]
+    append: bool,
+    /// Don't write the CSV header row. Use with --append if the file already has one.
+    #[clap(long, conflicts_with = "split_on_speech")]
+    no_header: bool,
+    /// Comma-separated parameters to leave out of the recording.
+    #[clap(long, value_delimiter = ',', conflicts_with = "split_on_speech")]
+    exclude: Vec<ParamKind>,
+    /// Order of the parameter columns in the CSV file.
+    #[clap(long, value_enum, default_value_t = ParamColumnOrder::SortedDefault, conflicts_with = "split_on_speech")]
+    column_order: ParamColumnOrder,
+    /// Start recording once this file exists and stop once it is deleted. The file may contain
+    /// `seconds=<N>` on its first line to limit the duration.
+    #[clap(long, conflicts_with_all = ["split_on_speech", "trigger_param"])]
+    trigger_file: Option<PathBuf>,
+    /// Continue in a new file once the CSV file has this many MiB. `session.csv` becomes
+    /// `session_001.csv`, followed by `session_002.csv` and so on.
+    #[clap(
+        long,
+        conflicts_with_all = ["split_on_speech", "append", "compress"],
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_file_size_mb: Option<u64>,
+    /// Add an `audio_sample_offset` column with the sample of this WAV file, which is recorded at the
+    /// same time, at `timestamp_before_read`. The recording has to start within 100 ms of the audio.
+    #[clap(long, conflicts_with_all = ["split_on_speech", "append"])]
+    interleave_audio: Option<PathBuf>,
+    /// Directory for the recording instead of `./recordings`, created if missing.
+    #[clap(long, conflicts_with_all = ["csv_path", "split_on_speech"])]
+    output_dir: Option<PathBuf>,
+    /// `per-category` writes one CSV file per parameter category into --output-dir, e.g. `aec.csv` and
+    /// `agc.csv`, all with the same timestamps.
+    #[clap(long, value_enum, default_value_t = OutputDirFormat::Single, requires = "output_dir")]
+    output_dir_format: OutputDirFormat,
+}
+
+/// A positive and finite number of seconds, e.g. `0.5`.
+fn parse_positive_secs(arg: &str) -> std::result::Result<Duration, String> {
+    let secs = arg.parse::<f32>().map_err(|e| e.to_string())?;
+    if secs <= 0.0 {
+        return Err("must be positive".to_string());
+    }
+    Duration::try_from_secs_f32(secs).map_err(|e| e.to_string())
+}
+
+/// A percentage from 0 to 100, e.g. `1.5`.
+fn parse_percent(arg: &str) -> std::result::Result<f64, String> {
+    let percent = arg.parse::<f64>().map_err(|e| e.to_string())?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err("must be between 0 and 100".to_string());
+    }
+    std::result::Result::Ok(percent)
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Shell {
+    /// `export RESPEAKER_AGCMAXGAIN=31.6`, also for zsh and other POSIX shells.
+    Bash,
+    /// `set -gx RESPEAKER_AGCMAXGAIN 31.6`
+    Fish,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StatusFormat {
+    /// `DOA=42° | VAD=ON | Speech=OFF | RT60=0.45s | AGC=+6dB`
+    Human,
+    /// `42 1 0 0.45 2.0`, raw values separated by spaces for awk. `-` if the device lacks a parameter.
+    Machine,
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotAction {
+    /// List the files in `./snapshots`. Does not need a device.
+    List,
+    /// Show the parameters which differ between two snapshots. Does not need a device.
+    Diff { a: PathBuf, b: PathBuf },
+}
+
 fn main() -> eyre::Result<()> {
-    let args: Arguments = init()?;
+    let args = init()?;
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -74,64 +544,1100 @@ fn main() -> eyre::Result<()> {
 
     info!("Running unofficial ReSpeaker CLI with {args:?}");
 
-    let shared_state = Arc::new(Mutex::new(ParamState {
-        current_params: HashMap::new(),
-    }));
+    let shared_state = Arc::new(Mutex::new(ParamState::default()));
 
-    let mut device = ReSpeakerDevice::open(args.device_index, shared_state)?;
+    let open_device_at = |device_index: Option<usize>, state: Arc<Mutex<ParamState>>| {
+        let device = if let Some(device) = open_mock_from_env(&state)? {
+            device
+        } else if args.wait_for_device {
+            ReSpeakerDevice::wait_for_device_and_control(
+                device_index,
+                &state,
+                &running,
+                Duration::from_secs(args.wait_timeout_secs),
+            )?
+        } else {
+            ReSpeakerDevice::open(device_index, state)?
+        };
+        device.set_timeout(Duration::from_millis(args.timeout_ms));
+        Ok(device)
+    };
+    let open_device = || open_device_at(args.device_index, shared_state.clone());
+
+    if args.list_devices {
+        let devices = list_devices()?;
+        if devices.is_empty() {
+            println!("No devices found");
+        } else {
+            println!("{}", Table::new(devices));
+        }
+        return Ok(());
+    }
 
     if let Some(command) = args.command {
-        match command {
-            Command::List => {
-                let list = device.list()?;
-                info!("Parameters:\n{list}");
-            }
-            Command::Read { params, continuous } => loop {
-                let values = params
-                    .iter()
-                    .map(|param| {
-                        let value = device.read(param)?;
-                        Ok((param, value))
-                    })
-                    .collect::<Result<Vec<_>>>()?;
-
-                let mut result = String::new();
-                for (param, value) in values {
-                    write!(&mut result, "\n{param:?}={value}")?;
-                }
-                info!("{result}");
-                if !continuous {
-                    break;
-                }
-                thread::sleep(Duration::from_millis(1));
-            },
-            Command::Write { param, value } => {
-                let value = param.parse_value(&value)?;
-                device.write(&param, &value)?;
-            }
-            Command::Reset => device.reset()?,
-            Command::Record { seconds, csv_path } => {
-                device.list()?; // cache rw params
-                record_respeaker_parameters(seconds, csv_path, &device, &running)?;
+        if let Some(result) = run_without_device(&command) {
+            return result;
+        }
+        if matches!(command, Command::Init) {
+            return init_config_dir(open_device);
+        }
+
+        if let Command::Compare {
+            index_a,
+            index_b,
+            params,
+            poll_ms,
+        } = &command
+        {
+            // Separate states, otherwise the devices would overwrite each other's cached values
+            let device_a = open_device_at(Some(*index_a), Arc::default())?;
+            let device_b = open_device_at(Some(*index_b), Arc::default())?;
+            if args.check_firmware {
+                check_firmware(&device_a)?;
+                check_firmware(&device_b)?;
             }
+            run_compare(
+                &device_a,
+                &device_b,
+                params,
+                Duration::from_millis(*poll_ms),
+                &running,
+            )?;
+            return Ok(());
+        }
+
+        let device = open_device()?;
+        if args.check_firmware {
+            check_firmware(&device)?;
+        }
+        if args.audit_log_file.is_some() && matches!(command, Command::Write(_)) {
+            // Cache the old values, the audit log only knows them from the cache
+            device.read_rw()?;
+        }
+        let result = run_command(command, &device, &running, args.quiet);
+        // Also keep the writes which happened before a failure
+        if let Some(path) = &args.audit_log_file {
+            write_audit_log(path, &shared_state.lock().expect("Lock failed"))?;
         }
+        result?;
     } else {
         info!("Opening UI...");
-        run_ui(device).map_err(|e| eyre!("UI error: {}", e))?;
+        run_ui(open_device()?).map_err(|e| eyre!("UI error: {}", e))?;
+        if let Some(path) = &args.audit_log_file {
+            write_audit_log(path, &shared_state.lock().expect("Lock failed"))?;
+        }
     }
 
     Ok(())
 }
 
-fn init<T>() -> Result<T>
-where
-    T: Parser,
-{
-    let args = T::try_parse()?;
+/// The old value of an audit log entry, `?` if it wasn't cached.
+fn audit_value(value: Option<&Value>) -> String {
+    value.map_or_else(|| "?".to_string(), ToString::to_string)
+}
+
+/// Appends one `<timestamp> PARAM: old -> new` line per write in `state` to `path`.
+fn write_audit_log(path: &Path, state: &ParamState) -> Result<()> {
+    let entries = state.audit_log();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for (at, param, old, new) in entries {
+        writeln!(
+            lines,
+            "{} {param:?}: {} -> {new}",
+            wall_clock_time(*at)?.format("%+"),
+            audit_value(old.as_ref())
+        )?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    std::io::Write::write_all(&mut file, lines.as_bytes())?;
+    info!("Appended {} writes to audit log {path:?}", entries.len());
+    Ok(())
+}
+
+/// Instants have no wall-clock time, so they are dated relative to now.
+fn wall_clock_time(at: Instant) -> Result<DateTime<Local>> {
+    Ok(Local::now() - chrono::Duration::from_std(at.elapsed())?)
+}
+
+/// Runs a command which doesn't need a device, `None` for all other commands.
+fn run_without_device(command: &Command) -> Option<Result<()>> {
+    match command {
+        Command::Export {
+            format,
+            recording,
+            output,
+            prefix,
+            device_name,
+        } => Some(export(
+            *format,
+            recording.as_deref(),
+            output.as_deref(),
+            prefix,
+            device_name,
+        )),
+        Command::Snapshot {
+            action: Some(action),
+            ..
+        } => Some(match action {
+            SnapshotAction::List => list_snapshots(),
+            SnapshotAction::Diff { a, b } => diff_snapshot_files(a, b),
+        }),
+        Command::ParamInfo { param } => {
+            print!("{}", param.to_documentation_string());
+            Some(Ok(()))
+        }
+        Command::Segment { recording, output } => Some(segment(recording, output.as_deref())),
+        Command::AuditLog => Some(Err(eyre!(
+            "audit log not available in this session, use --audit-log-file to keep the writes of a run"
+        ))),
+        _ => None,
+    }
+}
+
+/// Runs a command which needs a device.
+fn run_command(
+    command: Command,
+    device: &ReSpeakerDevice,
+    running: &Arc<AtomicBool>,
+    quiet: bool,
+) -> Result<()> {
+    match command {
+        Command::List {
+            filter_access,
+            sort,
+            format,
+            watch,
+            interval_ms,
+        } => {
+            let interval = Duration::from_millis(interval_ms);
+            list(
+                device,
+                filter_access,
+                sort,
+                format,
+                watch.then_some(interval),
+                running,
+            )?;
+        }
+        Command::Read {
+            params,
+            continuous,
+            rate_limit_hz,
+        } => read_params(device, params, continuous, rate_limit_hz, running)?,
+        Command::Write(args) => write(device, args)?,
+        Command::Reset {
+            wait_ready,
+            timeout_secs,
+            force,
+        } => reset(device, wait_ready, Duration::from_secs(timeout_secs), force)?,
+        Command::RevertFactory => revert_factory(device)?,
+        Command::Doctor => doctor(device)?,
+        Command::Identify => identify(device),
+        Command::Benchmark(args) => benchmark(device, &args)?,
+        Command::StressTest(args) => stress_test(device, &args, running)?,
+        Command::Status { format } => status(device, format)?,
+        Command::EnvExport { shell } => env_export(device, shell)?,
+        Command::Tune { scenario } => tune(device, scenario)?,
+        Command::Snapshot {
+            action: None,
+            output,
+        } => snapshot(device, output)?,
+        Command::Watch {
+            params,
+            interval_ms,
+            alert_on_change,
+            alert_threshold,
+        } => watch(
+            device,
+            params,
+            Duration::from_millis(interval_ms),
+            alert_on_change,
+            alert_threshold,
+            running,
+        )?,
+        Command::Listen {
+            params,
+            poll_ms,
+            float_threshold,
+            sector,
+        } => listen(
+            device,
+            params,
+            Duration::from_millis(poll_ms),
+            float_threshold,
+            sector,
+            running,
+        )?,
+        Command::Monitor { interval_ms } => {
+            run_monitor(device, Duration::from_millis(interval_ms), running)?;
+        }
+        Command::Record(args) => record(args, device, running, quiet)?,
+        #[cfg(feature = "tokio")]
+        Command::Session(args) => session(device, args, running)?,
+        #[cfg(feature = "debug")]
+        Command::PacketDump => packet_dump(device, running)?,
+        #[cfg(feature = "audio")]
+        Command::AudioCapture { seconds, output } => {
+            device.list()?; // cache rw params
+            record_with_audio(seconds, &output, device, running)?;
+        }
+        Command::Export { .. }
+        | Command::Compare { .. }
+        | Command::Init
+        | Command::AuditLog
+        | Command::Segment { .. }
+        | Command::ParamInfo { .. }
+        | Command::Snapshot {
+            action: Some(_), ..
+        } => unreachable!("Handled before opening the device"),
+    }
+    Ok(())
+}
+
+fn record(
+    args: RecordArgs,
+    device: &ReSpeakerDevice,
+    running: &Arc<AtomicBool>,
+    quiet: bool,
+) -> Result<()> {
+    let RecordArgs {
+        seconds,
+        csv_path,
+        output,
+        split_on_speech,
+        silence_grace_ms,
+        on_error,
+        retry_delay_ms,
+        compress,
+        rw_refresh_interval_secs,
+        trigger_param,
+        trigger_threshold,
+        trigger_below,
+        append,
+        no_header,
+        exclude,
+        column_order,
+        trigger_file,
+        max_file_size_mb,
+        interleave_audio,
+        output_dir,
+        output_dir_format,
+        trigger_above: _,
+    } = args;
+    let trigger = trigger_condition(trigger_param, trigger_threshold, trigger_below)?;
+    let max_file_size = max_file_size_mb
+        .map(|mb| {
+            mb.checked_mul(1024 * 1024)
+                .ok_or_else(|| eyre!("--max-file-size-mb {mb} is too large"))
+        })
+        .transpose()?;
+    device.list()?; // cache rw params
+    if let Some(output_dir) = split_on_speech {
+        record_speech_segments(
+            seconds,
+            &output_dir,
+            Duration::from_millis(silence_grace_ms),
+            device,
+            running,
+        )?;
+    } else {
+        record_respeaker_parameters(
+            seconds,
+            csv_path,
+            device,
+            running,
+            &RecordingOptions {
+                format: output,
+                on_error,
+                retry_delay: Duration::from_millis(retry_delay_ms),
+                compress,
+                progress: !quiet,
+                rw_refresh_interval: rw_refresh_interval_secs,
+                trigger,
+                append,
+                no_header,
+                exclude,
+                column_order,
+                trigger_file,
+                max_file_size,
+                interleave_audio: interleave_audio
+                    .as_deref()
+                    .map(AudioTimeline::from_wav)
+                    .transpose()?,
+                output_dir,
+                output_dir_format,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Spaces out writes by a minimum interval. The time of the last write is kept in
+/// `<runtime dir>/respeaker/last_write`, so separate `write` runs, e.g. from a shell loop, are
+/// throttled as well.
+struct WriteThrottle {
+    interval: Duration,
+    state_file: PathBuf,
+    last_write: Option<SystemTime>,
+}
+
+impl WriteThrottle {
+    fn new(interval: Duration) -> Self {
+        let state_file = dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("respeaker")
+            .join("last_write");
+        let last_write = fs::read_to_string(&state_file)
+            .ok()
+            .and_then(|millis| millis.trim().parse().ok())
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+        Self {
+            interval,
+            state_file,
+            last_write,
+        }
+    }
+
+    /// Writes after waiting until the interval has passed since the previous write to any
+    /// parameter. Without an interval this is a plain write.
+    fn write(&mut self, device: &ReSpeakerDevice, param: &ParamKind, value: &Value) -> Result<()> {
+        if self.interval.is_zero() {
+            return device.write(param, value);
+        }
+        let elapsed = |last: SystemTime| last.elapsed().unwrap_or_default();
+        if let Some(remaining) = self
+            .last_write
+            .and_then(|last| self.interval.checked_sub(elapsed(last)))
+        {
+            debug!("Throttling write of {param:?} for {remaining:?}");
+            thread::sleep(remaining);
+        }
+        let result = device.write(param, value);
+        let now = SystemTime::now();
+        self.last_write = Some(now);
+        if let Err(e) = self.save(now) {
+            warn!(
+                "Failed to save the last write time to {:?}: {e}",
+                self.state_file
+            );
+        }
+        result
+    }
+
+    fn save(&self, at: SystemTime) -> Result<()> {
+        if let Some(dir) = self.state_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let millis = at.duration_since(UNIX_EPOCH)?.as_millis();
+        fs::write(&self.state_file, millis.to_string())?;
+        Ok(())
+    }
+}
+
+fn write(device: &ReSpeakerDevice, args: WriteArgs) -> Result<()> {
+    let change_log = match args.log_change {
+        None => None,
+        Some(Some(path)) => Some(path),
+        Some(None) => Some(CliSettings::load()?.change_log.ok_or_else(|| {
+            eyre!(
+                "--log-change needs a path or change_log in the [cli] table of {:?}",
+                config_file().unwrap_or_default()
+            )
+        })?),
+    };
+    if change_log.is_some() {
+        // Cache the old values, the audit log only knows them from the cache
+        device.read_rw()?;
+    }
+    let logged = device
+        .params()
+        .lock()
+        .expect("Lock failed")
+        .audit_log()
+        .len();
+    let mut throttle = WriteThrottle::new(Duration::from_millis(args.throttle_write_ms));
+    let result = match (args.param, args.value) {
+        (Some(param), Some(value)) if !args.from_stdin => {
+            write_param(device, &param, &value, args.interactive, &mut throttle)
+        }
+        _ => write_from_stdin(device, std::io::stdin().lock(), &mut throttle),
+    };
+    // Also log the writes which happened before a failure
+    if let Some(path) = change_log {
+        let state = device.params();
+        let state = state.lock().expect("Lock failed");
+        append_change_log(&path, &state.audit_log()[logged..])?;
+    }
+    result
+}
+
+/// Appends one `[<UTC timestamp>] PARAM: old -> new` line per entry to `path`.
+fn append_change_log(path: &Path, entries: &[AuditEntry]) -> Result<()> {
+    let mut lines = String::new();
+    for (at, param, old, new) in entries {
+        writeln!(
+            lines,
+            "[{}] {param:?}: {} -> {new}",
+            wall_clock_time(*at)?
+                .with_timezone(&Utc)
+                .format("%Y-%m-%dT%H:%M:%SZ"),
+            audit_value(old.as_ref())
+        )?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    std::io::Write::write_all(&mut file, lines.as_bytes())?;
+    debug!("Appended {} writes to change log {path:?}", entries.len());
+    Ok(())
+}
+
+fn write_param(
+    device: &ReSpeakerDevice,
+    param: &ParamKind,
+    value: &str,
+    interactive: bool,
+    throttle: &mut WriteThrottle,
+) -> Result<()> {
+    let value = param.parse_value(value)?;
+    if interactive && !confirm_write(device, param, &value)? {
+        return Err(eyre!("Aborted, nothing was changed"));
+    }
+    throttle.write(device, param, &value)?;
+
+    let related = param.def().related_params;
+    if !related.is_empty() {
+        let names = related
+            .iter()
+            .map(|p| format!("{p:?}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        info!("Related parameters: {names}");
+    }
+    Ok(())
+}
+
+fn write_from_stdin(
+    device: &ReSpeakerDevice,
+    input: impl BufRead,
+    throttle: &mut WriteThrottle,
+) -> Result<()> {
+    let mut assignments = vec![];
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let assignment =
+            parse_assignment(device, line).map_err(|e| eyre!("Line {}: {e}", index + 1))?;
+        assignments.push(assignment);
+    }
+    for (param, value) in &assignments {
+        throttle.write(device, param, value)?;
+    }
+    info!("Wrote {} parameters", assignments.len());
+    Ok(())
+}
+
+/// Parses a `PARAM=value` line and checks that the value can be written to `device`.
+fn parse_assignment(device: &ReSpeakerDevice, line: &str) -> Result<(ParamKind, Value)> {
+    let (name, value) = line
+        .split_once('=')
+        .ok_or_else(|| eyre!("Expected PARAM=value but got {line:?}"))?;
+    let (name, value) = (name.trim(), value.trim());
+    let param = ParamKind::from_str(name, false).map_err(|_| eyre!("Unknown parameter {name}"))?;
+    if !device.has_param(&param) {
+        return Err(eyre!(
+            "Parameter {param:?} is not available on {:?}",
+            device.model()
+        ));
+    }
+    if param.as_writeable().is_none() {
+        return Err(eyre!("Parameter {param:?} is read-only"));
+    }
+    let value = param
+        .parse_value(value)
+        .map_err(|e| eyre!("Invalid value {value:?} for {param:?}, {e}"))?;
+    let def = param.def();
+    if value.sanitize(&def) != value {
+        return Err(eyre!(
+            "Value {value} for {param:?} is not in range {}..={}",
+            def.min(),
+            def.max()
+        ));
+    }
+    Ok((param, value))
+}
+
+/// Prints the current and the new value and asks on stdin whether to write. Anything but `y` is a no.
+fn confirm_write(device: &ReSpeakerDevice, param: &ParamKind, value: &Value) -> Result<bool> {
+    let def = param.def();
+    let describe = |value: &Value| {
+        let text = value.to_display_string(&def);
+        match param.to_db(value) {
+            Some(db) => format!("{text} ({db:.1} dB)"),
+            None => text,
+        }
+    };
+    let current = device.read(param)?;
+    eprint!(
+        "Current value: {}. About to write: {} (max: {}). Confirm? [y/N]: ",
+        describe(&current),
+        describe(value),
+        def.max()
+    );
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+/// A reset sent while the firmware is in a DFU error state can crash it, so only continue if it is idle
+/// or `force` is set.
+fn check_dfu_idle(device: &ReSpeakerDevice, force: bool) -> Result<()> {
+    let status = device.dfu_status()?;
+    if status.is_idle() {
+        return Ok(());
+    }
+    warn!(
+        "DFU state machine is not idle (state {}, status {})",
+        status.state, status.status
+    );
+    if !force {
+        return Err(eyre!("Aborted reset, use --force to reset anyway"));
+    }
+    Ok(())
+}
+
+fn check_firmware(device: &ReSpeakerDevice) -> Result<()> {
+    let check = device.check_firmware_compat()?;
+    for warning in &check.warnings {
+        warn!("{warning}");
+    }
+    if !check.compatible {
+        return Err(eyre!(
+            "The firmware seems to be incompatible, it may use different command indices"
+        ));
+    }
+    Ok(())
+}
+
+fn revert_factory(device: &ReSpeakerDevice) -> Result<()> {
+    eprintln!(
+        "This reverts the firmware to the factory image. It can only be undone by re-flashing the"
+    );
+    eprintln!("firmware and all custom parameters are lost. Type REVERT to continue:");
+    let mut confirmation = String::new();
+    std::io::stdin().read_line(&mut confirmation)?;
+    if confirmation.trim() != "REVERT" {
+        return Err(eyre!("Aborted, nothing was changed"));
+    }
+    device.firmware_revert()
+}
+
+fn trigger_condition(
+    param: Option<ParamKind>,
+    threshold: Option<String>,
+    below: bool,
+) -> Result<Option<TriggerCondition>> {
+    let (Some(param), Some(threshold)) = (param, threshold) else {
+        return Ok(None);
+    };
+    Ok(Some(TriggerCondition {
+        threshold: param.parse_value(&threshold)?,
+        param,
+        direction: if below {
+            TriggerDirection::Below
+        } else {
+            TriggerDirection::Above
+        },
+    }))
+}
+
+/// `RESPEAKER_MOCK=PARAM=value,...` simulates a device, `RESPEAKER_MOCK_TRANSFERS=PATH` logs its
+/// transfers. Only in debug builds, for the integration tests.
+#[cfg(debug_assertions)]
+fn open_mock_from_env(state: &Arc<Mutex<ParamState>>) -> Result<Option<ReSpeakerDevice>> {
+    let Some(seed) = std::env::var_os("RESPEAKER_MOCK") else {
+        return Ok(None);
+    };
+    let mut mock = MockDevice::from_seed(&seed.to_string_lossy())?;
+    if let Some(path) = std::env::var_os("RESPEAKER_MOCK_TRANSFERS") {
+        mock = mock.with_transfer_log(path.into());
+    }
+    Ok(Some(ReSpeakerDevice::open_mock(
+        Arc::new(mock),
+        state.clone(),
+    )))
+}
+
+#[cfg(not(debug_assertions))]
+#[allow(clippy::unnecessary_wraps)]
+fn open_mock_from_env(_state: &Arc<Mutex<ParamState>>) -> Result<Option<ReSpeakerDevice>> {
+    Ok(None)
+}
+
+/// Prints `params` once, or with `continuous` until Ctrl-C is pressed.
+fn read_params(
+    device: &ReSpeakerDevice,
+    mut params: Vec<ParamKind>,
+    continuous: bool,
+    rate_limit_hz: u32,
+    running: &AtomicBool,
+) -> Result<()> {
+    let period = Duration::from_secs_f64(1.0 / f64::from(rate_limit_hz));
+    if params.is_empty() {
+        params = device
+            .available_params()
+            .into_iter()
+            .filter(ParamKind::is_real_time_monitor)
+            .collect();
+    }
+    loop {
+        let start = Instant::now();
+        let values = params
+            .iter()
+            .map(|param| {
+                let value = device.read(param)?;
+                Ok((param, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut result = String::new();
+        for (param, value) in values {
+            writeln!(
+                &mut result,
+                "{:?}={}",
+                param,
+                value.to_display_string(&param.def())
+            )?;
+        }
+        print!("{result}");
+        if !continuous || !running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        // Don't hog the USB bus, other devices may share the host controller
+        if let Some(remaining) = period.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        } else {
+            warn!("Read took longer than rate-limit period");
+        }
+    }
+}
+
+/// Runs `export`, which does not need a device.
+#[cfg_attr(not(feature = "bincode"), allow(unused_variables))]
+fn export(
+    format: ExportTarget,
+    recording: Option<&Path>,
+    output: Option<&Path>,
+    prefix: &str,
+    device_name: &str,
+) -> Result<()> {
+    match format {
+        ExportTarget::HomeAssistant => {
+            print!("{}", home_assistant_yaml(prefix, device_name));
+        }
+        ExportTarget::Python => {
+            let recording = recording.ok_or_else(|| eyre!("--format python needs a recording"))?;
+            print!("{}", python_replay_script(&mut CsvReader::new(recording)?)?);
+        }
+        ExportTarget::Matlab => {
+            let (Some(recording), Some(output)) = (recording, output) else {
+                return Err(eyre!(
+                    "--format matlab needs a CSV recording and an output file"
+                ));
+            };
+            let rows = csv_to_mat(&mut CsvReader::new(recording)?, output)?;
+            info!("Converted {rows} rows to {}", output.display());
+        }
+        ExportTarget::Parquet => {
+            let (Some(recording), Some(output)) = (recording, output) else {
+                return Err(eyre!(
+                    "--format parquet needs a CSV recording and an output file"
+                ));
+            };
+            let rows = csv_to_parquet(&mut CsvReader::new(recording)?, output)?;
+            info!("Converted {rows} rows to {}", output.display());
+        }
+        #[cfg(feature = "bincode")]
+        ExportTarget::Csv => {
+            let (Some(recording), Some(output)) = (recording, output) else {
+                return Err(eyre!(
+                    "--format csv needs a binary recording and an output file"
+                ));
+            };
+            let rows = binary_to_csv(recording, output)?;
+            info!("Converted {rows} rows to {}", output.display());
+        }
+    }
+    Ok(())
+}
+
+fn segment(recording: &Path, output: Option<&Path>) -> Result<()> {
+    let segments = extract_segments(&mut CsvReader::new(recording)?)?;
+    if let Some(output) = output {
+        write_segments_csv(output, &segments)?;
+        info!("Wrote {} segments to {}", segments.len(), output.display());
+    } else {
+        println!("{}", segments_table(&segments));
+    }
+    Ok(())
+}
+
+fn reset(device: &ReSpeakerDevice, wait_ready: bool, timeout: Duration, force: bool) -> Result<()> {
+    check_dfu_idle(device, force)?;
+    if wait_ready {
+        device.reset_wait_ready(timeout)
+    } else {
+        device.reset()
+    }
+}
+
+#[cfg(feature = "tokio")]
+fn session(device: &ReSpeakerDevice, args: SessionArgs, running: &Arc<AtomicBool>) -> Result<()> {
+    let options = SessionOptions {
+        record_path: args.record_path,
+        serve_port: args.serve_port,
+        bind: Some(args.bind),
+        seconds: args.seconds,
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    let summary = runtime.block_on(run_session(device, &options, running))?;
+    println!("{}", session_summary(device, &summary));
+    Ok(())
+}
+
+/// Prints the parameters once, or keeps refreshing the table every `watch_interval`.
+fn list(
+    device: &ReSpeakerDevice,
+    filter: Option<Access>,
+    sort: Option<ParamSortOrder>,
+    format: ExportFormat,
+    watch_interval: Option<Duration>,
+    running: &Arc<AtomicBool>,
+) -> Result<()> {
+    if let Some(interval) = watch_interval {
+        return run_list_watch(device, filter, sort, interval, running);
+    }
+    device.export_sorted_to_writer(std::io::stdout().lock(), format, filter, sort)
+}
+
+fn tune(device: &ReSpeakerDevice, scenario: Scenario) -> Result<()> {
+    run_tune(
+        device,
+        scenario,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
+    )
+}
+
+fn benchmark(device: &ReSpeakerDevice, args: &BenchmarkArgs) -> Result<()> {
+    let iterations = usize::try_from(args.iterations)?;
+    print!(
+        "{}",
+        run_benchmark(device, args.profile, &args.param, iterations)?
+    );
+    println!("{}", device.metrics());
+    Ok(())
+}
+
+fn stress_test(
+    device: &ReSpeakerDevice,
+    args: &StressTestArgs,
+    running: &AtomicBool,
+) -> Result<()> {
+    let max_error_rate_percent = args.max_error_rate_percent;
+    let result = device.stress_test(args.iterations, running)?;
+    println!("{}", Table::new([result]));
+    let error_rate = result.error_rate_percent();
+    if error_rate > max_error_rate_percent {
+        return Err(eyre!(
+            "Error rate of {error_rate:.2}% is above {max_error_rate_percent}%"
+        ));
+    }
+    println!("Error rate {error_rate:.2}%, passed");
+    Ok(())
+}
+
+fn listen(
+    device: &ReSpeakerDevice,
+    params: Vec<ParamKind>,
+    interval: Duration,
+    float_threshold: f32,
+    sector: bool,
+    running: &Arc<AtomicBool>,
+) -> Result<()> {
+    let params = if params.is_empty() {
+        device
+            .available_params()
+            .into_iter()
+            .filter(|p| p.def().access == Access::ReadOnly)
+            .collect()
+    } else {
+        params
+    };
+    run_listen(device, &params, interval, float_threshold, sector, running)
+}
+
+fn watch(
+    device: &ReSpeakerDevice,
+    params: Vec<ParamKind>,
+    interval: Duration,
+    alert_on_change: bool,
+    alert_threshold: Option<f32>,
+    running: &Arc<AtomicBool>,
+) -> Result<()> {
+    let params = if params.is_empty() {
+        device
+            .available_params()
+            .into_iter()
+            .filter(ParamKind::is_real_time_monitor)
+            .collect()
+    } else {
+        params
+    };
+    run_watch(
+        device,
+        &params,
+        interval,
+        alert_on_change,
+        alert_threshold,
+        running,
+    )
+}
+
+fn snapshot(device: &ReSpeakerDevice, output: Option<PathBuf>) -> Result<()> {
+    device.read_rw()?;
+    device.read_ro()?;
+    let path = output.unwrap_or_else(|| {
+        let timestamp = Local::now().format("%Y-%m-%dT%H_%M_%S");
+        PathBuf::from(SNAPSHOT_DIR).join(format!("{timestamp}.toml"))
+    });
+    save_snapshot(&path, &device.params().lock().expect("Lock failed"))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// Creates the config directory and `config.toml`. Only the `current.toml` preset needs the device,
+/// it is skipped if the device can't be opened.
+fn init_config_dir(open_device: impl FnOnce() -> Result<ReSpeakerDevice>) -> Result<()> {
+    let (Some(dir), Some(presets), Some(config)) = (config_dir(), presets_dir(), config_file())
+    else {
+        return Err(eyre!("No config directory on this system"));
+    };
+    for dir in [&dir, &presets] {
+        if dir.is_dir() {
+            println!("Exists:  {}", dir.display());
+        } else {
+            fs::create_dir_all(dir)?;
+            println!("Created: {}", dir.display());
+        }
+    }
+    create_new_file(&config, &default_config_toml()?)?;
+
+    let current = presets.join("current.toml");
+    let device = match open_device() {
+        Err(e) => {
+            warn!("Could not open the device, not saving its parameters: {e}");
+            println!("Skipped: {} (no device)", current.display());
+            return Ok(());
+        }
+        device => device?,
+    };
+    device.read_rw()?;
+    let params = config_to_toml(&device.params().lock().expect("Lock failed"))?;
+    create_new_file(&current, &params)
+}
+
+/// Writes `contents` to a new file, an existing file is skipped with a warning.
+fn create_new_file(path: &Path, contents: &str) -> Result<()> {
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            warn!("{} already exists, not overwriting it", path.display());
+            println!("Skipped: {}", path.display());
+            return Ok(());
+        }
+        file => file?,
+    };
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+    println!("Created: {}", path.display());
+    Ok(())
+}
+
+fn list_snapshots() -> Result<()> {
+    let dir = PathBuf::from(SNAPSHOT_DIR);
+    if !dir.exists() {
+        println!("No snapshots in {}", dir.display());
+        return Ok(());
+    }
+    let mut paths = fs::read_dir(&dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    // Timestamped names sort chronologically
+    paths.sort();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+    {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn diff_snapshot_files(a: &Path, b: &Path) -> Result<()> {
+    let diff = diff_snapshots(&load_snapshot(a)?, &load_snapshot(b)?);
+    if diff.is_empty() {
+        println!("No differences");
+    }
+    for (param, old, new) in diff {
+        let format =
+            |value: Option<Value>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+        println!("{param:?}: {} -> {}", format(old), format(new));
+    }
+    Ok(())
+}
+
+fn identify(device: &ReSpeakerDevice) {
+    let info = device.device_info();
+    println!(
+        "{} | Bus {:03} Device {:03} | Serial: {} | Firmware: {} | Uptime: N/A",
+        device.model().name(),
+        info.bus,
+        info.address,
+        info.serial.as_deref().unwrap_or("N/A"),
+        info.firmware
+            .map_or_else(|| "N/A".to_string(), |v| format!("v{v}"))
+    );
+}
+
+fn status(device: &ReSpeakerDevice, format: StatusFormat) -> Result<()> {
+    let read = |param: ParamKind| {
+        if device.has_param(&param) {
+            device.read(&param).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    let doa = read(ParamKind::DOAANGLE)?;
+    let vad = read(ParamKind::VOICEACTIVITY)?;
+    let speech = read(ParamKind::SPEECHDETECTED)?;
+    let rt60 = read(ParamKind::RT60)?;
+    let agc = read(ParamKind::AGCGAIN)?;
+
+    match format {
+        StatusFormat::Human => {
+            let on_off = |value: &Value| if value == &Value::Int(1) { "ON" } else { "OFF" };
+            let or_na = |value: Option<String>| value.unwrap_or_else(|| "N/A".to_string());
+            println!(
+                "DOA={} | VAD={} | Speech={} | RT60={} | AGC={}",
+                or_na(doa.map(|v| format!("{v}°"))),
+                or_na(vad.as_ref().map(|v| on_off(v).to_string())),
+                or_na(speech.as_ref().map(|v| on_off(v).to_string())),
+                or_na(rt60.map(|v| format!("{v}s"))),
+                or_na(
+                    agc.and_then(|v| ParamKind::AGCGAIN.to_db(&v))
+                        .map(|db| format!("{db:+.0}dB"))
+                ),
+            );
+        }
+        StatusFormat::Machine => {
+            let line = [doa, vad, speech, rt60, agc]
+                .iter()
+                .map(|value| match value {
+                    Some(Value::Int(i)) => i.to_string(),
+                    Some(Value::Float(f)) => format!("{f:?}"),
+                    None => "-".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+fn env_export(device: &ReSpeakerDevice, shell: Shell) -> Result<()> {
+    let mut state = ParamState::default();
+    state.current_params = device.read_rw()?;
+    for (name, value) in state.to_env_vars() {
+        match shell {
+            Shell::Bash => println!("export {name}={value}"),
+            Shell::Fish => println!("set -gx {name} {value}"),
+        }
+    }
+    Ok(())
+}
+
+fn doctor(device: &ReSpeakerDevice) -> Result<()> {
+    let info = device.device_info();
+    println!(
+        "Device: bus {:03} address {:03}, serial {}, firmware {}",
+        info.bus,
+        info.address,
+        info.serial.as_deref().unwrap_or("unknown"),
+        info.firmware.as_deref().unwrap_or("unknown")
+    );
+
+    let model = device.model();
+    println!("Model: {model:?}");
+
+    let check = device.check_firmware_compat()?;
+    for warning in &check.warnings {
+        println!("WARN {warning}");
+    }
+
+    let mut failed = 0;
+    let mut total = 0;
+    for param in device.available_params() {
+        total += 1;
+        if let Err(e) = device.read(&param) {
+            failed += 1;
+            let since = param
+                .def()
+                .firmware_since
+                .map_or_else(String::new, |v| format!(" (requires firmware {v})"));
+            println!("FAIL {param:?}: {e}{since}");
+        }
+    }
+
+    if failed > 0 {
+        return Err(eyre!("{failed} of {total} parameters could not be read"));
+    }
+    if !check.compatible {
+        return Err(eyre!(
+            "The firmware seems to be incompatible, it may use different command indices"
+        ));
+    }
+    println!("OK: all {total} parameters are readable");
+    Ok(())
+}
+
+fn init() -> Result<Arguments> {
+    let args = Arguments::try_parse()?;
     color_eyre::install()?;
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .try_init()
-        .map_err(|e| eyre!("Tracing init error: {e}"))?;
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(args.log_level().to_string()));
+    let writer = match &args.log_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        // The device's spans are trace level, so this only logs their timing with -vv or RUST_LOG
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(args.log_file.is_none())
+        .with_writer(writer);
+    match args.log_format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| eyre!("Tracing init error: {e}"))?;
     Ok(args)
 }
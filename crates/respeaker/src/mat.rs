@@ -0,0 +1,147 @@
+use std::{fs, path::Path};
+
+use chrono::Local;
+
+// Data types of the data elements
+const MI_INT8: u32 = 1;
+const MI_INT32: u32 = 5;
+const MI_UINT16: u32 = 4;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_DOUBLE: u32 = 9;
+const MI_MATRIX: u32 = 14;
+
+// Array classes in the array flags
+const MX_STRUCT_CLASS: u32 = 2;
+const MX_CHAR_CLASS: u32 = 4;
+const MX_DOUBLE_CLASS: u32 = 6;
+const MX_SINGLE_CLASS: u32 = 7;
+const MX_INT32_CLASS: u32 = 12;
+
+/// Field names of a struct are stored with a fixed length, including the terminating NUL.
+const FIELD_NAME_LEN: usize = 32;
+
+/// A MATLAB variable. Numeric arrays are column vectors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatArray {
+    Double(Vec<f64>),
+    Single(Vec<f32>),
+    Int32(Vec<i32>),
+    Char(String),
+    Struct(Vec<(String, MatArray)>),
+}
+
+/// Writes `variables` to an uncompressed Level 5 MAT-file, which MATLAB and Octave (`load`) as well as
+/// `scipy.io.loadmat` can read.
+///
+/// Names must be valid MATLAB identifiers, struct field names at most 31 characters.
+pub fn write_mat(path: &Path, variables: &[(String, MatArray)]) -> eyre::Result<()> {
+    let mut bytes = header();
+    for (name, array) in variables {
+        element(&mut bytes, MI_MATRIX, &matrix(name, array)?);
+    }
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+fn header() -> Vec<u8> {
+    let text = format!(
+        "MATLAB 5.0 MAT-file, Platform: {}, Created on: {}, by respeaker-rs {}",
+        std::env::consts::OS,
+        Local::now().format("%a %b %e %H:%M:%S %Y"),
+        env!("CARGO_PKG_VERSION")
+    );
+    let mut bytes = text.into_bytes();
+    bytes.resize(116, b' ');
+    // No subsystem data
+    bytes.extend([0; 8]);
+    // Version 0x0100 and the endian indicator, which reads "IM" in little endian files
+    bytes.extend(0x0100u16.to_le_bytes());
+    bytes.extend(*b"IM");
+    bytes
+}
+
+/// Appends a data element: type and size (u32 each) followed by the data, padded to 8 bytes.
+fn element(out: &mut Vec<u8>, data_type: u32, data: &[u8]) {
+    out.extend(data_type.to_le_bytes());
+    out.extend(
+        u32::try_from(data.len())
+            .expect("MAT element too large")
+            .to_le_bytes(),
+    );
+    out.extend(data);
+    out.resize(out.len().next_multiple_of(8), 0);
+}
+
+fn matrix(name: &str, array: &MatArray) -> eyre::Result<Vec<u8>> {
+    let class = match array {
+        MatArray::Double(_) => MX_DOUBLE_CLASS,
+        MatArray::Single(_) => MX_SINGLE_CLASS,
+        MatArray::Int32(_) => MX_INT32_CLASS,
+        MatArray::Char(_) => MX_CHAR_CLASS,
+        MatArray::Struct(_) => MX_STRUCT_CLASS,
+    };
+    let (rows, columns) = match array {
+        MatArray::Double(values) => (values.len(), 1),
+        MatArray::Single(values) => (values.len(), 1),
+        MatArray::Int32(values) => (values.len(), 1),
+        MatArray::Char(text) if text.is_empty() => (0, 0),
+        MatArray::Char(text) => (1, text.encode_utf16().count()),
+        MatArray::Struct(_) => (1, 1),
+    };
+
+    let mut out = vec![];
+    element(&mut out, MI_UINT32, &[class.to_le_bytes(), [0; 4]].concat());
+    let dimensions = [i32::try_from(rows)?, i32::try_from(columns)?];
+    element(
+        &mut out,
+        MI_INT32,
+        &dimensions.map(i32::to_le_bytes).concat(),
+    );
+    element(&mut out, MI_INT8, name.as_bytes());
+
+    let (data_type, data): (u32, Vec<u8>) = match array {
+        MatArray::Double(values) => (
+            MI_DOUBLE,
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ),
+        MatArray::Single(values) => (
+            MI_SINGLE,
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ),
+        MatArray::Int32(values) => (
+            MI_INT32,
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ),
+        MatArray::Char(text) => (
+            MI_UINT16,
+            text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        ),
+        MatArray::Struct(fields) => {
+            struct_fields(&mut out, fields)?;
+            return Ok(out);
+        }
+    };
+    element(&mut out, data_type, &data);
+    Ok(out)
+}
+
+fn struct_fields(out: &mut Vec<u8>, fields: &[(String, MatArray)]) -> eyre::Result<()> {
+    // Small data element: size in the upper, type in the lower 16 bits, the data in the tag itself
+    out.extend(((4 << 16) | MI_INT32).to_le_bytes());
+    out.extend(u32::try_from(FIELD_NAME_LEN)?.to_le_bytes());
+    let mut names = vec![0; fields.len() * FIELD_NAME_LEN];
+    for ((field, _), chunk) in fields.iter().zip(names.chunks_mut(FIELD_NAME_LEN)) {
+        eyre::ensure!(
+            field.len() < FIELD_NAME_LEN,
+            "Struct field name {field} is too long"
+        );
+        chunk[..field.len()].copy_from_slice(field.as_bytes());
+    }
+    element(out, MI_INT8, &names);
+    for (_, value) in fields {
+        // Fields are unnamed matrices
+        element(out, MI_MATRIX, &matrix("", value)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+use eyre::{bail, eyre, OptionExt};
+use strum::IntoEnumIterator;
+
+use crate::params::{ParamKind, Value};
+
+/// A USB control transfer as seen by the [`MockDevice`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlTransfer {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub data: Vec<u8>,
+}
+
+/// One line of the transfer log, see [`MockDevice::with_transfer_log`]: request type, request, value
+/// and index in hex, followed by the data as a hex string if there is any.
+impl Display for ControlTransfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02x} {:02x} {:04x} {:04x}",
+            self.request_type, self.request, self.value, self.index
+        )?;
+        if !self.data.is_empty() {
+            f.write_str(" ")?;
+            for byte in &self.data {
+                write!(f, "{byte:02x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ControlTransfer {
+    type Err = eyre::Report;
+
+    fn from_str(line: &str) -> eyre::Result<Self> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (header, data) = match fields.as_slice() {
+            [a, b, c, d] => ([*a, *b, *c, *d], ""),
+            [a, b, c, d, data] => ([*a, *b, *c, *d], *data),
+            _ => bail!("Invalid control transfer {line:?}"),
+        };
+        if data.len() % 2 != 0 {
+            bail!("Odd number of hex digits in {line:?}");
+        }
+        let data = (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            request_type: u8::from_str_radix(header[0], 16)?,
+            request: u8::from_str_radix(header[1], 16)?,
+            value: u16::from_str_radix(header[2], 16)?,
+            index: u16::from_str_radix(header[3], 16)?,
+            data,
+        })
+    }
+}
+
+/// Emulates the `ReSpeaker` firmware on the level of USB control transfers, so the complete
+/// encoding / decoding path of [`crate::respeaker_device::ReSpeakerDevice`] can be used without hardware.
+#[derive(Debug)]
+pub struct MockDevice {
+    registers: Mutex<HashMap<ParamKind, Value>>,
+    transfers: Mutex<Vec<ControlTransfer>>,
+    dfu_state: AtomicU8,
+    transfer_log: Option<PathBuf>,
+}
+
+impl Default for MockDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockDevice {
+    /// Creates a mock where every parameter is set to its minimum.
+    #[must_use]
+    pub fn new() -> Self {
+        let registers = ParamKind::iter()
+            .map(|p| {
+                let value = p.def().min();
+                (p, value)
+            })
+            .collect();
+        Self {
+            registers: Mutex::new(registers),
+            transfers: Mutex::new(vec![]),
+            // dfuIDLE
+            dfu_state: AtomicU8::new(2),
+            transfer_log: None,
+        }
+    }
+
+    /// Also appends every transfer as a line to `path`, so another process can check them. See
+    /// [`ControlTransfer`] for the format.
+    #[must_use]
+    pub fn with_transfer_log(mut self, path: PathBuf) -> Self {
+        self.transfer_log = Some(path);
+        self
+    }
+
+    /// Creates a mock from a comma separated list of `PARAM=value` pairs, e.g. `DOAANGLE=42,VOICEACTIVITY=1`.
+    pub fn from_seed(seed: &str) -> eyre::Result<Self> {
+        let mock = Self::new();
+        for pair in seed.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_eyre("Mock seed must have the form PARAM=value")?;
+            let param = <ParamKind as clap::ValueEnum>::from_str(name, false)
+                .map_err(|e| eyre!("Unknown parameter in mock seed: {e}"))?;
+            let value = param.parse_value(value)?;
+            mock.set(&param, value);
+        }
+        Ok(mock)
+    }
+
+    /// Sets a value directly, bypassing range and access checks (like the firmware updating a RO value).
+    pub fn set(&self, param: &ParamKind, value: Value) {
+        self.registers
+            .lock()
+            .expect("Lock failed")
+            .insert(param.clone(), value);
+    }
+
+    #[must_use]
+    pub fn get(&self, param: &ParamKind) -> Option<Value> {
+        self.registers
+            .lock()
+            .expect("Lock failed")
+            .get(param)
+            .cloned()
+    }
+
+    /// Sets the `bState` reported by `DFU_GETSTATUS`, e.g. to simulate a firmware in an error state.
+    pub fn set_dfu_state(&self, state: u8) {
+        self.dfu_state.store(state, Ordering::Relaxed);
+    }
+
+    /// All control transfers received so far, oldest first.
+    #[must_use]
+    pub fn transfers(&self) -> Vec<ControlTransfer> {
+        self.transfers.lock().expect("Lock failed").clone()
+    }
+
+    pub(crate) fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+    ) -> rusb::Result<usize> {
+        self.log(request_type, request, value, index, &[]);
+
+        if request_type & 0x60 == 0x20 {
+            // DFU_GETSTATUS is the only class request with a response: OK, no poll timeout
+            let response = [0, 0, 0, 0, self.dfu_state.load(Ordering::Relaxed), 0];
+            let len = buf.len().min(response.len());
+            buf[..len].copy_from_slice(&response[..len]);
+            return Ok(len);
+        }
+
+        let cmd = value & !0xC0;
+        let param = ParamKind::from_index(index, cmd).ok_or(rusb::Error::Pipe)?;
+        let response = match self.get(&param) {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            Some(Value::Int(v)) => [v as i32, 0],
+            Some(Value::Float(f)) => encode_float(f),
+            None => return Err(rusb::Error::Pipe),
+        };
+
+        let mut bytes = [0u8; 8];
+        bytes[0..4].copy_from_slice(&response[0].to_le_bytes());
+        bytes[4..8].copy_from_slice(&response[1].to_le_bytes());
+        let len = buf.len().min(bytes.len());
+        buf[..len].copy_from_slice(&bytes[..len]);
+        Ok(len)
+    }
+
+    pub(crate) fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+    ) -> rusb::Result<usize> {
+        self.log(request_type, request, value, index, buf);
+
+        if request_type & 0x60 != 0x40 {
+            // Class requests (DFU) are only logged
+            return Ok(buf.len());
+        }
+
+        let word = |i: usize| -> rusb::Result<[u8; 4]> {
+            buf.get(i * 4..i * 4 + 4)
+                .and_then(|b| b.try_into().ok())
+                .ok_or(rusb::Error::InvalidParam)
+        };
+        let cmd =
+            u16::try_from(i32::from_le_bytes(word(0)?)).map_err(|_| rusb::Error::InvalidParam)?;
+        let param = ParamKind::from_index(index, cmd).ok_or(rusb::Error::Pipe)?;
+        let new_value = if i32::from_le_bytes(word(2)?) == 1 {
+            #[allow(clippy::cast_sign_loss)]
+            Value::Int(i32::from_le_bytes(word(1)?) as usize)
+        } else {
+            Value::Float(f32::from_le_bytes(word(1)?))
+        };
+        self.set(&param, new_value);
+        Ok(buf.len())
+    }
+
+    fn log(&self, request_type: u8, request: u8, value: u16, index: u16, data: &[u8]) {
+        let transfer = ControlTransfer {
+            request_type,
+            request,
+            value,
+            index,
+            data: data.to_vec(),
+        };
+        let mut transfers = self.transfers.lock().expect("Lock failed");
+        if let Some(path) = &self.transfer_log {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{transfer}"))
+                .expect("Failed to write the mock transfer log");
+        }
+        transfers.push(transfer);
+    }
+}
+
+/// Encodes a float like the firmware does: `value = mantissa * 2^exponent`.
+fn encode_float(value: f32) -> [i32; 2] {
+    if value == 0.0 {
+        return [0, 0];
+    }
+    let value = f64::from(value);
+    // Keeps |mantissa| in [2^29, 2^30)
+    #[allow(clippy::cast_possible_truncation)]
+    let exponent = value.abs().log2().floor() as i32 - 29;
+    #[allow(clippy::cast_possible_truncation)]
+    let mantissa = (value * (-f64::from(exponent)).exp2()).round() as i32;
+    [mantissa, exponent]
+}
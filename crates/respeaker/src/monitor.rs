@@ -0,0 +1,513 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    hash::BuildHasher,
+    io::{stdout, Stdout, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use chrono::Local;
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    execute, queue,
+    style::{Print, Stylize},
+    terminal::{Clear, ClearType},
+};
+use eyre::bail;
+use tracing::warn;
+
+use crate::{
+    alert::{send_notification, should_alert},
+    analysis::DoaSector,
+    build_string,
+    params::{Access, ParamKind, ParamSortOrder, Value},
+    respeaker_device::ReSpeakerDevice,
+};
+
+const COMPASS_SECTORS: usize = 12;
+const VAD_BAR_WIDTH: usize = 20;
+
+/// Live dashboard with DOA, voice activity, speech detection and RT60, redrawn in place every `interval`
+/// until `running` is false.
+pub fn run_monitor(
+    device: &ReSpeakerDevice,
+    interval: Duration,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let read = |param: ParamKind| -> eyre::Result<Option<Value>> {
+        if device.has_param(&param) {
+            Ok(Some(device.read(&param)?))
+        } else {
+            Ok(None)
+        }
+    };
+
+    let mut out = stdout();
+    execute!(out, Hide, Clear(ClearType::All))?;
+    let mut vad_level = 0.0f32;
+
+    let result = (|| {
+        while running.load(Ordering::SeqCst) {
+            let doa = read(ParamKind::DOAANGLE)?;
+            let vad = read(ParamKind::VOICEACTIVITY)? == Some(Value::Int(1));
+            let speech = read(ParamKind::SPEECHDETECTED)? == Some(Value::Int(1));
+            let rt60 = read(ParamKind::RT60)?;
+            let (speech_events, vad_events) = {
+                let params = device.params();
+                let params = params.lock().expect("Lock failed");
+                (params.speech_detection_count, params.voice_activity_count)
+            };
+
+            // Decays slowly so short VAD pulses stay visible
+            vad_level = if vad { 1.0 } else { vad_level * 0.9 };
+
+            let mut screen = String::new();
+            writeln!(screen, "ReSpeaker monitor (Ctrl-C to quit)")?;
+            writeln!(screen)?;
+            match doa {
+                Some(Value::Int(angle)) => {
+                    writeln!(screen, "DOA     {} {angle:>3}°", compass(angle))?;
+                }
+                _ => {
+                    writeln!(screen, "DOA     n/a")?;
+                }
+            }
+            writeln!(
+                screen,
+                "VAD     {} {}",
+                level_bar(vad_level),
+                if vad { "voice" } else { "silence" }
+            )?;
+            writeln!(
+                screen,
+                "Speech  {}  {speech_events} events ({vad_events} VAD events)",
+                if speech { "●" } else { "○" }
+            )?;
+            match rt60 {
+                Some(Value::Float(rt60)) => {
+                    writeln!(screen, "RT60    {rt60:.3} s")?;
+                }
+                _ => {
+                    writeln!(screen, "RT60    n/a")?;
+                }
+            }
+
+            draw(&mut out, &screen)?;
+            thread::sleep(interval);
+        }
+        Ok(())
+    })();
+
+    execute!(out, Show)?;
+    result
+}
+
+/// Side-by-side view of `params` on two devices, e.g. `DOAANGLE: A=42° B=38° diff=4°`, redrawn in place
+/// every `interval` until `running` is false.
+pub fn run_compare(
+    device_a: &ReSpeakerDevice,
+    device_b: &ReSpeakerDevice,
+    params: &[ParamKind],
+    interval: Duration,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let read = |device: &ReSpeakerDevice, param: &ParamKind| -> eyre::Result<Option<Value>> {
+        if device.has_param(param) {
+            Ok(Some(device.read(param)?))
+        } else {
+            Ok(None)
+        }
+    };
+
+    let mut out = stdout();
+    execute!(out, Hide, Clear(ClearType::All))?;
+
+    let result = (|| {
+        while running.load(Ordering::SeqCst) {
+            let mut screen = String::new();
+            writeln!(screen, "ReSpeaker compare (Ctrl-C to quit)")?;
+            writeln!(screen)?;
+            for param in params {
+                let a = read(device_a, param)?;
+                let b = read(device_b, param)?;
+                writeln!(screen, "{}", compare_line(param, a.as_ref(), b.as_ref()))?;
+            }
+
+            draw(&mut out, &screen)?;
+            thread::sleep(interval);
+        }
+        Ok(())
+    })();
+
+    execute!(out, Show)?;
+    result
+}
+
+/// Prints each of `params` with a timestamp whenever its value changes, polled every `interval` until
+/// `running` is false.
+///
+/// With `alert_on_change`, changes which pass [`should_alert`] also show a desktop notification, or ring
+/// the terminal bell if notifications are not available.
+pub fn run_watch(
+    device: &ReSpeakerDevice,
+    params: &[ParamKind],
+    interval: Duration,
+    alert_on_change: bool,
+    alert_threshold: Option<f32>,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let mut previous: Vec<Option<Value>> = vec![None; params.len()];
+    // Value of the last alert, so slow drifts below the threshold still add up to an alert
+    let mut alerted: Vec<Option<Value>> = vec![None; params.len()];
+    let mut notifications_work = true;
+    while running.load(Ordering::SeqCst) {
+        for (i, param) in params.iter().enumerate() {
+            let def = param.def();
+            let value = device.read(param)?;
+            if previous[i].as_ref() != Some(&value) {
+                println!(
+                    "{} {param:?}={}",
+                    Local::now().format("%H:%M:%S%.3f"),
+                    value.to_display_string(&def)
+                );
+            }
+            match &alerted[i] {
+                Some(old)
+                    if alert_on_change && should_alert(param, old, &value, alert_threshold) =>
+                {
+                    let body = format!(
+                        "{} -> {}",
+                        old.to_display_string(&def),
+                        value.to_display_string(&def)
+                    );
+                    if notifications_work {
+                        if let Err(e) = send_notification(&format!("ReSpeaker {param:?}"), &body) {
+                            warn!("Desktop notifications are not available, ringing the bell instead: {e}");
+                            notifications_work = false;
+                        }
+                    }
+                    if !notifications_work {
+                        eprint!("\x07");
+                    }
+                    alerted[i] = Some(value.clone());
+                }
+                Some(_) => {}
+                None => alerted[i] = Some(value.clone()),
+            }
+            previous[i] = Some(value);
+        }
+        thread::sleep(interval);
+    }
+    Ok(())
+}
+
+/// Prints a line like `[12:34:56.789] VOICEACTIVITY: 0 -> 1` whenever one of `params` changes, polled every
+/// `interval` until `running` is false. Nothing is printed for the first reading.
+///
+/// Float parameters only count as changed if they moved by more than `float_threshold` since the last
+/// printed value, so slow drifts still show up eventually. With `sector`, changes of the [`DoaSector`]
+/// are printed as `DOASECTOR: Front -> Left` lines as well.
+pub fn run_listen(
+    device: &ReSpeakerDevice,
+    params: &[ParamKind],
+    interval: Duration,
+    float_threshold: f32,
+    sector: bool,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    if sector && !device.has_param(&ParamKind::DOAANGLE) {
+        bail!(
+            "{:?} has no DOAANGLE, --sector is not available",
+            device.model()
+        );
+    }
+    let timestamp = || Local::now().format("%H:%M:%S%.3f");
+    let mut reported: HashMap<ParamKind, Value> = HashMap::new();
+    let mut reported_sector: Option<DoaSector> = None;
+    while running.load(Ordering::SeqCst) {
+        let mut doa = None;
+        for param in params {
+            let value = device.read(param)?;
+            if *param == ParamKind::DOAANGLE {
+                doa = Some(value.clone());
+            }
+            match reported.get(param) {
+                Some(old) if should_alert(param, old, &value, Some(float_threshold)) => {
+                    println!("[{}] {}", timestamp(), listen_line(param, old, &value));
+                }
+                Some(_) => continue,
+                None => {}
+            }
+            reported.insert(param.clone(), value);
+        }
+
+        if sector {
+            let doa = match doa {
+                Some(doa) => doa,
+                None => device.read(&ParamKind::DOAANGLE)?,
+            };
+            if let Value::Int(angle) = doa {
+                let angle = u16::try_from(angle).unwrap_or(u16::MAX);
+                let new = DoaSector::with_hysteresis(angle, reported_sector);
+                match reported_sector {
+                    Some(old) if old != new => println!(
+                        "[{}] DOASECTOR: {} -> {} {}",
+                        timestamp(),
+                        old.label(),
+                        new.label(),
+                        new.icon()
+                    ),
+                    _ => {}
+                }
+                reported_sector = Some(new);
+            }
+        }
+        thread::sleep(interval);
+    }
+    Ok(())
+}
+
+fn listen_line(param: &ParamKind, old: &Value, new: &Value) -> String {
+    format!("{param:?}: {old} -> {new}")
+}
+
+/// The table of `list`, redrawn in place every `interval` until `running` is false. Rows whose value
+/// changed since the previous refresh are bold.
+pub fn run_list_watch(
+    device: &ReSpeakerDevice,
+    filter: Option<Access>,
+    order: Option<ParamSortOrder>,
+    interval: Duration,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let cached = || {
+        let params = device.params();
+        let params = params.lock().expect("Lock failed");
+        params.current_params.clone()
+    };
+
+    let mut out = stdout();
+    execute!(out, Hide, Clear(ClearType::All))?;
+    let mut previous: Option<HashMap<ParamKind, Value>> = None;
+
+    let result = (|| {
+        while running.load(Ordering::SeqCst) {
+            let table = device.list_sorted(filter, order)?;
+            let current = cached();
+            let changed = previous.as_ref().map_or_else(HashSet::new, |previous| {
+                current
+                    .iter()
+                    .filter(|(param, value)| previous.get(*param) != Some(*value))
+                    .map(|(param, _)| format!("{param:?}"))
+                    .collect()
+            });
+            previous = Some(current);
+
+            let mut screen = String::new();
+            writeln!(screen, "ReSpeaker parameters (Ctrl-C to quit)")?;
+            writeln!(screen)?;
+            screen.push_str(&highlight_rows(&table, &changed));
+
+            draw(&mut out, &screen)?;
+            thread::sleep(interval);
+        }
+        Ok(())
+    })();
+
+    execute!(out, Show)?;
+    result
+}
+
+/// Makes all lines of the table rows whose name is in `names` bold. Rows are the blocks between the
+/// `+---+` separator lines, the name is the first cell of their first line.
+#[must_use]
+pub fn highlight_rows<S: BuildHasher>(table: &str, names: &HashSet<String, S>) -> String {
+    build_string(|result| {
+        let mut bold = false;
+        let mut first_row_line = true;
+        for line in table.lines() {
+            if line.starts_with('+') {
+                bold = false;
+                first_row_line = true;
+                writeln!(result, "{line}")?;
+                continue;
+            }
+            if first_row_line {
+                let name = line.split('|').nth(1).unwrap_or_default().trim();
+                bold = names.contains(name);
+                first_row_line = false;
+            }
+            if bold {
+                writeln!(result, "{}", line.bold())?;
+            } else {
+                writeln!(result, "{line}")?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Formats one row of [`run_compare`]. DOA angles wrap around, so 350° and 10° are 20° apart.
+#[must_use]
+pub fn compare_line(param: &ParamKind, a: Option<&Value>, b: Option<&Value>) -> String {
+    let unit = param.def().unit.unwrap_or_default();
+    let format =
+        |value: Option<&Value>| value.map_or_else(|| "n/a".to_string(), |v| format!("{v}{unit}"));
+    let diff = match (a, b) {
+        (Some(Value::Int(a)), Some(Value::Int(b))) => {
+            let diff = a.abs_diff(*b);
+            let diff = if *param == ParamKind::DOAANGLE {
+                diff.min(360 - diff % 360)
+            } else {
+                diff
+            };
+            Some(Value::Int(diff))
+        }
+        (Some(Value::Float(a)), Some(Value::Float(b))) => Some(Value::Float((a - b).abs())),
+        _ => None,
+    };
+    format!(
+        "{param:?}: A={} B={} diff={}",
+        format(a),
+        format(b),
+        format(diff.as_ref())
+    )
+}
+
+fn draw(out: &mut Stdout, screen: &str) -> eyre::Result<()> {
+    queue!(out, MoveTo(0, 0))?;
+    for line in screen.lines() {
+        queue!(
+            out,
+            Print(line),
+            Clear(ClearType::UntilNewLine),
+            Print("\r\n")
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// 12 sectors of 30°, the sector containing `angle` is filled.
+fn compass(angle: usize) -> String {
+    let active = (angle % 360) * COMPASS_SECTORS / 360;
+    (0..COMPASS_SECTORS)
+        .map(|sector| if sector == active { '█' } else { '░' })
+        .collect()
+}
+
+fn level_bar(level: f32) -> String {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let filled =
+        ((level.clamp(0.0, 1.0) * VAD_BAR_WIDTH as f32).round() as usize).min(VAD_BAR_WIDTH);
+    format!(
+        "[{}{}]",
+        "█".repeat(filled),
+        " ".repeat(VAD_BAR_WIDTH - filled)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use std::collections::HashSet;
+
+    use super::{compare_line, highlight_rows, listen_line};
+    use crate::params::{ParamKind, Value};
+
+    #[rstest]
+    #[case(
+        ParamKind::DOAANGLE,
+        Value::Int(42),
+        Value::Int(38),
+        "DOAANGLE: A=42° B=38° diff=4°"
+    )]
+    #[case(
+        ParamKind::DOAANGLE,
+        Value::Int(350),
+        Value::Int(10),
+        "DOAANGLE: A=350° B=10° diff=20°"
+    )]
+    #[case(
+        ParamKind::VOICEACTIVITY,
+        Value::Int(0),
+        Value::Int(1),
+        "VOICEACTIVITY: A=0 B=1 diff=1"
+    )]
+    #[case(
+        ParamKind::RT60,
+        Value::Float(0.5),
+        Value::Float(0.25),
+        "RT60: A=0.5s B=0.25s diff=0.25s"
+    )]
+    fn compare(
+        #[case] param: ParamKind,
+        #[case] a: Value,
+        #[case] b: Value,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(compare_line(&param, Some(&a), Some(&b)), expected);
+    }
+
+    #[test]
+    fn highlight_changed_rows() {
+        let table = "\
++------+-------+
+| name | value |
++------+-------+
+| A    | 0     |
+|      | 1     |
++------+-------+
+| B    | 2     |
++------+-------+
+";
+        let highlighted = highlight_rows(table, &HashSet::from(["A".to_string()]));
+
+        let lines = highlighted.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), table.lines().count());
+        assert_eq!(lines[1], "| name | value |");
+        assert_eq!(lines[3], "\u{1b}[1m| A    | 0     |\u{1b}[0m");
+        assert_eq!(lines[4], "\u{1b}[1m|      | 1     |\u{1b}[0m");
+        assert_eq!(lines[6], "| B    | 2     |");
+    }
+
+    #[rstest]
+    #[case(
+        ParamKind::VOICEACTIVITY,
+        Value::Int(0),
+        Value::Int(1),
+        "VOICEACTIVITY: 0 -> 1"
+    )]
+    #[case(
+        ParamKind::RT60,
+        Value::Float(0.5),
+        Value::Float(0.25),
+        "RT60: 0.5 -> 0.25"
+    )]
+    fn listen(
+        #[case] param: ParamKind,
+        #[case] old: Value,
+        #[case] new: Value,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(listen_line(&param, &old, &new), expected);
+    }
+
+    #[test]
+    fn compare_unsupported() {
+        assert_eq!(
+            compare_line(&ParamKind::DOAANGLE, Some(&Value::Int(1)), None),
+            "DOAANGLE: A=1° B=n/a diff=n/a"
+        );
+    }
+}
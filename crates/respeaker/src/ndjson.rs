@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Lines, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use eyre::{bail, Context};
+
+use crate::params::{ParamKind, Value};
+
+const TIMESTAMP_BEFORE: &str = "timestamp_before_read";
+const TIMESTAMP_AFTER: &str = "timestamp_after_read";
+
+/// Writes recordings as newline-delimited JSON, one object per row with the timestamps and all values.
+/// A path of `-` writes to stdout instead of a file.
+pub struct NdjsonWriter {
+    writer: Box<dyn Write>,
+}
+
+impl NdjsonWriter {
+    pub fn new(file_path: &Path) -> eyre::Result<Self> {
+        let writer: Box<dyn Write> = if file_path.as_os_str() == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(file_path)?)
+        };
+        Ok(Self { writer })
+    }
+
+    pub fn write_row(
+        &mut self,
+        timestamp_before: &str,
+        timestamp_after: &str,
+        values: &HashMap<ParamKind, Value>,
+    ) -> eyre::Result<()> {
+        let mut row = serde_json::Map::new();
+        row.insert(TIMESTAMP_BEFORE.into(), timestamp_before.into());
+        row.insert(TIMESTAMP_AFTER.into(), timestamp_after.into());
+        for param in ParamKind::sorted() {
+            if let Some(value) = values.get(&param) {
+                row.insert(format!("{param:?}"), value.to_json());
+            }
+        }
+
+        writeln!(self.writer, "{}", serde_json::Value::Object(row))?;
+        // Rows should show up immediately when piped
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads recordings written by [`NdjsonWriter`]. Empty lines are skipped, the timestamps are left out.
+pub struct NdjsonReader {
+    lines: Lines<BufReader<File>>,
+}
+
+impl NdjsonReader {
+    pub fn new(file_path: &Path) -> eyre::Result<Self> {
+        let file =
+            File::open(file_path).with_context(|| format!("Could not open {file_path:?}"))?;
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+        })
+    }
+}
+
+impl Iterator for NdjsonReader {
+    type Item = eyre::Result<HashMap<ParamKind, Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if !line.trim().is_empty() {
+                return Some(parse_line(&line));
+            }
+        }
+    }
+}
+
+fn parse_line(line: &str) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let serde_json::Value::Object(map) = serde_json::from_str(line)? else {
+        bail!("Expected a JSON object but got {line}");
+    };
+    let mut values = HashMap::new();
+    for (key, json) in map {
+        if key == TIMESTAMP_BEFORE || key == TIMESTAMP_AFTER {
+            continue;
+        }
+        let param = ParamKind::from_str(&key, false)
+            .map_err(|e| eyre::eyre!("Unknown parameter {key}: {e}"))?;
+        let value = Value::from_json(&param, &json)
+            .ok_or_else(|| eyre::eyre!("Invalid value {json} for parameter {key}"))?;
+        values.insert(param, value);
+    }
+    Ok(values)
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use eyre::{eyre, Context};
+use tracing::info;
+
+use crate::{
+    build_string,
+    params::{ParamKind, ParamType, Value},
+    respeaker_device::{decode_response, ReSpeakerDevice},
+};
+
+/// Direction of a control transfer, seen from the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One line of the Linux usbmon text interface, see
+/// <https://www.kernel.org/doc/Documentation/usb/usbmon.txt>.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsbmonEvent {
+    /// Submission of a control transfer, including the setup packet.
+    Submit {
+        tag: String,
+        direction: Direction,
+        device: u8,
+        request_type: u8,
+        value: u16,
+        index: u16,
+        data: Vec<u8>,
+    },
+    /// Completion of a transfer with the data returned by the device (IN) or nothing (OUT).
+    Complete {
+        tag: String,
+        device: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// Parses a control transfer line of the usbmon text interface. Returns `None` for other lines.
+#[must_use]
+pub fn parse_usbmon_line(line: &str) -> Option<UsbmonEvent> {
+    let mut fields = line.split_whitespace();
+    let tag = fields.next()?.to_string();
+    let _timestamp = fields.next()?;
+    let event = fields.next()?;
+    // e.g. "Ci:1:002:0" = control in, bus 1, device 2, endpoint 0
+    let mut address = fields.next()?.split(':');
+    let direction = match address.next()? {
+        "Ci" => Direction::In,
+        "Co" => Direction::Out,
+        _ => return None,
+    };
+    let _bus = address.next()?;
+    let device = address.next()?.parse().ok()?;
+
+    let rest = fields.collect::<Vec<_>>();
+    match event {
+        "S" => {
+            let ["s", request_type, _request, value, index, _length, rest @ ..] = rest.as_slice()
+            else {
+                return None;
+            };
+            Some(UsbmonEvent::Submit {
+                tag,
+                direction,
+                device,
+                request_type: u8::from_str_radix(request_type, 16).ok()?,
+                value: u16::from_str_radix(value, 16).ok()?,
+                index: u16::from_str_radix(index, 16).ok()?,
+                data: parse_data(rest),
+            })
+        }
+        "C" => Some(UsbmonEvent::Complete {
+            tag,
+            device,
+            data: parse_data(rest.get(2..).unwrap_or_default()),
+        }),
+        _ => None,
+    }
+}
+
+/// Data words follow a `=`, e.g. `= 0a000000 00000000`, each word holding up to 4 bytes in bus order.
+fn parse_data(fields: &[&str]) -> Vec<u8> {
+    let Some(start) = fields.iter().position(|f| *f == "=") else {
+        return vec![];
+    };
+    fields[start + 1..]
+        .iter()
+        .flat_map(|word| {
+            (0..word.len() / 2)
+                .filter_map(move |i| u8::from_str_radix(&word[i * 2..i * 2 + 2], 16).ok())
+        })
+        .collect()
+}
+
+/// Formats a transfer like `[IN] cmd=0xC7 id=21 -> bytes=[0A 00 00 00 00 00 00 00] = DOAANGLE: 10°`.
+///
+/// For IN transfers `data` is the response, for OUT transfers the 12 byte write payload.
+#[must_use]
+pub fn format_transfer(direction: Direction, value: u16, index: u16, data: &[u8]) -> String {
+    let bytes = data
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let (cmd, decoded) = match direction {
+        Direction::In => {
+            let cmd = value & !0xC0;
+            let decoded = ParamKind::from_index(index, cmd).and_then(|param| {
+                let response = data.get(..8)?.try_into().ok()?;
+                let value = decode_response(&param.def().param_type, response);
+                Some((param, value))
+            });
+            (value, decoded)
+        }
+        Direction::Out => {
+            let word =
+                |i: usize| -> Option<[u8; 4]> { data.get(i * 4..i * 4 + 4)?.try_into().ok() };
+            let cmd = word(0).map_or(0, |w| u16::try_from(i32::from_le_bytes(w)).unwrap_or(0));
+            let decoded = ParamKind::from_index(index, cmd).and_then(|param| {
+                let value = word(1)?;
+                let value = match param.def().param_type {
+                    ParamType::FloatRange { .. } => Value::Float(f32::from_le_bytes(value)),
+                    #[allow(clippy::cast_sign_loss)]
+                    _ => Value::Int(i32::from_le_bytes(value) as usize),
+                };
+                Some((param, value))
+            });
+            (cmd, decoded)
+        }
+    };
+
+    let direction = match direction {
+        Direction::In => "IN",
+        Direction::Out => "OUT",
+    };
+    build_string(|line| {
+        write!(
+            line,
+            "[{direction}] cmd=0x{cmd:02X} id={index} -> bytes=[{bytes}]"
+        )?;
+        if let Some((param, value)) = decoded {
+            let unit = param.def().unit.unwrap_or_default();
+            write!(line, " = {param:?}: {value}{unit}")?;
+        }
+        Ok(())
+    })
+}
+
+/// Prints all vendor control transfers of `device` until `running` is false.
+///
+/// `running` is checked after each usbmon line. Needs the `usbmon` kernel module and read access to `/sys/kernel/debug/usb/usbmon` (usually root).
+pub fn packet_dump(device: &ReSpeakerDevice, running: &Arc<AtomicBool>) -> eyre::Result<()> {
+    let info = device.device_info();
+    let path = PathBuf::from(format!("/sys/kernel/debug/usb/usbmon/{}u", info.bus));
+    let file = File::open(&path).with_context(|| {
+        format!("Could not open {path:?}. Is usbmon loaded (modprobe usbmon) and are you root?")
+    })?;
+    info!(
+        "Capturing control transfers of device {:03} on bus {:03}. Press Ctrl-C to stop.",
+        info.address, info.bus
+    );
+
+    // IN transfers are only complete once the device answered
+    let mut pending = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        let line = line.map_err(|e| eyre!("Could not read from {path:?}: {e}"))?;
+        match parse_usbmon_line(&line) {
+            Some(UsbmonEvent::Submit {
+                tag,
+                direction,
+                device,
+                request_type,
+                value,
+                index,
+                data,
+            }) if device == info.address && request_type & 0x60 == 0x40 => match direction {
+                Direction::Out => println!("{}", format_transfer(direction, value, index, &data)),
+                Direction::In => {
+                    pending.insert(tag, (value, index));
+                }
+            },
+            Some(UsbmonEvent::Complete { tag, device, data }) if device == info.address => {
+                if let Some((value, index)) = pending.remove(&tag) {
+                    println!("{}", format_transfer(Direction::In, value, index, &data));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
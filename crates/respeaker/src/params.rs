@@ -1,14 +1,26 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::{Display, Write as _},
+    ops::{Add, Mul, Sub},
+    sync::LazyLock,
+    time::Instant,
+};
 
 use clap::ValueEnum;
-use eyre::Context;
+use eyre::{bail, Context};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
+use crate::build_string;
+
 #[allow(clippy::upper_case_acronyms)] // ReSpeaker API uses UPPERCASE
 #[allow(non_camel_case_types)] // ReSpeaker API uses UPPERCASE
+/// All parameters of the device. Future firmware versions may add parameters, so the enum is
+/// `#[non_exhaustive]`.
 #[derive(Clone, Debug, ValueEnum, EnumIter, Hash, PartialEq, Eq)]
 #[clap(rename_all = "verbatim")]
+#[non_exhaustive]
 pub enum ParamKind {
     AECFREEZEONOFF,
     AECNORM,
@@ -53,51 +65,216 @@ pub enum ParamKind {
 }
 
 impl ParamKind {
+    #[must_use]
     pub const fn def(&self) -> ParamDef {
         match self {
-            Self::AECFREEZEONOFF => int_discrete(18, 7, Access::ReadWrite, "Adaptive Echo Canceler updates inhibit.", &[ "0 = Adaptation enabled", "1 = Freeze adaptation, filter only"]),
-            Self::AECNORM => float_range(18, 19, 16., 0.25, Access::ReadWrite, "Limit on norm of AEC filter coefficients"),
-            Self::AECPATHCHANGE => int_discrete(18, 25,  Access::ReadOnly, "AEC Path Change Detection.", &[ "0 = false (no path change detected)", "1 = true (path change detected)"]),
-            Self::RT60 => float_range(18, 26, 0.9, 0.25, Access::ReadOnly, "Current RT60 estimate in seconds"),
+            Self::AECFREEZEONOFF => int_discrete(18, 7, Access::ReadWrite, "Adaptive Echo Canceler updates inhibit.", &[ "0 = Adaptation enabled", "1 = Freeze adaptation, filter only"]).related(&[Self::AECNORM, Self::AECPATHCHANGE, Self::AECSILENCELEVEL, Self::AECSILENCEMODE]),
+            Self::AECNORM => float_range(18, 19, 16., 0.25, Access::ReadWrite, "Limit on norm of AEC filter coefficients").related(&[Self::AECFREEZEONOFF, Self::AECPATHCHANGE]),
+            Self::AECPATHCHANGE => int_discrete(18, 25,  Access::ReadOnly, "AEC Path Change Detection.", &[ "0 = false (no path change detected)", "1 = true (path change detected)"]).related(&[Self::AECFREEZEONOFF, Self::AECNORM]),
+            Self::RT60 => float_range(18, 26, 0.9, 0.25, Access::ReadOnly, "Current RT60 estimate in seconds").related(&[Self::RT60ONOFF]).with_unit("s"),
             Self::HPFONOFF => int_discrete(18, 27, Access::ReadWrite, "High-pass Filter on microphone signals.", &["0 = OFF", "1 = ON - 70 Hz cut-off", "2 = ON - 125 Hz cut-off", "3 = ON - 180 Hz cut-off"]),
-            Self::RT60ONOFF => int_discrete(18, 28,  Access::ReadWrite, "RT60 Estimation for AES.", &["0 = OFF", "1 = ON"]),
-            Self::AECSILENCELEVEL => float_range(18, 30, 1., 1e-09, Access::ReadWrite, "Threshold for signal detection in AEC [-inf .. 0] dBov (Default: -80dBov = 10log10(1x10-8))"),
-            Self::AECSILENCEMODE => int_discrete(18, 31,  Access::ReadOnly, "AEC far-end silence detection status. ", &["0 = false (signal detected) ", "1 = true (silence detected)"]),
-            Self::AGCONOFF => int_discrete(19, 0,  Access::ReadWrite, "Automatic Gain Control. ", &[ "0 = OFF ", "1 = ON"]),
-            Self::AGCMAXGAIN => float_range(19, 1, 1000., 1., Access::ReadWrite, "Maximum AGC gain factor. [0 .. 60] dB (default 30dB = 20log10(31.6))"),
-            Self::AGCDESIREDLEVEL => float_range(19, 2, 0.99, 1e-08, Access::ReadWrite, "Target power level of the output signal. [-inf .. 0] dBov (default: -23dBov = 10log10(0.005))"),
-            Self::AGCGAIN => float_range(19, 3, 1000., 1., Access::ReadWrite, "Current AGC gain factor. [0 .. 60] dB (default: 0.0dB = 20log10(1.0))"),
-            Self::AGCTIME => float_range(19, 4, 1., 0.1, Access::ReadWrite, "Ramps-up / down time-constant in seconds."),
+            Self::RT60ONOFF => int_discrete(18, 28,  Access::ReadWrite, "RT60 Estimation for AES.", &["0 = OFF", "1 = ON"]).related(&[Self::RT60]),
+            Self::AECSILENCELEVEL => float_range(18, 30, 1., 1e-09, Access::ReadWrite, "Threshold for signal detection in AEC [-inf .. 0] dBov (Default: -80dBov = 10log10(1x10-8))").related(&[Self::AECSILENCEMODE]),
+            Self::AECSILENCEMODE => int_discrete(18, 31,  Access::ReadOnly, "AEC far-end silence detection status. ", &["0 = false (signal detected) ", "1 = true (silence detected)"]).related(&[Self::AECSILENCELEVEL]),
+            Self::AGCONOFF => int_discrete(19, 0,  Access::ReadWrite, "Automatic Gain Control. ", &[ "0 = OFF ", "1 = ON"]).related(&[Self::AGCMAXGAIN, Self::AGCDESIREDLEVEL, Self::AGCGAIN, Self::AGCTIME]),
+            Self::AGCMAXGAIN => float_range(19, 1, 1000., 1., Access::ReadWrite, "Maximum AGC gain factor. [0 .. 60] dB (default 30dB = 20log10(31.6))").related(&[Self::AGCONOFF, Self::AGCGAIN]),
+            Self::AGCDESIREDLEVEL => float_range(19, 2, 0.99, 1e-08, Access::ReadWrite, "Target power level of the output signal. [-inf .. 0] dBov (default: -23dBov = 10log10(0.005))").related(&[Self::AGCONOFF, Self::AGCGAIN]),
+            Self::AGCGAIN => float_range(19, 3, 1000., 1., Access::ReadWrite, "Current AGC gain factor. [0 .. 60] dB (default: 0.0dB = 20log10(1.0))").related(&[Self::AGCONOFF, Self::AGCMAXGAIN, Self::AGCDESIREDLEVEL]),
+            Self::AGCTIME => float_range(19, 4, 1., 0.1, Access::ReadWrite, "Ramps-up / down time-constant in seconds.").related(&[Self::AGCONOFF]).with_unit("s"),
             Self::CNIONOFF => int_discrete(19, 5,  Access::ReadWrite, "Comfort Noise Insertion.", &["0 = OFF", "1 = ON"]),
-            Self::FREEZEONOFF => int_discrete(19, 6,  Access::ReadWrite, "Adaptive beamformer updates.", &[ "0 = Adaptation enabled", "1 = Freeze adaptation, filter only"]),
-            Self::STATNOISEONOFF => int_discrete(19, 8,  Access::ReadWrite, "Stationary noise suppression.", &[ "0 = OFF", "1 = ON"]),
-            Self::GAMMA_NS => float_range(19, 9, 3., 0., Access::ReadWrite, "Over-subtraction factor of stationary noise. min .. max attenuation"),
-            Self::MIN_NS => float_range(19, 10, 1., 0., Access::ReadWrite, "Gain-floor for stationary noise suppression. [-inf .. 0] dB (default: -16dB = 20log10(0.15))"),
-            Self::NONSTATNOISEONOFF => int_discrete(19, 11,  Access::ReadWrite, "Non-stationary noise suppression.", &[ "0 = OFF", "1 = ON"]),
-            Self::GAMMA_NN => float_range(19, 12, 3., 0., Access::ReadWrite, "Over-subtraction factor of non- stationary noise. min .. max attenuation"),
-            Self::MIN_NN => float_range(19, 13, 1., 0., Access::ReadWrite, "Gain-floor for non-stationary noise suppression. [-inf .. 0] dB (default: -10dB = 20log10(0.3))"),
-            Self::ECHOONOFF => int_discrete(19, 14,  Access::ReadWrite, "Echo suppression.", &[ "0 = OFF", "1 = ON"]),
-            Self::GAMMA_E => float_range(19, 15, 3., 0., Access::ReadWrite, "Over-subtraction factor of echo (direct and early components). min .. max attenuation"),
-            Self::GAMMA_ETAIL => float_range(19, 16, 3., 0., Access::ReadWrite, "Over-subtraction factor of echo (tail components). min .. max attenuation"),
-            Self::GAMMA_ENL => float_range(19, 17, 5., 0., Access::ReadWrite, "Over-subtraction factor of non-linear echo. min .. max attenuation"),
-            Self::NLATTENONOFF => int_discrete(19, 18, Access::ReadWrite, "Non-Linear echo attenuation.", &[ "0 = OFF", "1 = ON"]),
-            Self::NLAEC_MODE => int_discrete(19, 20, Access::ReadWrite, "Non-Linear AEC training mode.", &[ "0 = OFF", "1 = ON - phase 1", "2 = ON - phase 2"]),
-            Self::SPEECHDETECTED => int_discrete(19, 22, Access::ReadOnly, "Speech detection status.", &["0 = false (no speech detected)", "1 = true (speech detected)"]),
-            Self::FSBUPDATED => int_discrete(19, 23, Access::ReadOnly, "FSB Update Decision.", &[ "0 = false (FSB was not updated)", "1 = true (FSB was updated)"]),
-            Self::FSBPATHCHANGE => int_discrete(19, 24, Access::ReadOnly, "FSB Path Change Detection.", &["0 = false (no path change detected)", "1 = true (path change detected)"]),
+            Self::FREEZEONOFF => int_discrete(19, 6,  Access::ReadWrite, "Adaptive beamformer updates.", &[ "0 = Adaptation enabled", "1 = Freeze adaptation, filter only"]).related(&[Self::FSBUPDATED, Self::FSBPATHCHANGE]),
+            Self::STATNOISEONOFF => int_discrete(19, 8,  Access::ReadWrite, "Stationary noise suppression.", &[ "0 = OFF", "1 = ON"]).related(&[Self::GAMMA_NS, Self::MIN_NS]),
+            Self::GAMMA_NS => float_range(19, 9, 3., 0., Access::ReadWrite, "Over-subtraction factor of stationary noise. min .. max attenuation").related(&[Self::STATNOISEONOFF, Self::MIN_NS]),
+            Self::MIN_NS => float_range(19, 10, 1., 0., Access::ReadWrite, "Gain-floor for stationary noise suppression. [-inf .. 0] dB (default: -16dB = 20log10(0.15))").related(&[Self::STATNOISEONOFF, Self::GAMMA_NS]),
+            Self::NONSTATNOISEONOFF => int_discrete(19, 11,  Access::ReadWrite, "Non-stationary noise suppression.", &[ "0 = OFF", "1 = ON"]).related(&[Self::GAMMA_NN, Self::MIN_NN]),
+            Self::GAMMA_NN => float_range(19, 12, 3., 0., Access::ReadWrite, "Over-subtraction factor of non- stationary noise. min .. max attenuation").related(&[Self::NONSTATNOISEONOFF, Self::MIN_NN]),
+            Self::MIN_NN => float_range(19, 13, 1., 0., Access::ReadWrite, "Gain-floor for non-stationary noise suppression. [-inf .. 0] dB (default: -10dB = 20log10(0.3))").related(&[Self::NONSTATNOISEONOFF, Self::GAMMA_NN]),
+            Self::ECHOONOFF => int_discrete(19, 14,  Access::ReadWrite, "Echo suppression.", &[ "0 = OFF", "1 = ON"]).related(&[Self::GAMMA_E, Self::GAMMA_ETAIL, Self::GAMMA_ENL]),
+            Self::GAMMA_E => float_range(19, 15, 3., 0., Access::ReadWrite, "Over-subtraction factor of echo (direct and early components). min .. max attenuation").related(&[Self::ECHOONOFF, Self::GAMMA_ETAIL, Self::GAMMA_ENL]),
+            Self::GAMMA_ETAIL => float_range(19, 16, 3., 0., Access::ReadWrite, "Over-subtraction factor of echo (tail components). min .. max attenuation").related(&[Self::ECHOONOFF, Self::GAMMA_E, Self::GAMMA_ENL]),
+            Self::GAMMA_ENL => float_range(19, 17, 5., 0., Access::ReadWrite, "Over-subtraction factor of non-linear echo. min .. max attenuation").related(&[Self::ECHOONOFF, Self::GAMMA_E, Self::GAMMA_ETAIL, Self::NLATTENONOFF]),
+            Self::NLATTENONOFF => int_discrete(19, 18, Access::ReadWrite, "Non-Linear echo attenuation.", &[ "0 = OFF", "1 = ON"]).related(&[Self::NLAEC_MODE, Self::GAMMA_ENL]),
+            Self::NLAEC_MODE => int_discrete(19, 20, Access::ReadWrite, "Non-Linear AEC training mode.", &[ "0 = OFF", "1 = ON - phase 1", "2 = ON - phase 2"]).related(&[Self::NLATTENONOFF]),
+            Self::SPEECHDETECTED => int_discrete(19, 22, Access::ReadOnly, "Speech detection status.", &["0 = false (no speech detected)", "1 = true (speech detected)"]).related(&[Self::VOICEACTIVITY]),
+            Self::FSBUPDATED => int_discrete(19, 23, Access::ReadOnly, "FSB Update Decision.", &[ "0 = false (FSB was not updated)", "1 = true (FSB was updated)"]).related(&[Self::FREEZEONOFF, Self::FSBPATHCHANGE]),
+            Self::FSBPATHCHANGE => int_discrete(19, 24, Access::ReadOnly, "FSB Path Change Detection.", &["0 = false (no path change detected)", "1 = true (path change detected)"]).related(&[Self::FREEZEONOFF, Self::FSBUPDATED]),
             Self::TRANSIENTONOFF => int_discrete(19, 29, Access::ReadWrite, "Transient echo suppression.", &["0 = OFF", "1 = ON"]),
-            Self::VOICEACTIVITY => int_discrete(19, 32, Access::ReadOnly, "VAD voice activity status.", &["0 = false (no voice activity)", "1 = true (voice activity)"]),
-            Self::STATNOISEONOFF_SR => int_discrete(19, 33, Access::ReadWrite, "Stationary noise suppression for ASR.", &[ "0 = OFF", "1 = ON"]),
-            Self::NONSTATNOISEONOFF_SR => int_discrete(19, 34, Access::ReadWrite, "Non-stationary noise suppression for ASR.", &["0 = OFF", "1 = ON"]),
-            Self::GAMMA_NS_SR => float_range(19, 35, 3., 0., Access::ReadWrite, "Over-subtraction factor of stationary noise for ASR. [0.0 .. 3.0] (default: 1.0)"),
-            Self::GAMMA_NN_SR => float_range(19, 36, 3., 0., Access::ReadWrite, "Over-subtraction factor of non-stationary noise for ASR. [0.0 .. 3.0] (default: 1.1)"),
-            Self::MIN_NS_SR => float_range(19, 37, 1., 0., Access::ReadWrite, "Gain-floor for stationary noise suppression for ASR. [-inf .. 0] dB (default: -16dB = 20log10(0.15))"),
-            Self::MIN_NN_SR => float_range(19, 38, 1., 0., Access::ReadWrite, "Gain-floor for non-stationary noise suppression for ASR. [-inf .. 0] dB (default: -10dB = 20log10(0.3))"),
-            Self::GAMMAVAD_SR => float_range(19, 39, 1000., 0., Access::ReadWrite, "Set the threshold for voice activity detection. [-inf .. 60] dB (default: 3.5dB 20log10(1.5))"),
-            Self::DOAANGLE => int_range(21, 0, 359, 0, Access::ReadOnly, "DOA angle. Current value. Orientation depends on build configuration.", &["[0 .. 359] Angle"])
+            Self::VOICEACTIVITY => int_discrete(19, 32, Access::ReadOnly, "VAD voice activity status.", &["0 = false (no voice activity)", "1 = true (voice activity)"]).related(&[Self::GAMMAVAD_SR, Self::SPEECHDETECTED]),
+            Self::STATNOISEONOFF_SR => int_discrete(19, 33, Access::ReadWrite, "Stationary noise suppression for ASR.", &[ "0 = OFF", "1 = ON"]).related(&[Self::GAMMA_NS_SR, Self::MIN_NS_SR]).since("v2.1"),
+            Self::NONSTATNOISEONOFF_SR => int_discrete(19, 34, Access::ReadWrite, "Non-stationary noise suppression for ASR.", &["0 = OFF", "1 = ON"]).related(&[Self::GAMMA_NN_SR, Self::MIN_NN_SR]).since("v2.1"),
+            Self::GAMMA_NS_SR => float_range(19, 35, 3., 0., Access::ReadWrite, "Over-subtraction factor of stationary noise for ASR. [0.0 .. 3.0] (default: 1.0)").related(&[Self::STATNOISEONOFF_SR, Self::MIN_NS_SR]).since("v2.1"),
+            Self::GAMMA_NN_SR => float_range(19, 36, 3., 0., Access::ReadWrite, "Over-subtraction factor of non-stationary noise for ASR. [0.0 .. 3.0] (default: 1.1)").related(&[Self::NONSTATNOISEONOFF_SR, Self::MIN_NN_SR]).since("v2.1"),
+            Self::MIN_NS_SR => float_range(19, 37, 1., 0., Access::ReadWrite, "Gain-floor for stationary noise suppression for ASR. [-inf .. 0] dB (default: -16dB = 20log10(0.15))").related(&[Self::STATNOISEONOFF_SR, Self::GAMMA_NS_SR]).since("v2.1"),
+            Self::MIN_NN_SR => float_range(19, 38, 1., 0., Access::ReadWrite, "Gain-floor for non-stationary noise suppression for ASR. [-inf .. 0] dB (default: -10dB = 20log10(0.3))").related(&[Self::NONSTATNOISEONOFF_SR, Self::GAMMA_NN_SR]).since("v2.1"),
+            Self::GAMMAVAD_SR => float_range(19, 39, 1000., 0., Access::ReadWrite, "Set the threshold for voice activity detection. [-inf .. 60] dB (default: 3.5dB 20log10(1.5))").related(&[Self::VOICEACTIVITY]).since("v2.1"),
+            Self::DOAANGLE => int_range(21, 0, 359, 0, Access::ReadOnly, "DOA angle. Current value. Orientation depends on build configuration.", &["[0 .. 359] Angle"]).with_unit("°")
+        }
+    }
+
+    /// Parameters of the processing path tuned for speech recognition (the `_SR` family).
+    #[must_use]
+    pub const fn is_related_to_asr(&self) -> bool {
+        matches!(
+            self,
+            Self::STATNOISEONOFF_SR
+                | Self::NONSTATNOISEONOFF_SR
+                | Self::GAMMA_NS_SR
+                | Self::GAMMA_NN_SR
+                | Self::MIN_NS_SR
+                | Self::MIN_NN_SR
+                | Self::GAMMAVAD_SR
+        )
+    }
+
+    /// Parameters which change continuously and are worth watching live.
+    #[must_use]
+    pub const fn is_real_time_monitor(&self) -> bool {
+        matches!(
+            self,
+            Self::DOAANGLE
+                | Self::VOICEACTIVITY
+                | Self::SPEECHDETECTED
+                | Self::AGCGAIN
+                | Self::RT60
+        )
+    }
+
+    /// Metric name for a Prometheus exporter, e.g. `respeaker_gamma_nn_sr`.
+    #[must_use]
+    pub fn as_prometheus_metric_name(&self) -> String {
+        format!("respeaker_{self:?}").to_lowercase()
+    }
+
+    /// `# HELP` text for [`Self::as_prometheus_metric_name`]: the description plus the unit, if any.
+    #[must_use]
+    pub fn as_prometheus_help(&self) -> String {
+        let def = self.def();
+        let help = def.unit.map_or_else(
+            || def.description.to_string(),
+            |unit| format!("{} [{unit}]", def.description),
+        );
+        // Backslashes and line feeds are the only characters which need escaping in HELP lines
+        help.replace('\\', "\\\\").replace('\n', "\\n")
+    }
+
+    /// The value in dB for factors which the datasheet also gives in dB, e.g. 31.6 -> 30 dB for
+    /// AGCMAXGAIN. Gains and gain floors are amplitude factors (`20 log10`), the AEC silence and AGC
+    /// target levels are powers (`10 log10`). `None` for all other parameters.
+    #[must_use]
+    pub fn to_db(&self, value: &Value) -> Option<f32> {
+        let Value::Float(factor) = value else {
+            return None;
+        };
+        let scale = match self {
+            Self::AGCGAIN
+            | Self::AGCMAXGAIN
+            | Self::GAMMAVAD_SR
+            | Self::MIN_NN
+            | Self::MIN_NN_SR
+            | Self::MIN_NS
+            | Self::MIN_NS_SR => 20.0,
+            Self::AECSILENCELEVEL | Self::AGCDESIREDLEVEL => 10.0,
+            _ => return None,
+        };
+        Some(scale * factor.log10())
+    }
+
+    #[must_use]
+    pub const fn category(&self) -> ParamCategory {
+        match self {
+            Self::AECFREEZEONOFF
+            | Self::AECNORM
+            | Self::AECPATHCHANGE
+            | Self::AECSILENCELEVEL
+            | Self::AECSILENCEMODE
+            | Self::ECHOONOFF
+            | Self::GAMMA_E
+            | Self::GAMMA_ENL
+            | Self::GAMMA_ETAIL
+            | Self::NLAEC_MODE
+            | Self::NLATTENONOFF
+            | Self::RT60
+            | Self::RT60ONOFF => ParamCategory::EchoCancellation,
+            Self::AGCDESIREDLEVEL
+            | Self::AGCGAIN
+            | Self::AGCMAXGAIN
+            | Self::AGCONOFF
+            | Self::AGCTIME => ParamCategory::GainControl,
+            Self::CNIONOFF
+            | Self::GAMMA_NN
+            | Self::GAMMA_NN_SR
+            | Self::GAMMA_NS
+            | Self::GAMMA_NS_SR
+            | Self::MIN_NN
+            | Self::MIN_NN_SR
+            | Self::MIN_NS
+            | Self::MIN_NS_SR
+            | Self::NONSTATNOISEONOFF
+            | Self::NONSTATNOISEONOFF_SR
+            | Self::STATNOISEONOFF
+            | Self::STATNOISEONOFF_SR
+            | Self::TRANSIENTONOFF => ParamCategory::NoiseSuppression,
+            Self::DOAANGLE | Self::FREEZEONOFF | Self::FSBPATHCHANGE | Self::FSBUPDATED => {
+                ParamCategory::Beamforming
+            }
+            Self::GAMMAVAD_SR | Self::SPEECHDETECTED | Self::VOICEACTIVITY => {
+                ParamCategory::VoiceActivity
+            }
+            Self::HPFONOFF => ParamCategory::Filter,
+        }
+    }
+
+    /// The definition of the parameter on the given device model, `None` if the model doesn't have it.
+    #[must_use]
+    pub const fn def_for_model(&self, model: DeviceModel) -> Option<ParamDef> {
+        match (model, self) {
+            // The linear array has no direction of arrival estimation
+            (DeviceModel::MicLinear4, Self::DOAANGLE) => None,
+            _ => Some(self.def()),
+        }
+    }
+
+    /// Looks up a parameter by the `(index, cmd)` pair used on the USB bus. The read flags (`0x80` and
+    /// `0x40`) have to be removed from `cmd` before.
+    #[must_use]
+    pub fn from_index(index: u16, cmd: u16) -> Option<Self> {
+        static LOOKUP: LazyLock<HashMap<(u16, u16), ParamKind>> = LazyLock::new(|| {
+            let mut lookup = HashMap::new();
+            for param in ParamKind::iter() {
+                let def = param.def();
+                lookup.entry((def.index, def.cmd)).or_insert(param);
+            }
+            lookup
+        });
+        LOOKUP.get(&(index, cmd)).cloned()
+    }
+
+    /// The `wValue` of a read request. A read sets `0x80` on top of the command id and ints
+    /// additionally set `0x40`, the parameter id is sent as `wIndex`.
+    #[must_use]
+    pub const fn read_usb_cmd(&self) -> u16 {
+        let def = self.def();
+        let mut cmd = 0x80 | def.cmd;
+        if def.param_type.is_int() {
+            cmd |= 0x40;
         }
+        cmd
     }
 
+    /// The command id at the start of a write payload. Writes send the plain command id followed by
+    /// the value and a type flag (`1` for ints), again with the parameter id as `wIndex`.
+    #[must_use]
+    pub const fn write_usb_cmd(&self) -> u16 {
+        self.def().cmd
+    }
+
+    /// Returns a [`WriteableParam`] for RW parameters, `None` for RO parameters.
+    #[must_use]
+    pub fn as_writeable(&self) -> Option<WriteableParam> {
+        (self.def().access == Access::ReadWrite).then(|| WriteableParam(self.clone()))
+    }
+
+    #[must_use]
     pub fn sorted() -> Vec<Self> {
         let mut params = Self::iter().collect::<Vec<_>>();
         params.sort_by_key(|p| {
@@ -117,18 +294,247 @@ impl ParamKind {
         params
     }
 
+    #[must_use]
+    pub fn sorted_by_name() -> Vec<Self> {
+        let mut params = Self::iter().collect::<Vec<_>>();
+        params.sort_by_cached_key(|p| format!("{p:?}"));
+        params
+    }
+
+    /// Grouped by [`ParamCategory`], alphabetical within a category.
+    #[must_use]
+    pub fn sorted_by_category() -> Vec<Self> {
+        let mut params = Self::sorted_by_name();
+        params.sort_by_key(Self::category);
+        params
+    }
+
+    /// RW parameters first, alphabetical within RW and RO.
+    #[must_use]
+    pub fn sorted_by_access() -> Vec<Self> {
+        let mut params = Self::sorted_by_name();
+        params.sort_by_key(|p| p.def().access == Access::ReadOnly);
+        params
+    }
+
+    /// Multi-line help page with everything known about the parameter, printed by `param-info`.
+    #[must_use]
+    pub fn to_documentation_string(&self) -> String {
+        let def = self.def();
+        build_string(|doc| {
+            writeln!(doc, "{self:?}")?;
+            writeln!(doc, "  {}", def.description)?;
+            writeln!(doc)?;
+            let field = |doc: &mut String, name: &str, value: &str| {
+                writeln!(doc, "  {:<11}{value}", format!("{name}:"))
+            };
+            let kind = match def.param_type {
+                ParamType::IntDiscete { .. } => "int (discrete values)",
+                ParamType::IntRange { .. } => "int",
+                ParamType::FloatRange { .. } => "float",
+            };
+            field(doc, "Type", kind)?;
+            field(
+                doc,
+                "Access",
+                match def.access {
+                    Access::ReadOnly => "read-only",
+                    Access::ReadWrite => "read-write",
+                },
+            )?;
+            field(doc, "Range", &format!("{} .. {}", def.min(), def.max()))?;
+            field(doc, "Unit", def.unit.unwrap_or("-"))?;
+            field(doc, "Default", def.default_hint().unwrap_or("-"))?;
+            field(doc, "Category", self.category().name())?;
+            let related = def
+                .related_params
+                .iter()
+                .map(|p| format!("{p:?}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            field(
+                doc,
+                "Related",
+                if related.is_empty() { "-" } else { &related },
+            )?;
+            field(
+                doc,
+                "Firmware",
+                &def.firmware_since
+                    .map_or_else(|| "all versions".to_string(), |v| format!("{v} or later")),
+            )?;
+            field(doc, "USB", &format!("index {}, cmd {}", def.index, def.cmd))?;
+            if !def.value_descriptions.is_empty() {
+                writeln!(doc)?;
+                writeln!(doc, "  Values:")?;
+                for description in def.value_descriptions {
+                    writeln!(doc, "    {description}")?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// By `(index, cmd)`, the order of the firmware's parameter table.
+    #[must_use]
+    pub fn sorted_by_firmware_id() -> Vec<Self> {
+        let mut params = Self::iter().collect::<Vec<_>>();
+        params.sort_by_key(|p| {
+            let def = p.def();
+            (def.index, def.cmd)
+        });
+        params
+    }
+
     pub fn parse_value(&self, string: &str) -> eyre::Result<Value> {
         Ok(match self.def().param_type {
             ParamType::IntDiscete { min: _, max: _ } | ParamType::IntRange { min: _, max: _ } => {
                 Value::Int(string.parse::<usize>().context("must be a usize")?)
             }
             ParamType::FloatRange { min: _, max: _ } => {
-                Value::Float(string.parse::<f32>().context("must be an f32")?)
+                let value = string.parse::<f32>().context("must be an f32")?;
+                if !value.is_finite() {
+                    bail!("must be a finite f32");
+                }
+                // -0 is not representable by the firmware's mantissa / exponent encoding
+                Value::Float(value + 0.0)
             }
         })
     }
 }
 
+/// Supported devices. All of them use the same USB protocol but have different parameter sets, see
+/// [`ParamKind::def_for_model`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceModel {
+    /// `ReSpeaker` Mic Array v2.0 (2886:0018)
+    MicArrayV2,
+    /// `ReSpeaker` 4-Mic Linear Array (2886:0019)
+    MicLinear4,
+}
+
+impl DeviceModel {
+    pub const VENDOR_ID: u16 = 0x2886;
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::MicArrayV2 => "ReSpeaker Mic Array v2.0",
+            Self::MicLinear4 => "ReSpeaker 4-Mic Linear Array",
+        }
+    }
+
+    #[must_use]
+    pub const fn product_id(self) -> u16 {
+        match self {
+            Self::MicArrayV2 => 0x0018,
+            Self::MicLinear4 => 0x0019,
+        }
+    }
+
+    #[must_use]
+    pub const fn from_product_id(product_id: u16) -> Option<Self> {
+        match product_id {
+            0x0018 => Some(Self::MicArrayV2),
+            0x0019 => Some(Self::MicLinear4),
+            _ => None,
+        }
+    }
+
+    /// Whether `param` exists on this model.
+    #[must_use]
+    pub const fn supports(self, param: &ParamKind) -> bool {
+        param.def_for_model(self).is_some()
+    }
+}
+
+/// Processing stage of the audio pipeline a parameter belongs to.
+#[derive(Clone, Copy, Debug, EnumIter, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ParamCategory {
+    EchoCancellation,
+    GainControl,
+    NoiseSuppression,
+    Beamforming,
+    VoiceActivity,
+    Filter,
+}
+
+impl ParamCategory {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::EchoCancellation => "Echo cancellation",
+            Self::GainControl => "Automatic gain control",
+            Self::NoiseSuppression => "Noise suppression",
+            Self::Beamforming => "Beamforming & DOA",
+            Self::VoiceActivity => "Voice activity",
+            Self::Filter => "Filter",
+        }
+    }
+
+    /// Short lowercase name for file names, e.g. `aec` for `aec.csv`.
+    #[must_use]
+    pub const fn file_stem(self) -> &'static str {
+        match self {
+            Self::EchoCancellation => "aec",
+            Self::GainControl => "agc",
+            Self::NoiseSuppression => "noise",
+            Self::Beamforming => "doa",
+            Self::VoiceActivity => "vad",
+            Self::Filter => "filter",
+        }
+    }
+
+    #[must_use]
+    pub fn params(self) -> Vec<ParamKind> {
+        ParamKind::iter().filter(|p| p.category() == self).collect()
+    }
+}
+
+/// Order in which parameters are listed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, EnumIter)]
+pub enum ParamSortOrder {
+    /// RW before RO, ints before floats, see [`ParamKind::sorted`].
+    #[default]
+    Default,
+    Name,
+    Category,
+    Access,
+}
+
+impl ParamSortOrder {
+    #[must_use]
+    pub fn sorted(self) -> Vec<ParamKind> {
+        match self {
+            Self::Default => ParamKind::sorted(),
+            Self::Name => ParamKind::sorted_by_name(),
+            Self::Category => ParamKind::sorted_by_category(),
+            Self::Access => ParamKind::sorted_by_access(),
+        }
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Default => "Access & type",
+            Self::Name => "Name",
+            Self::Category => "Category",
+            Self::Access => "Access",
+        }
+    }
+}
+
+/// A [`ParamKind`] which is known to be writeable. Can only be created with [`ParamKind::as_writeable`].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct WriteableParam(pub(crate) ParamKind);
+
+impl WriteableParam {
+    #[must_use]
+    pub const fn param(&self) -> &ParamKind {
+        &self.0
+    }
+}
+
 #[derive(Debug)]
 pub struct ParamDef {
     pub param_type: ParamType,
@@ -137,9 +543,79 @@ pub struct ParamDef {
     pub access: Access,
     pub description: &'static str,
     pub value_descriptions: &'static [&'static str],
+    /// Parameters which influence each other and should usually be tuned together.
+    pub related_params: &'static [ParamKind],
+    /// Physical unit of the value, `None` for flags and dimensionless factors.
+    pub unit: Option<&'static str>,
+    /// Firmware version which introduced the parameter, `None` if all versions support it.
+    pub firmware_since: Option<&'static str>,
 }
 
 impl ParamDef {
+    const fn related(self, related_params: &'static [ParamKind]) -> Self {
+        Self {
+            related_params,
+            ..self
+        }
+    }
+
+    const fn with_unit(self, unit: &'static str) -> Self {
+        Self {
+            unit: Some(unit),
+            ..self
+        }
+    }
+
+    const fn since(self, firmware_version: &'static str) -> Self {
+        Self {
+            firmware_since: Some(firmware_version),
+            ..self
+        }
+    }
+
+    /// The factory default as documented in the description, e.g. `30dB = 20log10(31.6)` for AGCMAXGAIN.
+    #[must_use]
+    pub fn default_hint(&self) -> Option<&'static str> {
+        let description = self.description;
+        // Some descriptions use "(Default: ...)"
+        let start = description.to_ascii_lowercase().find("(default")?;
+        // The hint may contain parentheses itself, e.g. `(default: -23dBov = 10log10(0.005))`
+        let mut depth = 0;
+        let end = description[start..].char_indices().find_map(|(i, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            (depth == 0).then_some(start + i)
+        })?;
+        Some(
+            description[start + "(default".len()..end]
+                .trim_start_matches(':')
+                .trim(),
+        )
+    }
+
+    /// The factory default parsed from [`Self::default_hint`]. Hints in dB use the linear value the
+    /// firmware expects, e.g. 31.6 for `30dB = 20log10(31.6)`. `None` if the description has no default.
+    #[must_use]
+    pub fn default_value(&self) -> Option<Value> {
+        let hint = self.default_hint()?;
+        let number = match hint.split_once("log10(") {
+            // Written like `1x10-8` in the datasheet
+            Some((_, linear)) => linear.trim_end_matches(')').replace("x10", "e"),
+            None => hint.to_string(),
+        };
+        let value = match self.param_type {
+            ParamType::IntDiscete { .. } | ParamType::IntRange { .. } => {
+                Value::Int(number.parse().ok()?)
+            }
+            ParamType::FloatRange { .. } => Value::Float(number.parse().ok()?),
+        };
+        Some(value.sanitize(self))
+    }
+
+    #[must_use]
     pub const fn min(&self) -> Value {
         match self.param_type {
             ParamType::IntDiscete { min, max: _ } | ParamType::IntRange { min, max: _ } => {
@@ -149,6 +625,7 @@ impl ParamDef {
         }
     }
 
+    #[must_use]
     pub const fn max(&self) -> Value {
         match self.param_type {
             ParamType::IntDiscete { min: _, max } | ParamType::IntRange { min: _, max } => {
@@ -157,8 +634,49 @@ impl ParamDef {
             ParamType::FloatRange { min: _, max } => Value::Float(max),
         }
     }
+
+    /// One row of a Markdown table with the columns of [`MARKDOWN_TABLE_HEADER`], e.g.
+    /// `| HPFONOFF | 1 | int | rw | 0..3 | High-pass Filter on microphone signals. | 0 = OFF, ... |`.
+    #[must_use]
+    pub fn to_markdown_table_row(&self, value: &Value) -> String {
+        let name = ParamKind::from_index(self.index, self.cmd)
+            .map(|param| format!("{param:?}"))
+            .unwrap_or_default();
+        let cells = [
+            name,
+            value.to_string(),
+            if self.param_type.is_int() {
+                "int"
+            } else {
+                "float"
+            }
+            .to_string(),
+            match self.access {
+                Access::ReadOnly => "ro",
+                Access::ReadWrite => "rw",
+            }
+            .to_string(),
+            format!("{}..{}", self.min(), self.max()),
+            self.description.trim().to_string(),
+            self.value_descriptions
+                .iter()
+                .map(|d| d.trim())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ];
+        let cells = cells
+            .iter()
+            // A pipe would end the cell
+            .map(|cell| cell.replace('|', "\\|"))
+            .collect::<Vec<_>>();
+        format!("| {} |", cells.join(" | "))
+    }
 }
 
+/// Header and delimiter row of a Markdown table of [`ParamDef::to_markdown_table_row`] rows.
+pub const MARKDOWN_TABLE_HEADER: &str = "| Name | Value | Type | Access | Range | Description | Values |\n\
+                                         |------|-------|------|--------|-------|-------------|--------|";
+
 #[derive(Debug, Clone)]
 pub enum ParamType {
     IntDiscete { min: usize, max: usize },
@@ -167,23 +685,208 @@ pub enum ParamType {
 }
 
 impl ParamType {
+    #[must_use]
     pub const fn is_int(&self) -> bool {
         !matches!(self, Self::FloatRange { min: _, max: _ })
     }
+
+    /// Number of distinct valid values, `None` for continuous (float) parameters. For discrete
+    /// parameters this is the number of value descriptions.
+    #[must_use]
+    pub const fn cardinality(&self) -> Option<usize> {
+        match self {
+            Self::IntDiscete { min, max } | Self::IntRange { min, max } => Some(*max - *min + 1),
+            Self::FloatRange { min: _, max: _ } => None,
+        }
+    }
+
+    /// On / off style parameters with exactly two values.
+    #[must_use]
+    pub const fn is_binary(&self) -> bool {
+        matches!(self.cardinality(), Some(2))
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum Access {
+    #[value(name = "ro")]
     ReadOnly,
+    #[value(name = "rw")]
     ReadWrite,
 }
 
+/// Relative difference of floats below which [`ParamState::iter_changed_since`] and the change callback
+/// of the device don't count a change.
+const CHANGE_RELATIVE_EPSILON: f32 = 1e-6;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Int(usize),
     Float(f32),
 }
 
+impl Value {
+    /// Clamps the value into the range of `def`. Values of the wrong type are returned unchanged.
+    #[must_use]
+    pub fn sanitize(&self, def: &ParamDef) -> Self {
+        match (self, &def.param_type) {
+            (
+                Self::Int(v),
+                ParamType::IntDiscete { min, max } | ParamType::IntRange { min, max },
+            ) => Self::Int(*v.clamp(min, max)),
+            (Self::Float(v), ParamType::FloatRange { min, max }) => {
+                Self::Float(v.clamp(*min, *max))
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Why the value doesn't fit `def`, `None` if it does. NaN is never in range.
+    fn validate(&self, def: &ParamDef) -> Option<ValidationReason> {
+        let in_range = match (self, &def.param_type) {
+            (
+                Self::Int(v),
+                ParamType::IntDiscete { min, max } | ParamType::IntRange { min, max },
+            ) => (min..=max).contains(&v),
+            (Self::Float(v), ParamType::FloatRange { min, max }) => (min..=max).contains(&v),
+            _ => {
+                return Some(ValidationReason::WrongType {
+                    expected: if def.param_type.is_int() {
+                        "int"
+                    } else {
+                        "float"
+                    },
+                    got: match self {
+                        Self::Int(_) => "int",
+                        Self::Float(_) => "float",
+                    },
+                })
+            }
+        };
+        (!in_range).then(|| ValidationReason::OutOfRange {
+            min: def.min(),
+            max: def.max(),
+        })
+    }
+
+    /// Whether the values are equal, floats if they differ by at most `epsilon`. `epsilon` is in the unit
+    /// of the parameter value, e.g. a gain factor for AGCGAIN, not dB. Ints are compared exactly and an
+    /// int never equals a float.
+    #[must_use]
+    pub fn eq_approx(&self, other: &Self, epsilon: f32) -> bool {
+        match (self, other) {
+            (Self::Float(a), Self::Float(b)) => (a - b).abs() <= epsilon,
+            _ => self == other,
+        }
+    }
+
+    /// Whether the value differs from `old` by more than f32 rounding. The tolerance scales with the
+    /// value, so changes of tiny levels like AECSILENCELEVEL are still seen.
+    pub(crate) fn changed_from(&self, old: &Self) -> bool {
+        let epsilon = match self {
+            Self::Int(_) => 0.0,
+            Self::Float(v) => v.abs() * CHANGE_RELATIVE_EPSILON,
+        };
+        !self.eq_approx(old, epsilon)
+    }
+
+    /// Value for CSV files. Floats get the shortest representation which parses back to the same `f32`
+    /// with [`ParamKind::parse_value`], so tiny levels like AECSILENCELEVEL aren't rounded to 0.
+    #[must_use]
+    pub fn to_csv_string(&self) -> String {
+        match self {
+            Self::Int(v) => v.to_string(),
+            Self::Float(v) => format!("{v}"),
+        }
+    }
+
+    /// Like [`Self::to_csv_string`] with `precision` significant digits for floats, in scientific
+    /// notation (`4.5e-1` for 0.45 with 2 digits).
+    #[must_use]
+    pub fn to_csv_string_with_precision(&self, precision: usize) -> String {
+        match self {
+            Self::Int(v) => v.to_string(),
+            Self::Float(v) => format!("{v:.*e}", precision.saturating_sub(1)),
+        }
+    }
+}
+
+/// Values of the same type are ordered by their number. An int and a float are not comparable.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.partial_cmp(b),
+            (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+/// Same-type addition. `None` for an int and a float or if an int overflows.
+impl Add for Value {
+    type Output = Option<Self>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Self::Int(a), Self::Int(b)) => a.checked_add(b).map(Self::Int),
+            (Self::Float(a), Self::Float(b)) => Some(Self::Float(a + b)),
+            _ => None,
+        }
+    }
+}
+
+/// Same-type subtraction. `None` for an int and a float or if an int would become negative.
+impl Sub for Value {
+    type Output = Option<Self>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Self::Int(a), Self::Int(b)) => a.checked_sub(b).map(Self::Int),
+            (Self::Float(a), Self::Float(b)) => Some(Self::Float(a - b)),
+            _ => None,
+        }
+    }
+}
+
+/// Scales a float. `None` for ints, which can't represent the result.
+impl Mul<f32> for Value {
+    type Output = Option<Self>;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        match self {
+            Self::Int(_) => None,
+            Self::Float(v) => Some(Self::Float(v * rhs)),
+        }
+    }
+}
+
+impl Value {
+    /// Formats the value for humans: discrete values with their description (`1 (ON - 70 Hz cut-off)`),
+    /// floats with their unit (`0.45 s`).
+    #[must_use]
+    pub fn to_display_string(&self, def: &ParamDef) -> String {
+        match (self, &def.param_type) {
+            (Self::Int(i), ParamType::IntDiscete { .. }) => {
+                def.value_descriptions.get(*i).map_or_else(
+                    || i.to_string(),
+                    |description| {
+                        // Descriptions repeat the value, e.g. "1 = ON - 70 Hz cut-off"
+                        let description = description
+                            .split_once(" = ")
+                            .map_or(*description, |(_, description)| description)
+                            .trim();
+                        format!("{i} ({description})")
+                    },
+                )
+            }
+            (Self::Float(f), _) => def
+                .unit
+                .map_or_else(|| f.to_string(), |unit| format!("{f} {unit}")),
+            (Self::Int(i), _) => i.to_string(),
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -207,6 +910,9 @@ const fn int_discrete<const N: usize>(
         access,
         description,
         value_descriptions,
+        related_params: &[],
+        unit: None,
+        firmware_since: None,
     }
 }
 
@@ -226,6 +932,9 @@ const fn int_range(
         access,
         description,
         value_descriptions,
+        related_params: &[],
+        unit: None,
+        firmware_since: None,
     }
 }
 
@@ -244,10 +953,510 @@ const fn float_range(
         access,
         description,
         value_descriptions: &[],
+        related_params: &[],
+        unit: None,
+        firmware_since: None,
     }
 }
 
-#[derive(Debug, Clone)]
+const ENV_PREFIX: &str = "RESPEAKER_";
+
+/// A value which doesn't match the [`ParamDef`] of its parameter, see [`ParamState::validate_against_defs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub param: ParamKind,
+    pub value: Value,
+    pub reason: ValidationReason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationReason {
+    OutOfRange {
+        min: Value,
+        max: Value,
+    },
+    /// `"int"` or `"float"`.
+    WrongType {
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// The device model doesn't have the parameter, see [`ParamKind::def_for_model`].
+    UnknownParam(ParamKind),
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (param, value) = (&self.param, &self.value);
+        match &self.reason {
+            ValidationReason::OutOfRange { min, max } => {
+                write!(f, "{param:?} = {value} is not in range {min}..={max}")
+            }
+            ValidationReason::WrongType { expected, got } => {
+                write!(
+                    f,
+                    "{param:?} = {value} must be an {expected} but is a {got}"
+                )
+            }
+            ValidationReason::UnknownParam(param) => {
+                write!(f, "{param:?} is not available on this device model")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// A write performed in this process: when, which parameter, the old and the new value. The old value is
+/// `None` if it wasn't cached before the write.
+pub type AuditEntry = (Instant, ParamKind, Option<Value>, Value);
+
+#[derive(Debug, Clone, Default)]
 pub struct ParamState {
     pub current_params: HashMap<ParamKind, Value>,
+    /// Number of SPEECHDETECTED 0 -> 1 transitions since the last [`ParamState::reset_counters`].
+    pub speech_detection_count: u64,
+    /// Number of VOICEACTIVITY 0 -> 1 transitions since the last [`ParamState::reset_counters`].
+    pub voice_activity_count: u64,
+    audit_log: Vec<AuditEntry>,
+}
+
+impl ParamState {
+    /// Stores a freshly read value and counts speech / voice activity events.
+    pub fn update(&mut self, param: &ParamKind, value: &Value) {
+        let old = self.current_params.insert(param.clone(), value.clone());
+        if old == Some(Value::Int(0)) && value == &Value::Int(1) {
+            match param {
+                ParamKind::SPEECHDETECTED => self.speech_detection_count += 1,
+                ParamKind::VOICEACTIVITY => self.voice_activity_count += 1,
+                _ => {}
+            }
+        }
+    }
+
+    pub const fn reset_counters(&mut self) {
+        self.speech_detection_count = 0;
+        self.voice_activity_count = 0;
+    }
+
+    /// Appends a successful write to the audit log.
+    pub fn log_write(&mut self, param: &ParamKind, old: Option<Value>, new: Value) {
+        self.audit_log
+            .push((Instant::now(), param.clone(), old, new));
+    }
+
+    /// All writes since the state was created, oldest first. Only covers the current process.
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)] // Vec to slice deref is not const yet
+    pub fn audit_log(&self) -> &[AuditEntry] {
+        &self.audit_log
+    }
+
+    /// Copies the values of `other` into `self`. Existing values are only replaced if `overwrite` is set,
+    /// the event counters are left untouched.
+    pub fn merge_from(&mut self, other: &Self, overwrite: bool) {
+        for (param, value) in &other.current_params {
+            if overwrite || !self.current_params.contains_key(param) {
+                self.current_params.insert(param.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Parameters whose value differs from `snapshot` (or which `snapshot` doesn't have), in declaration
+    /// order. Floats which only differ by rounding are not reported, see [`Value::eq_approx`]. Unlike
+    /// [`crate::config::diff_snapshots`] nothing is cloned, but parameters which are only in `snapshot` are
+    /// not reported.
+    pub fn iter_changed_since<'a>(
+        &'a self,
+        snapshot: &'a Self,
+    ) -> impl Iterator<Item = (&'a ParamKind, &'a Value)> {
+        ParamKind::iter()
+            .filter_map(|param| self.current_params.get_key_value(&param))
+            .filter(|(param, value)| {
+                snapshot
+                    .current_params
+                    .get(param)
+                    .is_none_or(|old| value.changed_from(old))
+            })
+    }
+
+    /// Whether [`Self::iter_changed_since`] has any item.
+    #[must_use]
+    pub fn any_changed_since(&self, snapshot: &Self) -> bool {
+        self.iter_changed_since(snapshot).next().is_some()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &ParamKind> {
+        self.current_params.keys()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.current_params.values()
+    }
+
+    /// Returns the number of (RW, RO) parameters which have a value.
+    #[must_use]
+    pub fn count_by_access(&self) -> (usize, usize) {
+        let rw = self
+            .keys()
+            .filter(|p| p.def().access == Access::ReadWrite)
+            .count();
+        (rw, self.current_params.len() - rw)
+    }
+
+    /// Whether every [`ParamKind`] has a value, e.g. to detect a partially failed initial read.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        ParamKind::iter().all(|p| self.current_params.contains_key(&p))
+    }
+
+    /// Checks every value against the [`ParamDef`] of its parameter on the `ReSpeaker` Mic Array v2.0, in
+    /// declaration order. Values are not changed, see [`Value::sanitize`] for clamping them instead.
+    #[must_use]
+    pub fn validate_against_defs(&self) -> Vec<ValidationError> {
+        self.validate_for_model(DeviceModel::MicArrayV2)
+    }
+
+    /// Like [`Self::validate_against_defs`] for the parameter set of `model`.
+    #[must_use]
+    pub fn validate_for_model(&self, model: DeviceModel) -> Vec<ValidationError> {
+        ParamKind::iter()
+            .filter_map(|param| {
+                let value = self.current_params.get(&param)?;
+                let reason = match param.def_for_model(model) {
+                    None => ValidationReason::UnknownParam(param.clone()),
+                    Some(def) => value.validate(&def)?,
+                };
+                Some(ValidationError {
+                    param,
+                    value: value.clone(),
+                    reason,
+                })
+            })
+            .collect()
+    }
+
+    /// Fails with all errors of [`Self::validate_against_defs`].
+    pub(crate) fn ensure_valid(&self) -> eyre::Result<()> {
+        let errors = self.validate_against_defs();
+        if !errors.is_empty() {
+            bail!(
+                "Invalid values: {}",
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// `(RESPEAKER_<PARAM>, value)` pairs for all values, sorted by name, e.g. for Docker `--env`.
+    #[must_use]
+    pub fn to_env_vars(&self) -> Vec<(String, String)> {
+        ParamKind::sorted_by_name()
+            .into_iter()
+            .filter_map(|param| {
+                let value = self.current_params.get(&param)?;
+                Some((format!("{ENV_PREFIX}{param:?}"), value.to_string()))
+            })
+            .collect()
+    }
+
+    /// Prometheus text exposition format: one gauge per value in declaration order, see
+    /// [`ParamKind::as_prometheus_metric_name`], followed by the event counters.
+    #[must_use]
+    pub fn to_prometheus_text(&self) -> String {
+        build_string(|text| {
+            let mut metric = |name: &str, help: &str, kind: &str, value: &dyn Display| {
+                writeln!(text, "# HELP {name} {help}")?;
+                writeln!(text, "# TYPE {name} {kind}")?;
+                writeln!(text, "{name} {value}")
+            };
+            for param in ParamKind::iter() {
+                if let Some(value) = self.current_params.get(&param) {
+                    metric(
+                        &param.as_prometheus_metric_name(),
+                        &param.as_prometheus_help(),
+                        "gauge",
+                        value,
+                    )?;
+                }
+            }
+            metric(
+                "respeaker_speech_detection_events_total",
+                "SPEECHDETECTED 0 -> 1 transitions",
+                "counter",
+                &self.speech_detection_count,
+            )?;
+            metric(
+                "respeaker_voice_activity_events_total",
+                "VOICEACTIVITY 0 -> 1 transitions",
+                "counter",
+                &self.voice_activity_count,
+            )
+        })
+    }
+
+    /// Reads the `RESPEAKER_<PARAM>` variables of the environment, see [`Self::from_env_vars`].
+    pub fn from_env() -> eyre::Result<Self> {
+        Self::from_env_vars(std::env::vars())
+    }
+
+    /// Parses `(RESPEAKER_<PARAM>, value)` pairs. Other variables, also `RESPEAKER_` ones which don't
+    /// name a parameter like `RESPEAKER_MOCK`, are ignored. Event counters start at 0.
+    pub fn from_env_vars(vars: impl IntoIterator<Item = (String, String)>) -> eyre::Result<Self> {
+        let mut state = Self::default();
+        for (key, value) in vars {
+            let Some(param) = key
+                .strip_prefix(ENV_PREFIX)
+                .and_then(|name| ParamKind::from_str(name, false).ok())
+            else {
+                continue;
+            };
+            let value = param
+                .parse_value(&value)
+                .with_context(|| format!("Invalid value {value:?} in {key}"))?;
+            state.current_params.insert(param, value);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ParamState {
+    /// Serializes the current values as a JSON object, e.g. `{"DOAANGLE":42,"RT60":0.45}`.
+    #[must_use]
+    pub fn to_json_string(&self) -> String {
+        let map = self
+            .current_params
+            .iter()
+            .map(|(param, value)| (format!("{param:?}"), value.to_json()))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::Value::Object(map).to_string()
+    }
+
+    pub fn from_json_str(s: &str) -> eyre::Result<Self> {
+        Self::from_json_value(serde_json::from_str(s)?)
+    }
+
+    /// Parses an object of `PARAM: value` pairs. Whether a value is an int or a float is taken from the
+    /// [`ParamDef`] of the parameter. Event counters start at 0.
+    pub fn from_json_value(json: serde_json::Value) -> eyre::Result<Self> {
+        let serde_json::Value::Object(map) = json else {
+            bail!("Expected a JSON object but got {json}");
+        };
+        let mut state = Self::default();
+        for (key, value) in map {
+            let param = ParamKind::from_str(&key, false)
+                .map_err(|e| eyre::eyre!("Unknown parameter {key}: {e}"))?;
+            let value = Value::from_json(&param, &value)
+                .ok_or_else(|| eyre::eyre!("Invalid value {value} for parameter {key}"))?;
+            state.current_params.insert(param, value);
+        }
+        state.ensure_valid()?;
+        Ok(state)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Value {
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::Int(v) => serde_json::Value::from(*v),
+            // Going through the string avoids f32 -> f64 artifacts like 0.44999998807907104
+            Self::Float(v) => v
+                .to_string()
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        }
+    }
+
+    /// Whether the JSON number is an int or a float is taken from the [`ParamDef`] of `param`.
+    #[must_use]
+    pub fn from_json(param: &ParamKind, json: &serde_json::Value) -> Option<Self> {
+        if param.def().param_type.is_int() {
+            json.as_u64()
+                .and_then(|v| usize::try_from(v).ok())
+                .map(Self::Int)
+        } else {
+            #[allow(clippy::cast_possible_truncation)]
+            json.as_f64().map(|v| Self::Float(v as f32))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use rstest::rstest;
+
+    use super::{DeviceModel, ParamKind, ParamState, ValidationReason, Value};
+
+    #[rstest]
+    #[case(Value::Int(1), Value::Int(2), Some(Ordering::Less))]
+    #[case(Value::Int(2), Value::Int(2), Some(Ordering::Equal))]
+    #[case(Value::Float(2.5), Value::Float(0.5), Some(Ordering::Greater))]
+    #[case(Value::Float(f32::NAN), Value::Float(0.5), None)]
+    #[case(Value::Int(1), Value::Float(1.0), None)]
+    #[case(Value::Float(1.0), Value::Int(1), None)]
+    fn compare(#[case] a: Value, #[case] b: Value, #[case] expected: Option<Ordering>) {
+        assert_eq!(a.partial_cmp(&b), expected);
+    }
+
+    #[rstest]
+    #[case(Value::Int(1), Value::Int(2), Some(Value::Int(3)))]
+    #[case(Value::Float(0.5), Value::Float(0.25), Some(Value::Float(0.75)))]
+    #[case(Value::Int(usize::MAX), Value::Int(1), None)]
+    #[case(Value::Int(1), Value::Float(1.0), None)]
+    #[case(Value::Float(1.0), Value::Int(1), None)]
+    fn add(#[case] a: Value, #[case] b: Value, #[case] expected: Option<Value>) {
+        assert_eq!(a + b, expected);
+    }
+
+    #[rstest]
+    #[case(Value::Int(3), Value::Int(2), Some(Value::Int(1)))]
+    #[case(Value::Float(0.5), Value::Float(0.75), Some(Value::Float(-0.25)))]
+    #[case(Value::Int(1), Value::Int(2), None)]
+    #[case(Value::Int(1), Value::Float(1.0), None)]
+    #[case(Value::Float(1.0), Value::Int(1), None)]
+    fn sub(#[case] a: Value, #[case] b: Value, #[case] expected: Option<Value>) {
+        assert_eq!(a - b, expected);
+    }
+
+    #[rstest]
+    #[case(Value::Float(0.5), 4.0, Some(Value::Float(2.0)))]
+    #[case(Value::Float(0.5), -1.0, Some(Value::Float(-0.5)))]
+    #[case(Value::Int(2), 2.0, None)]
+    fn mul(#[case] a: Value, #[case] factor: f32, #[case] expected: Option<Value>) {
+        assert_eq!(a * factor, expected);
+    }
+
+    fn state(values: &[(ParamKind, Value)]) -> ParamState {
+        let mut state = ParamState::default();
+        state.current_params.extend(values.iter().cloned());
+        state
+    }
+
+    #[rstest]
+    #[case(Value::Int(1), Value::Int(1), 0.5, true)]
+    #[case(Value::Int(1), Value::Int(2), 5.0, false)]
+    #[case(Value::Float(0.1), Value::Float(0.1 + 1e-7), 1e-6, true)]
+    #[case(Value::Float(0.1), Value::Float(0.2), 1e-6, false)]
+    #[case(Value::Float(1.0), Value::Int(1), 1.0, false)]
+    fn eq_approx(#[case] a: Value, #[case] b: Value, #[case] epsilon: f32, #[case] expected: bool) {
+        assert_eq!(a.eq_approx(&b, epsilon), expected);
+    }
+
+    #[rstest]
+    #[case::round_trip(Value::Float(0.900_000_04), Value::Float(0.9), false)]
+    #[case::tiny_level(Value::Float(2e-8), Value::Float(1e-8), true)]
+    #[case::int(Value::Int(1), Value::Int(0), true)]
+    fn changed_from(#[case] value: Value, #[case] old: Value, #[case] expected: bool) {
+        assert_eq!(value.changed_from(&old), expected);
+    }
+
+    #[test]
+    fn changed_since() {
+        let snapshot = state(&[
+            (ParamKind::AGCONOFF, Value::Int(1)),
+            (ParamKind::DOAANGLE, Value::Int(42)),
+        ]);
+        let current = state(&[
+            (ParamKind::AGCONOFF, Value::Int(1)),
+            (ParamKind::DOAANGLE, Value::Int(90)),
+            (ParamKind::RT60, Value::Float(0.45)),
+        ]);
+
+        assert_eq!(
+            current.iter_changed_since(&snapshot).collect::<Vec<_>>(),
+            vec![
+                (&ParamKind::DOAANGLE, &Value::Int(90)),
+                (&ParamKind::RT60, &Value::Float(0.45)),
+            ]
+        );
+        assert!(current.any_changed_since(&snapshot));
+    }
+
+    #[rstest]
+    #[case(&[], &[], false)]
+    #[case(&[(ParamKind::AGCONOFF, Value::Int(1))], &[(ParamKind::AGCONOFF, Value::Int(1))], false)]
+    #[case(&[(ParamKind::AGCONOFF, Value::Int(1))], &[(ParamKind::AGCONOFF, Value::Int(0))], true)]
+    #[case(&[(ParamKind::AGCONOFF, Value::Int(1))], &[], true)]
+    // Only in the snapshot
+    #[case(&[], &[(ParamKind::AGCONOFF, Value::Int(1))], false)]
+    #[case(&[(ParamKind::AGCTIME, Value::Float(0.9))], &[(ParamKind::AGCTIME, Value::Float(0.900_000_04))], false)]
+    #[case(&[(ParamKind::AECSILENCELEVEL, Value::Float(1e-8))], &[(ParamKind::AECSILENCELEVEL, Value::Float(2e-8))], true)]
+    fn any_changed_since(
+        #[case] current: &[(ParamKind, Value)],
+        #[case] snapshot: &[(ParamKind, Value)],
+        #[case] expected: bool,
+    ) {
+        assert_eq!(state(current).any_changed_since(&state(snapshot)), expected);
+    }
+
+    #[rstest]
+    #[case(ParamKind::AGCONOFF, Value::Int(1), None)]
+    #[case(
+        ParamKind::AGCONOFF,
+        Value::Int(2),
+        Some(ValidationReason::OutOfRange { min: Value::Int(0), max: Value::Int(1) })
+    )]
+    #[case(
+        ParamKind::AGCMAXGAIN,
+        Value::Float(1001.0),
+        Some(ValidationReason::OutOfRange { min: Value::Float(1.0), max: Value::Float(1000.0) })
+    )]
+    #[case(
+        ParamKind::AGCMAXGAIN,
+        Value::Float(f32::NAN),
+        Some(ValidationReason::OutOfRange { min: Value::Float(1.0), max: Value::Float(1000.0) })
+    )]
+    #[case(
+        ParamKind::DOAANGLE,
+        Value::Float(42.0),
+        Some(ValidationReason::WrongType { expected: "int", got: "float" })
+    )]
+    fn validate_against_defs(
+        #[case] param: ParamKind,
+        #[case] value: Value,
+        #[case] expected: Option<ValidationReason>,
+    ) {
+        let mut state = ParamState::default();
+        state.current_params.insert(param.clone(), value.clone());
+
+        let errors = state.validate_against_defs();
+
+        assert_eq!(
+            errors.first().map(|e| (&e.param, &e.reason)),
+            expected.as_ref().map(|reason| (&param, reason))
+        );
+        assert!(errors.len() <= 1);
+        // Only reported, not clamped. Compared as text because NaN != NaN
+        assert_eq!(state.current_params[&param].to_string(), value.to_string());
+    }
+
+    #[test]
+    fn validate_for_model_reports_unknown_params() {
+        let mut state = ParamState::default();
+        state
+            .current_params
+            .insert(ParamKind::DOAANGLE, Value::Int(42));
+
+        assert!(state.validate_against_defs().is_empty());
+        let errors = state.validate_for_model(DeviceModel::MicLinear4);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].reason,
+            ValidationReason::UnknownParam(ParamKind::DOAANGLE)
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "DOAANGLE is not available on this device model"
+        );
+    }
 }
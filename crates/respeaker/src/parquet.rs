@@ -0,0 +1,616 @@
+use std::{fs, path::Path};
+
+const MAGIC: &[u8; 4] = b"PAR1";
+const FORMAT_VERSION: i32 = 1;
+
+// Physical types
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const FLOAT: i32 = 4;
+
+const OPTIONAL: i32 = 1;
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+const UNCOMPRESSED: i32 = 0;
+const DATA_PAGE: i32 = 0;
+
+// Thrift compact protocol field types
+const T_BOOL_TRUE: u8 = 1;
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+/// A Parquet column. All columns are optional, `None` is stored as null.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParquetColumn {
+    /// Unix timestamps in nanoseconds, read as UTC timestamps by pandas and `DuckDB`.
+    TimestampNanos(Vec<Option<i64>>),
+    Int32(Vec<Option<i32>>),
+    Float(Vec<Option<f32>>),
+}
+
+impl ParquetColumn {
+    fn len(&self) -> usize {
+        match self {
+            Self::TimestampNanos(values) => values.len(),
+            Self::Int32(values) => values.len(),
+            Self::Float(values) => values.len(),
+        }
+    }
+
+    const fn physical_type(&self) -> i32 {
+        match self {
+            Self::TimestampNanos(_) => INT64,
+            Self::Int32(_) => INT32,
+            Self::Float(_) => FLOAT,
+        }
+    }
+
+    fn defined(&self) -> Vec<bool> {
+        match self {
+            Self::TimestampNanos(values) => values.iter().map(Option::is_some).collect(),
+            Self::Int32(values) => values.iter().map(Option::is_some).collect(),
+            Self::Float(values) => values.iter().map(Option::is_some).collect(),
+        }
+    }
+
+    /// PLAIN encoded non-null values.
+    fn plain_values(&self) -> Vec<u8> {
+        match self {
+            Self::TimestampNanos(values) => values
+                .iter()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+            Self::Int32(values) => values
+                .iter()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+            Self::Float(values) => values
+                .iter()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        }
+    }
+}
+
+/// Writes `columns` as a single row group to an uncompressed Parquet file, which pandas
+/// (`read_parquet`), Spark and `DuckDB` can read. `metadata` ends up in the key-value metadata of the
+/// file footer.
+///
+/// All columns must have the same length.
+pub fn write_parquet(
+    path: &Path,
+    columns: &[(String, ParquetColumn)],
+    metadata: &[(String, String)],
+) -> eyre::Result<()> {
+    let rows = columns.first().map_or(0, |(_, column)| column.len());
+    eyre::ensure!(
+        columns.iter().all(|(_, column)| column.len() == rows),
+        "Parquet columns must have the same length"
+    );
+
+    let mut bytes = MAGIC.to_vec();
+    let mut chunks = vec![];
+    for (name, column) in columns {
+        let offset = bytes.len();
+        let page = page(column)?;
+        bytes.extend(&page);
+        chunks.push(ChunkInfo {
+            name,
+            column,
+            offset: i64::try_from(offset)?,
+            size: i64::try_from(page.len())?,
+        });
+    }
+
+    let footer = file_metadata(columns, &chunks, i64::try_from(rows)?, metadata);
+    bytes.extend(&footer);
+    bytes.extend(u32::try_from(footer.len())?.to_le_bytes());
+    bytes.extend(MAGIC);
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+struct ChunkInfo<'a> {
+    name: &'a str,
+    column: &'a ParquetColumn,
+    offset: i64,
+    /// Page header and data
+    size: i64,
+}
+
+/// A `DATA_PAGE` (v1) with its header: definition levels followed by the values.
+fn page(column: &ParquetColumn) -> eyre::Result<Vec<u8>> {
+    // No repetition levels, the columns are not nested
+    let levels = definition_levels(&column.defined());
+    let mut data = u32::try_from(levels.len())?.to_le_bytes().to_vec();
+    data.extend(levels);
+    data.extend(column.plain_values());
+
+    let size = i32::try_from(data.len())?;
+    let mut out = Compact::default();
+    out.i32_field(1, DATA_PAGE);
+    out.i32_field(2, size);
+    out.i32_field(3, size);
+    out.struct_field(5, |out| {
+        out.i32_field(1, i32::try_from(column.len()).unwrap_or(i32::MAX));
+        out.i32_field(2, PLAIN);
+        out.i32_field(3, RLE);
+        out.i32_field(4, RLE);
+    });
+    out.stop();
+    out.bytes.extend(data);
+    Ok(out.bytes)
+}
+
+/// RLE runs of the definition levels (bit width 1): `1` for values, `0` for nulls.
+fn definition_levels(defined: &[bool]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut rest = defined;
+    while let Some(&level) = rest.first() {
+        let run = rest.iter().take_while(|&&d| d == level).count();
+        varint(&mut out, (run as u64) << 1);
+        out.push(u8::from(level));
+        rest = &rest[run..];
+    }
+    out
+}
+
+fn file_metadata(
+    columns: &[(String, ParquetColumn)],
+    chunks: &[ChunkInfo],
+    rows: i64,
+    metadata: &[(String, String)],
+) -> Vec<u8> {
+    let mut out = Compact::default();
+    out.i32_field(1, FORMAT_VERSION);
+    out.list_field(2, T_STRUCT, columns.len() + 1);
+    out.list_struct(|out| {
+        out.binary_field(4, b"schema");
+        out.i32_field(5, i32::try_from(columns.len()).unwrap_or(i32::MAX));
+    });
+    for (name, column) in columns {
+        out.list_struct(|out| {
+            out.i32_field(1, column.physical_type());
+            out.i32_field(3, OPTIONAL);
+            out.binary_field(4, name.as_bytes());
+            if matches!(column, ParquetColumn::TimestampNanos(_)) {
+                // LogicalType::TIMESTAMP(isAdjustedToUTC: true, unit: NANOS)
+                out.struct_field(10, |out| {
+                    out.struct_field(8, |out| {
+                        out.bool_true_field(1);
+                        out.struct_field(2, |out| out.struct_field(3, |_| {}));
+                    });
+                });
+            }
+        });
+    }
+    out.i64_field(3, rows);
+    out.list_field(4, T_STRUCT, 1);
+    out.list_struct(|out| {
+        out.list_field(1, T_STRUCT, chunks.len());
+        for chunk in chunks {
+            out.list_struct(|out| {
+                out.i64_field(2, chunk.offset);
+                out.struct_field(3, |out| column_metadata(out, chunk));
+            });
+        }
+        out.i64_field(2, chunks.iter().map(|c| c.size).sum());
+        out.i64_field(3, rows);
+    });
+    out.list_field(5, T_STRUCT, metadata.len());
+    for (key, value) in metadata {
+        out.list_struct(|out| {
+            out.binary_field(1, key.as_bytes());
+            out.binary_field(2, value.as_bytes());
+        });
+    }
+    out.binary_field(
+        6,
+        format!("respeaker-rs version {}", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    out.stop();
+    out.bytes
+}
+
+fn column_metadata(out: &mut Compact, chunk: &ChunkInfo) {
+    out.i32_field(1, chunk.column.physical_type());
+    out.list_field(2, T_I32, 2);
+    for encoding in [PLAIN, RLE] {
+        varint(&mut out.bytes, zigzag(encoding.into()));
+    }
+    out.list_field(3, T_BINARY, 1);
+    binary(&mut out.bytes, chunk.name.as_bytes());
+    out.i32_field(4, UNCOMPRESSED);
+    out.i64_field(5, i64::try_from(chunk.column.len()).unwrap_or(i64::MAX));
+    out.i64_field(6, chunk.size);
+    out.i64_field(7, chunk.size);
+    out.i64_field(9, chunk.offset);
+}
+
+/// Thrift compact protocol encoder, just enough for the Parquet footer and page headers.
+#[derive(Default)]
+struct Compact {
+    bytes: Vec<u8>,
+    /// Id of the last written field of the current struct, field ids are delta encoded
+    last_field: i16,
+}
+
+impl Compact {
+    fn field_header(&mut self, id: i16, field_type: u8) {
+        if let Ok(delta @ 1..=15) = u8::try_from(id - self.last_field) {
+            self.bytes.push((delta << 4) | field_type);
+        } else {
+            self.bytes.push(field_type);
+            varint(&mut self.bytes, zigzag(id.into()));
+        }
+        self.last_field = id;
+    }
+
+    fn i32_field(&mut self, id: i16, value: i32) {
+        self.field_header(id, T_I32);
+        varint(&mut self.bytes, zigzag(value.into()));
+    }
+
+    fn i64_field(&mut self, id: i16, value: i64) {
+        self.field_header(id, T_I64);
+        varint(&mut self.bytes, zigzag(value));
+    }
+
+    fn bool_true_field(&mut self, id: i16) {
+        // Booleans are stored in the field type
+        self.field_header(id, T_BOOL_TRUE);
+    }
+
+    fn binary_field(&mut self, id: i16, value: &[u8]) {
+        self.field_header(id, T_BINARY);
+        binary(&mut self.bytes, value);
+    }
+
+    fn list_field(&mut self, id: i16, element_type: u8, len: usize) {
+        self.field_header(id, T_LIST);
+        if let Ok(len @ 0..=14) = u8::try_from(len) {
+            self.bytes.push((len << 4) | element_type);
+        } else {
+            self.bytes.push(0xF0 | element_type);
+            varint(&mut self.bytes, len as u64);
+        }
+    }
+
+    fn struct_field(&mut self, id: i16, fields: impl FnOnce(&mut Self)) {
+        self.field_header(id, T_STRUCT);
+        self.list_struct(fields);
+    }
+
+    /// A struct without field header, e.g. a list element.
+    fn list_struct(&mut self, fields: impl FnOnce(&mut Self)) {
+        let last_field = std::mem::replace(&mut self.last_field, 0);
+        fields(self);
+        self.stop();
+        self.last_field = last_field;
+    }
+
+    fn stop(&mut self) {
+        self.bytes.push(0);
+    }
+}
+
+fn binary(out: &mut Vec<u8>, value: &[u8]) {
+    varint(out, value.len() as u64);
+    out.extend(value);
+}
+
+/// ULEB128
+#[allow(clippy::cast_possible_truncation)]
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[allow(clippy::cast_sign_loss)]
+const fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rstest::rstest;
+
+    use super::{
+        definition_levels, varint, write_parquet, zigzag, Compact, ParquetColumn, DATA_PAGE, FLOAT,
+        INT32, INT64, MAGIC, OPTIONAL, PLAIN, RLE, T_BINARY, T_BOOL_TRUE, T_I32, T_I64, T_LIST,
+        T_STRUCT,
+    };
+
+    /// A decoded Thrift compact protocol value.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Thrift {
+        Bool(bool),
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Thrift>),
+        Struct(BTreeMap<i16, Thrift>),
+    }
+
+    impl Thrift {
+        fn field(&self, id: i16) -> &Self {
+            match self {
+                Self::Struct(fields) => &fields[&id],
+                other => panic!("{other:?} is not a struct"),
+            }
+        }
+
+        fn int(&self) -> i64 {
+            match self {
+                Self::Int(value) => *value,
+                other => panic!("{other:?} is not an integer"),
+            }
+        }
+
+        fn str(&self) -> &str {
+            match self {
+                Self::Binary(value) => std::str::from_utf8(value).expect("UTF-8 string"),
+                other => panic!("{other:?} is not binary"),
+            }
+        }
+
+        fn size(&self) -> usize {
+            usize::try_from(self.int()).expect("Non-negative size")
+        }
+
+        fn list(&self) -> &[Self] {
+            match self {
+                Self::List(values) => values,
+                other => panic!("{other:?} is not a list"),
+            }
+        }
+    }
+
+    /// Thrift compact protocol decoder, counterpart of [`Compact`].
+    struct Decoder<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl Decoder<'_> {
+        fn byte(&mut self) -> u8 {
+            self.pos += 1;
+            self.bytes[self.pos - 1]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.byte();
+                value |= u64::from(byte & 0x7F) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+
+        fn len(&mut self) -> usize {
+            usize::try_from(self.varint()).expect("Length fits into usize")
+        }
+
+        #[allow(clippy::cast_possible_wrap)]
+        fn zigzag(&mut self) -> i64 {
+            let value = self.varint();
+            (value >> 1) as i64 ^ -((value & 1) as i64)
+        }
+
+        fn value(&mut self, value_type: u8) -> Thrift {
+            match value_type {
+                T_BOOL_TRUE => Thrift::Bool(true),
+                2 => Thrift::Bool(false),
+                T_I32 | T_I64 => Thrift::Int(self.zigzag()),
+                T_BINARY => {
+                    let len = self.len();
+                    self.pos += len;
+                    Thrift::Binary(self.bytes[self.pos - len..self.pos].to_vec())
+                }
+                T_LIST => {
+                    let header = self.byte();
+                    let len = match header >> 4 {
+                        0xF => self.len(),
+                        len => usize::from(len),
+                    };
+                    Thrift::List((0..len).map(|_| self.value(header & 0x0F)).collect())
+                }
+                T_STRUCT => {
+                    let mut fields = BTreeMap::new();
+                    let mut last_field = 0;
+                    loop {
+                        let header = self.byte();
+                        if header == 0 {
+                            break Thrift::Struct(fields);
+                        }
+                        let id = match header >> 4 {
+                            0 => i16::try_from(self.zigzag()).expect("Field id fits into i16"),
+                            delta => last_field + i16::from(delta),
+                        };
+                        fields.insert(id, self.value(header & 0x0F));
+                        last_field = id;
+                    }
+                }
+                other => panic!("Unexpected Thrift type {other}"),
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8], pos: usize) -> (Thrift, usize) {
+        let mut decoder = Decoder { bytes, pos };
+        let value = decoder.value(T_STRUCT);
+        (value, decoder.pos)
+    }
+
+    fn le_u32(bytes: &[u8]) -> usize {
+        let bytes = bytes.try_into().expect("4 bytes");
+        usize::try_from(u32::from_le_bytes(bytes)).expect("u32 fits into usize")
+    }
+
+    /// Expands the RLE runs of bit width 1 back into definition levels.
+    fn expand_levels(mut runs: &[u8]) -> Vec<bool> {
+        let mut levels = vec![];
+        while !runs.is_empty() {
+            let mut decoder = Decoder {
+                bytes: runs,
+                pos: 0,
+            };
+            let header = decoder.varint();
+            assert_eq!(header & 1, 0, "Only RLE runs are written");
+            let level = decoder.byte();
+            levels.extend(std::iter::repeat_n(
+                level == 1,
+                usize::try_from(header >> 1).expect("Run length fits into usize"),
+            ));
+            runs = &runs[decoder.pos..];
+        }
+        levels
+    }
+
+    #[rstest]
+    #[case(&[], &[])]
+    #[case(&[true, true, true], &[6, 1])]
+    #[case(&[false, true, true, false], &[2, 0, 4, 1, 2, 0])]
+    fn definition_level_runs(#[case] defined: &[bool], #[case] expected: &[u8]) {
+        assert_eq!(definition_levels(defined), expected);
+    }
+
+    #[rstest]
+    #[case(0, &[0])]
+    #[case(-1, &[1])]
+    #[case(1, &[2])]
+    #[case(300, &[0xD8, 0x04])]
+    fn zigzag_varint(#[case] value: i64, #[case] expected: &[u8]) {
+        let mut out = vec![];
+        varint(&mut out, zigzag(value));
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn field_ids_are_delta_encoded() {
+        let mut out = Compact::default();
+        out.i32_field(1, 1);
+        out.i32_field(3, 1);
+        out.i32_field(20, 1);
+        out.struct_field(21, |out| out.i32_field(1, 1));
+        out.i32_field(22, 1);
+
+        assert_eq!(
+            out.bytes,
+            [0x15, 2, 0x25, 2, T_I32, 40, 2, 0x1C, 0x15, 2, 0, 0x15, 2]
+        );
+    }
+
+    /// Checks the page of `chunk`: its header, the definition levels and the PLAIN `values`.
+    fn assert_page(bytes: &[u8], chunk: &Thrift, levels: &[bool], values: &[u8]) {
+        let meta = chunk.field(3);
+        assert_eq!(meta.field(5).int(), 3);
+        assert_eq!(chunk.field(2), meta.field(9));
+        let offset = meta.field(9).size();
+
+        let (header, data_start) = decode(bytes, offset);
+        assert_eq!(header.field(1).int(), i64::from(DATA_PAGE));
+        let data_page = header.field(5);
+        assert_eq!(data_page.field(1).int(), 3);
+        assert_eq!(data_page.field(2).int(), i64::from(PLAIN));
+        assert_eq!(data_page.field(3).int(), i64::from(RLE));
+        let data_end = data_start + header.field(2).size();
+        assert_eq!(data_end - offset, meta.field(6).size());
+
+        let data = &bytes[data_start..data_end];
+        let levels_end = 4 + le_u32(&data[..4]);
+        assert_eq!(expand_levels(&data[4..levels_end]), levels);
+        assert_eq!(&data[levels_end..], values);
+    }
+
+    #[test]
+    fn written_file_can_be_decoded() {
+        let dir = tempfile::tempdir().expect("Temporary directory");
+        let path = dir.path().join("recording.parquet");
+        let columns = [
+            (
+                "ts_before".to_string(),
+                ParquetColumn::TimestampNanos(vec![Some(1), Some(-2), Some(3_000_000_000)]),
+            ),
+            (
+                "AGCONOFF".to_string(),
+                ParquetColumn::Int32(vec![Some(1), None, Some(-7)]),
+            ),
+            (
+                "AGCGAIN".to_string(),
+                ParquetColumn::Float(vec![None, None, Some(0.5)]),
+            ),
+        ];
+        write_parquet(
+            &path,
+            &columns,
+            &[("serial".to_string(), "MOCK".to_string())],
+        )
+        .expect("Parquet file written");
+
+        let bytes = std::fs::read(path).expect("Parquet file readable");
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(&bytes[bytes.len() - 4..], MAGIC);
+        let footer_end = bytes.len() - 8;
+        let footer_start = footer_end - le_u32(&bytes[footer_end..footer_end + 4]);
+        let (metadata, end) = decode(&bytes, footer_start);
+        assert_eq!(end, footer_end);
+
+        assert_eq!(metadata.field(3).int(), 3);
+        let schema = metadata.field(2).list();
+        assert_eq!(schema[0].field(4).str(), "schema");
+        assert_eq!(schema[0].field(5).int(), 3);
+        for (element, (name, physical_type)) in schema[1..].iter().zip([
+            ("ts_before", INT64),
+            ("AGCONOFF", INT32),
+            ("AGCGAIN", FLOAT),
+        ]) {
+            assert_eq!(element.field(1).int(), i64::from(physical_type));
+            assert_eq!(element.field(3).int(), i64::from(OPTIONAL));
+            assert_eq!(element.field(4).str(), name);
+        }
+        // TIMESTAMP(isAdjustedToUTC: true, unit: NANOS)
+        let timestamp = schema[1].field(10).field(8);
+        assert_eq!(timestamp.field(1), &Thrift::Bool(true));
+        assert_eq!(
+            timestamp.field(2).field(3),
+            &Thrift::Struct(BTreeMap::new())
+        );
+        let key_value = &metadata.field(5).list()[0];
+        assert_eq!(
+            (key_value.field(1).str(), key_value.field(2).str()),
+            ("serial", "MOCK")
+        );
+
+        let row_group = &metadata.field(4).list()[0];
+        assert_eq!(row_group.field(3).int(), 3);
+        let chunks = row_group.field(1).list();
+        assert_eq!(chunks.len(), 3);
+        let timestamps: Vec<u8> = [1i64, -2, 3_000_000_000]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert_page(&bytes, &chunks[0], &[true, true, true], &timestamps);
+        let ints: Vec<u8> = [1i32, -7].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_page(&bytes, &chunks[1], &[true, false, true], &ints);
+        assert_page(
+            &bytes,
+            &chunks[2],
+            &[false, false, true],
+            &0.5f32.to_le_bytes(),
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    path::Path,
+    sync::{atomic::AtomicBool, Arc, Barrier, Mutex},
+    thread,
+};
+
+use eyre::{bail, eyre};
+
+use crate::{
+    csv::part_path,
+    params::{ParamKind, ParamState, Value},
+    recorder::{record_respeaker_parameters, RecordingOptions, RecordingStats},
+    respeaker_device::{list_devices, ReSpeakerDevice},
+};
+
+/// Several devices used together, e.g. two arrays facing opposite walls.
+///
+/// Operations run on all devices concurrently, one thread per device. Results are in device order, the
+/// `usize` is the position in the pool (which is the `--device-index` for [`Self::open_all`]).
+#[derive(Clone)]
+pub struct DevicePool {
+    devices: Vec<ReSpeakerDevice>,
+}
+
+impl DevicePool {
+    /// Opens every connected `ReSpeaker` device, each with its own [`ParamState`].
+    pub fn open_all() -> eyre::Result<Self> {
+        let devices = list_devices()?
+            .into_iter()
+            .map(|summary| {
+                ReSpeakerDevice::open(
+                    Some(summary.index),
+                    Arc::new(Mutex::new(ParamState::default())),
+                )
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        if devices.is_empty() {
+            bail!("No ReSpeaker devices found");
+        }
+        Ok(Self { devices })
+    }
+
+    /// A pool of already opened devices, e.g. mock devices.
+    #[must_use]
+    pub const fn from_devices(devices: Vec<ReSpeakerDevice>) -> Self {
+        Self { devices }
+    }
+
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)] // Vec to slice deref is not const yet
+    pub fn devices(&self) -> &[ReSpeakerDevice] {
+        &self.devices
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Reads `param` from all devices at the same time.
+    #[must_use]
+    pub fn read_all_devices(&self, param: &ParamKind) -> Vec<eyre::Result<(usize, Value)>> {
+        self.on_all_devices(|index, device| Ok((index, device.read(param)?)))
+    }
+
+    /// Writes `value` to all devices at the same time. Devices which fail don't stop the others, the error
+    /// lists all of them.
+    pub fn write_all_devices(&self, param: &ParamKind, value: &Value) -> eyre::Result<()> {
+        let errors = self
+            .on_all_devices(|_, device| device.write(param, value))
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, result)| result.err().map(|e| format!("device {index}: {e}")))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            bail!("Writing {param:?} failed on {}", errors.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Records one CSV file per device, `recording.csv` becomes `recording_device0.csv`,
+    /// `recording_device1.csv` and so on. The recording threads wait for each other before they start.
+    pub fn record_synchronized(
+        &self,
+        seconds_to_record: Option<f32>,
+        path: &Path,
+        running: &Arc<AtomicBool>,
+        options: &RecordingOptions,
+    ) -> eyre::Result<Vec<RecordingStats>> {
+        let barrier = Barrier::new(self.devices.len());
+        self.on_all_devices(|index, device| {
+            barrier.wait();
+            record_respeaker_parameters(
+                seconds_to_record,
+                Some(part_path(path, &format!("_device{index}"))),
+                device,
+                running,
+                options,
+            )
+        })
+        .into_iter()
+        .collect()
+    }
+
+    fn on_all_devices<T: Send>(
+        &self,
+        f: impl Fn(usize, &ReSpeakerDevice) -> eyre::Result<T> + Sync,
+    ) -> Vec<eyre::Result<T>> {
+        thread::scope(|scope| {
+            // Collected so all threads are spawned before the first join
+            #[allow(clippy::needless_collect)]
+            let handles = self
+                .devices
+                .iter()
+                .enumerate()
+                .map(|(index, device)| {
+                    let f = &f;
+                    scope.spawn(move || f(index, device))
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(eyre!("Device thread panicked")))
+                })
+                .collect()
+        })
+    }
+}
@@ -1,7 +1,9 @@
 use chrono::Local;
+use clap::ValueEnum;
 use std::{
+    collections::HashMap,
     f32, fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -10,59 +12,855 @@ use std::{
     time::{Duration, Instant},
 };
 
-use eyre::Ok;
-use tracing::info;
+use eyre::{bail, Ok};
+use indicatif::{ProgressBar, ProgressStyle};
+use strum::IntoEnumIterator;
+use tabled::{Table, Tabled};
+use tracing::{info, warn};
 
-use crate::{csv::CsvWriter, respeaker_device::ReSpeakerDevice};
+#[cfg(feature = "audio")]
+use crate::audio::AudioCapture;
+#[cfg(feature = "bincode")]
+use crate::binary::BinaryRowWriter;
+#[cfg(feature = "tokio")]
+use crate::csv::{header_bytes, row_bytes};
+#[cfg(feature = "serde")]
+use crate::ndjson::NdjsonWriter;
+use crate::{
+    csv::{AudioTimeline, CsvWriter, CsvWriterOptions, ParamColumnOrder, RecordingMetadata},
+    params::{Access, ParamCategory, ParamKind, Value},
+    respeaker_device::ReSpeakerDevice,
+};
+
+/// What to do when reading a parameter fails during a recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnError {
+    /// Stop the recording.
+    #[default]
+    Fail,
+    /// Leave the cell of the failed parameter empty in this row.
+    Skip,
+    /// Wait and retry once, then skip.
+    Retry,
+}
+
+/// File format of a recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RecordFormat {
+    #[default]
+    Csv,
+    /// Newline-delimited JSON, one object per row. Has no metadata header.
+    #[cfg(feature = "serde")]
+    #[value(alias = "ndjson")]
+    Jsonl,
+    /// Compact binary rows with checksums, see `BinaryRowWriter`. Convert with `export --format csv`.
+    #[cfg(feature = "bincode")]
+    Binary,
+}
+
+/// Files created in [`RecordingOptions::output_dir`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputDirFormat {
+    /// One `<timestamp>.<ext>` file with all parameters.
+    #[default]
+    Single,
+    /// One CSV file per [`ParamCategory`], e.g. `aec.csv`, with the same timestamps in all files. Existing
+    /// files are only continued with [`RecordingOptions::append`].
+    PerCategory,
+}
+
+impl RecordFormat {
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            #[cfg(feature = "serde")]
+            Self::Jsonl => "jsonl",
+            #[cfg(feature = "bincode")]
+            Self::Binary => "bin",
+        }
+    }
+}
+
+enum RowWriter {
+    Csv(Box<CsvWriter>),
+    /// One writer per category, see [`OutputDirFormat::PerCategory`].
+    PerCategory(Vec<CsvWriter>),
+    #[cfg(feature = "serde")]
+    Ndjson(NdjsonWriter),
+    #[cfg(feature = "bincode")]
+    Binary(BinaryRowWriter),
+}
+
+impl RowWriter {
+    fn write_row(
+        &mut self,
+        timestamp_before: &str,
+        timestamp_after: &str,
+        values: &HashMap<ParamKind, Value>,
+    ) -> eyre::Result<()> {
+        match self {
+            Self::Csv(writer) => writer.write_row(timestamp_before, timestamp_after, values),
+            Self::PerCategory(writers) => writers
+                .iter_mut()
+                .try_for_each(|writer| writer.write_row(timestamp_before, timestamp_after, values)),
+            #[cfg(feature = "serde")]
+            Self::Ndjson(writer) => writer.write_row(timestamp_before, timestamp_after, values),
+            #[cfg(feature = "bincode")]
+            Self::Binary(writer) => {
+                let timestamp = chrono::DateTime::parse_from_rfc3339(timestamp_after)?;
+                let timestamp = timestamp
+                    .timestamp_nanos_opt()
+                    .and_then(|ns| u64::try_from(ns).ok())
+                    .ok_or_else(|| eyre::eyre!("Timestamp {timestamp_after} is out of range"))?;
+                writer.write_row(timestamp, values)
+            }
+        }
+    }
+
+    /// Only CSV has comments, the other formats ignore them.
+    fn write_comment(&mut self, comment: &str) -> eyre::Result<()> {
+        match self {
+            Self::Csv(writer) => writer.write_comment(comment),
+            Self::PerCategory(writers) => writers
+                .iter_mut()
+                .try_for_each(|writer| writer.write_comment(comment)),
+            #[cfg(feature = "serde")]
+            Self::Ndjson(_) => Ok(()),
+            #[cfg(feature = "bincode")]
+            Self::Binary(_) => Ok(()),
+        }
+    }
+
+    /// Writes what the CSV writers still buffer, the other formats are unbuffered.
+    fn finish(self) -> eyre::Result<()> {
+        match self {
+            Self::Csv(writer) => writer.finish(),
+            Self::PerCategory(writers) => writers.into_iter().try_for_each(CsvWriter::finish),
+            #[cfg(feature = "serde")]
+            Self::Ndjson(_) => Ok(()),
+            #[cfg(feature = "bincode")]
+            Self::Binary(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordingStats {
+    pub rows: u64,
+    /// Failed reads, including failed retries.
+    pub error_count: u64,
+    /// Cells left empty because of failed reads.
+    pub skip_count: u64,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct RecordingOptions {
+    pub format: RecordFormat,
+    pub on_error: OnError,
+    pub retry_delay: Duration,
+    /// Gzip compress the CSV file, `.gz` is appended to the file name if missing. Not supported for JSONL.
+    pub compress: bool,
+    /// Re-read the RW parameters at this interval and write them as a `RW_REFRESH` row.
+    pub rw_refresh_interval: Option<Duration>,
+    /// Show a progress bar on stderr. Never shown when recording to stdout.
+    pub progress: bool,
+    /// Wait for this condition before writing the first row. The recording duration starts afterwards.
+    pub trigger: Option<TriggerCondition>,
+    /// Append to an existing CSV file instead of replacing it. No metadata rows are written.
+    pub append: bool,
+    /// Don't write the CSV header row, e.g. when appending to a file which already has one.
+    pub no_header: bool,
+    /// Parameters which are neither read nor written.
+    pub exclude: Vec<ParamKind>,
+    /// Order of the parameter columns in CSV recordings.
+    pub column_order: ParamColumnOrder,
+    /// Start once this file exists and stop once it is deleted, see [`parse_trigger_file`].
+    pub trigger_file: Option<PathBuf>,
+    /// Continue in a new CSV file once the current one has this many bytes, see [`CsvWriter::rotate`].
+    pub max_file_size: Option<u64>,
+    /// Add the audio sample of each row of a WAV file recorded at the same time. Only supported for CSV.
+    pub interleave_audio: Option<AudioTimeline>,
+    /// Directory for recordings without a path, `./recordings` if `None`. Created if missing.
+    pub output_dir: Option<PathBuf>,
+    pub output_dir_format: OutputDirFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TriggerDirection {
+    #[default]
+    Above,
+    Below,
+}
+
+/// Starts a recording once `param` is above or below `threshold`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerCondition {
+    pub param: ParamKind,
+    pub threshold: Value,
+    pub direction: TriggerDirection,
+}
+
+impl TriggerCondition {
+    #[must_use]
+    pub fn is_met(&self, value: &Value) -> bool {
+        match self.direction {
+            TriggerDirection::Above => value > &self.threshold,
+            TriggerDirection::Below => value < &self.threshold,
+        }
+    }
+}
+
+/// Value of `timestamp_before_read` in rows holding re-read RW parameters.
+pub const RW_REFRESH_MARKER: &str = "RW_REFRESH";
+
+const TRIGGER_FILE_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 pub fn record_respeaker_parameters(
     seconds_to_record: Option<f32>,
     csv_path: Option<PathBuf>,
     device: &ReSpeakerDevice,
     running: &Arc<AtomicBool>,
-) -> eyre::Result<()> {
-    let dir = PathBuf::from("./recordings");
-    if csv_path.is_none() && !dir.exists() {
-        fs::create_dir(dir)?;
+    options: &RecordingOptions,
+) -> eyre::Result<RecordingStats> {
+    let mut metadata = recording_metadata(device);
+    if let Some(audio) = &options.interleave_audio {
+        metadata.audio_started_at = Some(audio.started_at.format("%+").to_string());
+    }
+    record_with_metadata(
+        seconds_to_record,
+        csv_path,
+        device,
+        running,
+        options,
+        &metadata,
+    )
+}
+
+/// Async variant of [`record_respeaker_parameters`] which does not block a thread between rows.
+///
+/// The USB reads run on tokio's blocking thread pool, the rows are written with `tokio::fs`. Only records
+/// the RO parameters, only supports plain CSV files and fails on the first read error.
+#[cfg(feature = "tokio")]
+pub async fn record_respeaker_parameters_async(
+    seconds_to_record: Option<f32>,
+    csv_path: &Path,
+    device: &ReSpeakerDevice,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<RecordingStats> {
+    use tokio::io::AsyncWriteExt;
+
+    let metadata = recording_metadata(device);
+    let columns = ParamKind::sorted()
+        .into_iter()
+        .filter(|p| p.def().access == Access::ReadOnly && device.has_param(p))
+        .collect::<Vec<_>>();
+    let mut file = tokio::fs::File::create(csv_path).await?;
+    file.write_all(&header_bytes(Some(&metadata), &columns, None)?)
+        .await?;
+
+    let start = Instant::now();
+    let mut stats = RecordingStats::default();
+    let mut ticks = tokio::time::interval(Duration::from_millis(10));
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    while running.load(Ordering::SeqCst)
+        && start.elapsed().as_secs_f32() <= seconds_to_record.unwrap_or(f32::INFINITY)
+    {
+        ticks.tick().await;
+        let before = iso8601();
+        let device = device.clone();
+        let values = tokio::task::spawn_blocking(move || {
+            read_row(
+                &device,
+                OnError::Fail,
+                Duration::ZERO,
+                &[],
+                &mut RecordingStats::default(),
+            )
+        })
+        .await??;
+        let after = iso8601();
+        file.write_all(&row_bytes(&before, &after, &values, &columns, None, None)?)
+            .await?;
+        stats.rows += 1;
     }
+    file.flush().await?;
+
+    info!(
+        "Recording done. {}, {} rows",
+        activity_summary(device),
+        stats.rows
+    );
+    Ok(stats)
+}
+
+/// Records `<output>.csv` and `<output>.wav` at the same time. The CSV metadata contains the local time of
+/// the first audio sample (`audio_started_at`) to align both timelines.
+#[cfg(feature = "audio")]
+pub fn record_with_audio(
+    seconds_to_record: Option<f32>,
+    output: &Path,
+    device: &ReSpeakerDevice,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<RecordingStats> {
+    let audio = AudioCapture::start(&output.with_extension("wav"))?;
+    let mut metadata = recording_metadata(device);
+    metadata.audio_started_at = Some(
+        audio
+            .wait_started(Duration::from_secs(2))?
+            .format("%+")
+            .to_string(),
+    );
+
+    let stats = record_with_metadata(
+        seconds_to_record,
+        Some(output.with_extension("csv")),
+        device,
+        running,
+        &RecordingOptions::default(),
+        &metadata,
+    );
+    audio.finish()?;
+    stats
+}
+
+fn record_with_metadata(
+    seconds_to_record: Option<f32>,
+    csv_path: Option<PathBuf>,
+    device: &ReSpeakerDevice,
+    running: &Arc<AtomicBool>,
+    options: &RecordingOptions,
+    metadata: &RecordingMetadata,
+) -> eyre::Result<RecordingStats> {
+    if options.compress && options.format != RecordFormat::Csv {
+        bail!("Compression is only supported for CSV recordings");
+    }
+    if options.append && options.format != RecordFormat::Csv {
+        bail!("Appending is only supported for CSV recordings");
+    }
+    if options.max_file_size.is_some() && options.format != RecordFormat::Csv {
+        bail!("A maximum file size is only supported for CSV recordings");
+    }
+    if options.interleave_audio.is_some() && options.format != RecordFormat::Csv {
+        bail!("Interleaving audio is only supported for CSV recordings");
+    }
+    if options.output_dir_format == OutputDirFormat::PerCategory
+        && options.format != RecordFormat::Csv
+    {
+        bail!("One file per category is only supported for CSV recordings");
+    }
+    if options.no_header && !options.append {
+        warn!("Writing a new CSV file without a header, did you mean to use --append?");
+    }
+    let (mut writer, csv_path) = open_row_writer(csv_path, options, metadata)?;
+    let info = device.device_info();
+    writer.write_comment(&format!(
+        "DEVICE model={:?} serial={} firmware={}",
+        device.model(),
+        info.serial.as_deref().unwrap_or("unknown"),
+        info.firmware.as_deref().unwrap_or("unknown")
+    ))?;
+    if let Some(trigger) = &options.trigger {
+        wait_for_trigger(device, trigger, running)?;
+    }
+    let file_seconds = match &options.trigger_file {
+        Some(path) => wait_for_trigger_file(path, running)?,
+        None => None,
+    };
+    // The shorter of -s and seconds=<N> in the trigger file
+    let seconds_to_record = match (seconds_to_record, file_seconds) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
 
     let start = Instant::now();
+    let mut stats = RecordingStats::default();
+    let mut last_rw_refresh = Instant::now();
+    let progress = if options.progress && csv_path.as_os_str() != "-" {
+        progress_bar(seconds_to_record)
+    } else {
+        ProgressBar::hidden()
+    };
+
+    let trigger_file_exists = || options.trigger_file.as_ref().is_none_or(|p| p.exists());
+    while running.load(Ordering::SeqCst)
+        && start.elapsed().as_secs_f32() <= seconds_to_record.unwrap_or(f32::INFINITY)
+        && trigger_file_exists()
+    {
+        if options
+            .rw_refresh_interval
+            .is_some_and(|interval| last_rw_refresh.elapsed() >= interval)
+        {
+            let mut values = device.read_rw()?;
+            values.retain(|param, _| !options.exclude.contains(param));
+            writer.write_comment("RW_PARAMS_REFRESHED")?;
+            writer.write_row(RW_REFRESH_MARKER, &iso8601(), &values)?;
+            last_rw_refresh = Instant::now();
+        }
+
+        let before = iso8601();
+        if let (Some(audio), 0) = (&options.interleave_audio, stats.rows) {
+            audio.check_aligned(&before)?;
+        }
+        let values = read_row(
+            device,
+            options.on_error,
+            options.retry_delay,
+            &options.exclude,
+            &mut stats,
+        )?;
+        let after = iso8601();
+        writer.write_row(&before, &after, &values)?;
+        stats.rows += 1;
+        update_progress(&progress, start.elapsed(), seconds_to_record, stats.rows);
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    progress.finish_and_clear();
+    let reason = if !running.load(Ordering::SeqCst) {
+        "ctrlc"
+    } else if trigger_file_exists() {
+        "duration"
+    } else {
+        "trigger_file"
+    };
+    writer.write_comment(&format!("RECORDING_ENDED reason={reason}"))?;
+    writer.finish()?;
+
+    info!(
+        "Recording done. {}, {} rows, {} read errors, {} skipped values",
+        activity_summary(device),
+        stats.rows,
+        stats.error_count,
+        stats.skip_count
+    );
+
+    Ok(stats)
+}
+
+/// Creates the output file, `<output dir>/<timestamp>.<ext>` if `csv_path` is `None`. Returns the writer
+/// and the final path, which has `.gz` appended for compressed recordings. For
+/// [`OutputDirFormat::PerCategory`] the path is the directory.
+fn open_row_writer(
+    csv_path: Option<PathBuf>,
+    options: &RecordingOptions,
+    metadata: &RecordingMetadata,
+) -> eyre::Result<(RowWriter, PathBuf)> {
+    let dir = options
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("./recordings"));
+    if csv_path.is_none() && !dir.exists() {
+        fs::create_dir_all(&dir)?;
+    }
+    if csv_path.is_none() && options.output_dir_format == OutputDirFormat::PerCategory {
+        return Ok((per_category_writer(&dir, options, metadata)?, dir));
+    }
 
     let csv_path = csv_path.unwrap_or_else(|| {
         let timetamp = iso8601();
         let timestap_save = timetamp.replace(':', "_");
-        PathBuf::from(format!("./recordings/{timestap_save}.csv"))
+        dir.join(format!("{timestap_save}.{}", options.format.extension()))
     });
-    let mut csv_writer = CsvWriter::new(&csv_path)?;
+    let csv_path = compressed_path(csv_path, options);
+    let writer = match options.format {
+        RecordFormat::Csv => RowWriter::Csv(Box::new(CsvWriter::with_options(
+            &csv_path,
+            &csv_writer_options(options, metadata, options.exclude.clone()),
+        )?)),
+        #[cfg(feature = "serde")]
+        RecordFormat::Jsonl => RowWriter::Ndjson(NdjsonWriter::new(&csv_path)?),
+        #[cfg(feature = "bincode")]
+        RecordFormat::Binary => RowWriter::Binary(BinaryRowWriter::new(&csv_path)?),
+    };
+    Ok((writer, csv_path))
+}
+
+/// `<dir>/<category>.csv` for every category with at least one recorded parameter. Unlike the
+/// timestamped single files these names repeat, so existing files are an error without `append`.
+fn per_category_writer(
+    dir: &Path,
+    options: &RecordingOptions,
+    metadata: &RecordingMetadata,
+) -> eyre::Result<RowWriter> {
+    let files = ParamCategory::iter()
+        .filter_map(|category| {
+            let excluded = ParamKind::iter()
+                .filter(|p| p.category() != category || options.exclude.contains(p))
+                .collect::<Vec<_>>();
+            let path = compressed_path(dir.join(format!("{}.csv", category.file_stem())), options);
+            (excluded.len() < ParamKind::iter().count()).then_some((path, excluded))
+        })
+        .collect::<Vec<_>>();
+    // Checked before creating the first file, so a previous session stays complete
+    if let Some((path, _)) = files
+        .iter()
+        .find(|(path, _)| !options.append && path.exists())
+    {
+        bail!(
+            "{} already exists, use --append to continue the recording or another --output-dir",
+            path.display()
+        );
+    }
+    let writers = files
+        .into_iter()
+        .map(|(path, excluded)| {
+            CsvWriter::with_options(&path, &csv_writer_options(options, metadata, excluded))
+        })
+        .collect::<eyre::Result<_>>()?;
+    Ok(RowWriter::PerCategory(writers))
+}
+
+/// Appends `.gz` for compressed recordings.
+fn compressed_path(csv_path: PathBuf, options: &RecordingOptions) -> PathBuf {
+    if options.compress
+        && csv_path.as_os_str() != "-"
+        && csv_path.extension() != Some("gz".as_ref())
+    {
+        let mut path = csv_path.into_os_string();
+        path.push(".gz");
+        PathBuf::from(path)
+    } else {
+        csv_path
+    }
+}
+
+fn csv_writer_options(
+    options: &RecordingOptions,
+    metadata: &RecordingMetadata,
+    excluded: Vec<ParamKind>,
+) -> CsvWriterOptions {
+    CsvWriterOptions {
+        // Metadata rows in the middle of an existing file would break the reader
+        metadata: (!options.append).then(|| metadata.clone()),
+        compress: options.compress,
+        append: options.append,
+        no_header: options.no_header,
+        excluded,
+        column_order: options.column_order,
+        max_file_size: options.max_file_size,
+        audio: options.interleave_audio.clone(),
+    }
+}
+
+fn wait_for_trigger(
+    device: &ReSpeakerDevice,
+    trigger: &TriggerCondition,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let direction = match trigger.direction {
+        TriggerDirection::Above => ">",
+        TriggerDirection::Below => "<",
+    };
+    info!(
+        "Waiting for {:?} {direction} {} to start recording...",
+        trigger.param, trigger.threshold
+    );
+    while running.load(Ordering::SeqCst) {
+        let value = device.read(&trigger.param)?;
+        if trigger.is_met(&value) {
+            info!("Triggered with {:?} = {value}", trigger.param);
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// Polls every 100 ms until `path` exists. Returns the maximum duration from the file, see
+/// [`parse_trigger_file`].
+fn wait_for_trigger_file(path: &Path, running: &Arc<AtomicBool>) -> eyre::Result<Option<f32>> {
+    info!("Waiting for {} to start recording...", path.display());
+    while running.load(Ordering::SeqCst) {
+        if path.exists() {
+            // Deleted again right away, the recording stops immediately
+            let contents = fs::read_to_string(path).unwrap_or_default();
+            let seconds = parse_trigger_file(&contents)?;
+            info!("Triggered by {}, delete it to stop", path.display());
+            return Ok(seconds);
+        }
+        thread::sleep(TRIGGER_FILE_POLL_INTERVAL);
+    }
+    Ok(None)
+}
+
+/// Maximum recording duration from the first line of a trigger file, `seconds=<N>`. An empty file has no
+/// maximum.
+fn parse_trigger_file(contents: &str) -> eyre::Result<Option<f32>> {
+    let first_line = contents.lines().next().unwrap_or_default().trim();
+    if first_line.is_empty() {
+        return Ok(None);
+    }
+    let Some(seconds) = first_line.strip_prefix("seconds=") else {
+        bail!("Invalid trigger file, expected seconds=<N> but got {first_line:?}");
+    };
+    let seconds = seconds
+        .trim()
+        .parse::<f32>()
+        .map_err(|e| eyre::eyre!("Invalid seconds in trigger file: {e}"))?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        bail!("Invalid seconds in trigger file: {seconds}");
+    }
+    Ok(Some(seconds))
+}
+
+/// A bar for recordings with a duration, otherwise a spinner.
+fn progress_bar(seconds_to_record: Option<f32>) -> ProgressBar {
+    let (progress, template) = seconds_to_record.map_or_else(
+        || (ProgressBar::new_spinner(), "{spinner} {msg}"),
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        |seconds| {
+            (
+                ProgressBar::new((seconds * 1000.0) as u64),
+                "[{bar:30}] {msg}",
+            )
+        },
+    );
+    progress.set_style(
+        ProgressStyle::with_template(template)
+            .expect("Valid template")
+            .progress_chars("=> "),
+    );
+    progress
+}
+
+fn update_progress(
+    progress: &ProgressBar,
+    elapsed: Duration,
+    seconds_to_record: Option<f32>,
+    rows: u64,
+) {
+    let elapsed_secs = elapsed.as_secs_f32();
+    #[allow(clippy::cast_precision_loss)]
+    let rate = rows as f32 / elapsed_secs.max(f32::EPSILON);
+    if let Some(seconds) = seconds_to_record {
+        #[allow(clippy::cast_possible_truncation)]
+        progress.set_position(elapsed.as_millis() as u64);
+        progress.set_message(format!(
+            "{elapsed_secs:.1}s / {seconds}s ({:.0}%) | {rate:.0} samples/s | {rows} rows written",
+            (elapsed_secs / seconds * 100.0).min(100.0)
+        ));
+    } else {
+        progress.tick();
+        progress.set_message(format!(
+            "{elapsed_secs:.1}s | {rate:.0} rows/s | {rows} rows written"
+        ));
+    }
+}
+
+/// Updates the RO values and returns all cached values. With [`OnError::Skip`] and [`OnError::Retry`]
+/// parameters which could not be read are left out.
+fn read_row(
+    device: &ReSpeakerDevice,
+    on_error: OnError,
+    retry_delay: Duration,
+    excluded: &[ParamKind],
+    stats: &mut RecordingStats,
+) -> eyre::Result<HashMap<ParamKind, Value>> {
+    let mut failed = vec![];
+    for param in device
+        .available_params()
+        .into_iter()
+        .filter(|p| p.def().access == Access::ReadOnly && !excluded.contains(p))
+    {
+        let Err(e) = device.read(&param) else {
+            continue;
+        };
+        if on_error == OnError::Fail {
+            return Err(e);
+        }
+        stats.error_count += 1;
+        if on_error == OnError::Retry {
+            thread::sleep(retry_delay);
+            if device.read(&param).is_ok() {
+                continue;
+            }
+            stats.error_count += 1;
+        }
+        warn!("Could not read {param:?}, leaving it empty in this row: {e}");
+        stats.skip_count += 1;
+        failed.push(param);
+    }
+
+    let mut values = device
+        .params()
+        .lock()
+        .expect("Lock failed")
+        .current_params
+        .clone();
+    values.retain(|param, _| !excluded.contains(param) && !failed.contains(param));
+    Ok(values)
+}
+
+/// Records one CSV file per speech segment into `output_dir`.
+/// A segment starts when VOICEACTIVITY goes from 0 to 1 and ends once it has been 0 for longer than `silence_grace`.
+pub fn record_speech_segments(
+    seconds_to_record: Option<f32>,
+    output_dir: &Path,
+    silence_grace: Duration,
+    device: &ReSpeakerDevice,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    struct OpenSegment {
+        writer: CsvWriter,
+        start: String,
+        silence_since: Option<Instant>,
+    }
+
+    if !output_dir.exists() {
+        fs::create_dir_all(output_dir)?;
+    }
+
+    let start = Instant::now();
+    let mut segments = vec![];
+    let mut current: Option<OpenSegment> = None;
 
     while running.load(Ordering::SeqCst)
         && start.elapsed().as_secs_f32() <= seconds_to_record.unwrap_or(f32::INFINITY)
     {
         let before = iso8601();
         device.read_ro()?; // update readonly values
-        let values = {
-            let params = device
-                .params()
-                .lock()
-                .expect("Lock failed")
-                .current_params
-                .clone();
-            params
-        };
+        let values = device
+            .params()
+            .lock()
+            .expect("Lock failed")
+            .current_params
+            .clone();
         let after = iso8601();
-        csv_writer.write_row(&before, &after, &values)?;
+
+        let voice_active = values.get(&ParamKind::VOICEACTIVITY) == Some(&Value::Int(1));
+
+        if current.is_none() && voice_active {
+            let path = output_dir.join(format!("segment_{:03}.csv", segments.len() + 1));
+            info!("Speech started, recording to {path:?}");
+            current = Some(OpenSegment {
+                writer: CsvWriter::with_metadata_header(&path, &recording_metadata(device))?,
+                start: before.clone(),
+                silence_since: None,
+            });
+        }
+
+        if let Some(segment) = &mut current {
+            segment.writer.write_row(&before, &after, &values)?;
+
+            if voice_active {
+                segment.silence_since = None;
+            } else {
+                let silence_since = *segment.silence_since.get_or_insert_with(Instant::now);
+                if silence_since.elapsed() >= silence_grace {
+                    if let Some(segment) = current.take() {
+                        segments.push(close_segment(
+                            segments.len() + 1,
+                            segment.writer,
+                            segment.start,
+                            after,
+                        )?);
+                    }
+                }
+            }
+        }
 
         thread::sleep(Duration::from_millis(10));
     }
 
-    drop(csv_writer);
+    if let Some(segment) = current.take() {
+        segments.push(close_segment(
+            segments.len() + 1,
+            segment.writer,
+            segment.start,
+            iso8601(),
+        )?);
+    }
 
-    info!("Recording done");
+    info!(
+        "Recording done. {}, {} speech segments:\n{}",
+        activity_summary(device),
+        segments.len(),
+        Table::new(segments)
+    );
 
     Ok(())
 }
 
+#[derive(Tabled)]
+struct SegmentRow {
+    segment: usize,
+    start: String,
+    end: String,
+    duration: String,
+}
+
+fn close_segment(
+    segment: usize,
+    writer: CsvWriter,
+    start: String,
+    end: String,
+) -> eyre::Result<SegmentRow> {
+    writer.finish()?;
+
+    let duration = chrono::DateTime::parse_from_rfc3339(&end)?
+        .signed_duration_since(chrono::DateTime::parse_from_rfc3339(&start)?);
+    #[allow(clippy::cast_precision_loss)]
+    let seconds = duration.num_milliseconds() as f64 / 1000.0;
+    info!("Speech ended after {seconds:.3} s");
+
+    Ok(SegmentRow {
+        segment,
+        start,
+        end,
+        duration: format!("{seconds:.3} s"),
+    })
+}
+
+pub(crate) fn activity_summary(device: &ReSpeakerDevice) -> String {
+    let params = device.params();
+    let params = params.lock().expect("Lock failed");
+    format!(
+        "Speech: {} events | VAD: {} events",
+        params.speech_detection_count, params.voice_activity_count
+    )
+}
+
+fn recording_metadata(device: &ReSpeakerDevice) -> RecordingMetadata {
+    let info = device.device_info();
+    RecordingMetadata {
+        serial: info.serial,
+        firmware: info.firmware,
+        recorded_at: iso8601(),
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        audio_started_at: None,
+    }
+}
+
 fn iso8601() -> String {
     let dt = Local::now();
     format!("{}", dt.format("%+"))
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::parse_trigger_file;
+
+    #[rstest]
+    #[case::seconds("seconds=2.5\n", Some(2.5))]
+    #[case::empty("", None)]
+    #[case::blank_line("\nseconds=1", None)]
+    fn trigger_file_duration(#[case] contents: &str, #[case] expected: Option<f32>) {
+        assert_eq!(
+            parse_trigger_file(contents).expect("Valid trigger file"),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::other_key("minutes=1")]
+    #[case::negative("seconds=-1")]
+    #[case::not_a_number("seconds=abc")]
+    fn trigger_file_rejects_invalid_contents(#[case] contents: &str) {
+        assert!(parse_trigger_file(contents).is_err());
+    }
+}
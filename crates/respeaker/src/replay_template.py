@@ -0,0 +1,42 @@
+#!/usr/bin/env python3
+@DOCSTRING@
+import argparse
+import struct
+import time
+
+VENDOR_ID = @VENDOR_ID@
+PRODUCT_IDS = (@PRODUCT_IDS@)
+TIMEOUT_MS = 2000
+
+
+def write(dev, name, index, cmd, value, is_int):
+    if dev is None:
+        print(f"{name} = {value}")
+        return
+    import usb.util
+
+    if is_int:
+        payload = struct.pack("<iii", cmd, value, 1)
+    else:
+        payload = struct.pack("<ifi", cmd, value, 0)
+    request_type = usb.util.CTRL_OUT | usb.util.CTRL_TYPE_VENDOR | usb.util.CTRL_RECIPIENT_DEVICE
+    dev.ctrl_transfer(request_type, 0, 0, index, payload, TIMEOUT_MS)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--dry-run", action="store_true", help="print the writes instead of sending them")
+    args = parser.parse_args()
+
+    dev = None
+    if not args.dry_run:
+        import usb.core
+
+        dev = usb.core.find(custom_match=lambda d: d.idVendor == VENDOR_ID and d.idProduct in PRODUCT_IDS)
+        if dev is None:
+            raise SystemExit("No ReSpeaker found")
+
+@WRITES@
+
+if __name__ == "__main__":
+    main()
@@ -1,34 +1,374 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    fmt::{Display, Write as _},
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex, RwLock,
+    },
     thread,
     time::{Duration, Instant},
 };
 
+use clap::ValueEnum;
 use rusb::{Device, DeviceHandle, GlobalContext};
 use strum::IntoEnumIterator;
 use tabled::{Table, Tabled};
-use tracing::info;
+use tracing::{debug, info, warn};
 
-use crate::params::{Access, ParamKind, ParamState, ParamType, Value};
-use eyre::{bail, OptionExt, Result};
+use crate::build_string;
+use crate::mock::MockDevice;
+use crate::params::{
+    Access, DeviceModel, ParamKind, ParamSortOrder, ParamState, ParamType, Value, WriteableParam,
+    MARKDOWN_TABLE_HEADER,
+};
+use eyre::{bail, Result};
 
-const TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// Values a subscription buffers for a slow consumer.
+pub const SUBSCRIPTION_BUFFER: usize = 16;
 
+/// Handle to a `ReSpeaker` device which can be cloned and shared between threads.
+///
+/// Locking strategy: the USB handle and the parameter cache live in a `DeviceInner` behind an [`RwLock`].
+/// Reads take the shared lock, so several threads (e.g. the UI refresh thread and the UI itself) can read
+/// concurrently. Writes and resets take the exclusive lock. The [`ParamState`] mutex is always acquired
+/// *after* the device lock and only held for the duration of a cache update, so callers must never try to
+/// lock the device while holding the `ParamState` mutex.
+#[derive(Clone)]
 pub struct ReSpeakerDevice {
+    inner: Arc<RwLock<DeviceInner>>,
+    metrics: Arc<DeviceMetrics>,
+    on_change: Arc<RwLock<Option<ParamChangeCallback>>>,
+}
+
+const XMOS_DFU_RESETDEVICE: u8 = 0xF0;
+const XMOS_DFU_REVERTFACTORY: u8 = 0xF1;
+/// Standard DFU class request, answered by the XMOS firmware on the same interface.
+const DFU_GETSTATUS: u8 = 3;
+/// Reverting takes longer than a reset because the factory image is copied first.
+const FIRMWARE_REVERT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Response of `DFU_GETSTATUS`, see the USB DFU 1.1 specification, section 6.1.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DfuStatus {
+    /// `bStatus`, 0 = OK, everything else is an error code.
+    pub status: u8,
+    /// `bState` of the DFU state machine.
+    pub state: u8,
+}
+
+impl DfuStatus {
+    /// `dfuIDLE`
+    pub const STATE_IDLE: u8 = 2;
+
+    /// Whether the firmware is idle and accepts a reset.
+    #[must_use]
+    pub const fn is_idle(&self) -> bool {
+        self.state == Self::STATE_IDLE
+    }
+}
+
+/// Called with `(param, old_value, new_value)`, see [`ReSpeakerDevice::set_on_change`].
+pub type ParamChangeCallback = Arc<dyn Fn(ParamKind, Value, Value) + Send + Sync>;
+
+/// USB transport counters, shared by all clones of a [`ReSpeakerDevice`] and kept across resets.
+#[derive(Debug, Default)]
+struct DeviceMetrics {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    errors: AtomicU64,
+    total_read_us: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceMetricsSnapshot {
+    pub reads: u64,
+    pub writes: u64,
+    /// Failed USB transfers (reads and writes). Rejected values are not counted.
+    pub errors: u64,
+    pub mean_read_latency_us: u64,
+}
+
+impl DeviceMetricsSnapshot {
+    /// Prometheus text exposition format: the transfer counters and the mean read latency, which
+    /// [`ParamState::to_prometheus_text`] doesn't cover.
+    #[must_use]
+    pub fn to_prometheus_text(&self) -> String {
+        build_string(|text| {
+            #[allow(clippy::cast_precision_loss)]
+            let mean_read_latency = self.mean_read_latency_us as f64 / 1e6;
+            for (name, help, kind, value) in [
+                (
+                    "respeaker_usb_reads_total",
+                    "Successful parameter reads",
+                    "counter",
+                    &self.reads as &dyn Display,
+                ),
+                (
+                    "respeaker_usb_writes_total",
+                    "Successful parameter writes",
+                    "counter",
+                    &self.writes,
+                ),
+                (
+                    "respeaker_usb_errors_total",
+                    "Failed USB transfers",
+                    "counter",
+                    &self.errors,
+                ),
+                (
+                    "respeaker_usb_mean_read_latency_seconds",
+                    "Mean duration of a parameter read",
+                    "gauge",
+                    &mean_read_latency,
+                ),
+            ] {
+                writeln!(text, "# HELP {name} {help}")?;
+                writeln!(text, "# TYPE {name} {kind}")?;
+                writeln!(text, "{name} {value}")?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Display for DeviceMetricsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "USB transfers: {} reads, {} writes, {} errors, mean read latency {} µs",
+            self.reads, self.writes, self.errors, self.mean_read_latency_us
+        )
+    }
+}
+
+/// One control transfer measured by [`ReSpeakerDevice::time_read_control`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferTiming {
+    /// From issuing the request until the response was received. Control transfers are synchronous, so
+    /// this includes NAK retries of the host controller.
+    pub elapsed: Duration,
+    /// Size of the response buffer.
+    pub requested: usize,
+    /// Bytes the device actually returned.
+    pub returned: usize,
+}
+
+/// New values of a subscribed parameter, see [`ReSpeakerDevice::subscribe_param`]. The polling thread stops
+/// once the subscription is dropped.
+pub struct Subscription {
+    rx: mpsc::Receiver<Value>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Subscription {
+    /// The next value if one is available, without blocking.
+    #[must_use]
+    pub fn try_recv(&self) -> Option<Value> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        self.rx.recv().ok()
+    }
+}
+
+/// Snapshots of the device at a fixed interval, see [`ReSpeakerDevice::into_snapshot_stream`].
+pub struct SnapshotStream {
+    device: ReSpeakerDevice,
+    interval: Duration,
+    all_params: bool,
+    next_read: Option<Instant>,
+}
+
+impl SnapshotStream {
+    /// Reads all parameters instead of only the RO ones.
+    #[must_use]
+    pub const fn all_params(mut self) -> Self {
+        self.all_params = true;
+        self
+    }
+
+    /// Gives the device back.
+    #[must_use]
+    pub fn into_inner(self) -> ReSpeakerDevice {
+        self.device
+    }
+}
+
+/// Never ends, stop it with e.g. `take_while`. The first snapshot is read immediately, the following ones
+/// at multiples of the interval. Reads which take longer than the interval delay the following ones.
+impl Iterator for SnapshotStream {
+    type Item = Result<ParamState>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(next_read) = self.next_read {
+            thread::sleep(next_read.saturating_duration_since(Instant::now()));
+        }
+        let start = Instant::now();
+        let next_read = self.next_read.unwrap_or(start) + self.interval;
+        // Skip the boundaries which have already passed
+        self.next_read = Some(if next_read < start {
+            start + self.interval
+        } else {
+            next_read
+        });
+        let values = if self.all_params {
+            self.device.read_all()
+        } else {
+            self.device.read_ro()
+        };
+        Some(values.map(|values| {
+            let mut snapshot = ParamState::default();
+            snapshot.current_params = values;
+            snapshot
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub bus: u8,
+    pub address: u8,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+}
+
+/// Result of [`ReSpeakerDevice::check_firmware_compat`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FirmwareCheck {
+    pub compatible: bool,
+    pub warnings: Vec<String>,
+}
+
+struct DeviceInner {
     index: usize,
-    handle: DeviceHandle<GlobalContext>,
+    model: DeviceModel,
+    backend: Backend,
     interface_number: u8,
     param_state: Arc<Mutex<ParamState>>,
+    timeout: Duration,
 }
 
 impl ReSpeakerDevice {
     pub fn open(device_index: Option<usize>, param_state: Arc<Mutex<ParamState>>) -> Result<Self> {
+        Ok(Self {
+            inner: Arc::new(RwLock::new(Self::open_inner(device_index, param_state)?)),
+            metrics: Arc::default(),
+            on_change: Arc::default(),
+        })
+    }
+
+    /// Like [`Self::open`] but returns `Ok(None)` instead of an error if the device is temporarily busy,
+    /// i.e. claiming the control interface fails with [`rusb::Error::Busy`]. The interface is claimed
+    /// and released once as a probe, because a busy interface only shows up when it is claimed, not
+    /// when the device is opened. [`rusb::Error::Access`] is returned as an error, it usually means
+    /// that a udev rule for the device is missing.
+    ///
+    /// The audio streaming interface and the vendor control interface should be independently accessible,
+    /// but some platforms serialize them, so the control interface can't be opened while the audio
+    /// subsystem (PulseAudio/PipeWire) holds the device.
+    pub fn open_nonblocking(
+        device_index: Option<usize>,
+        param_state: Arc<Mutex<ParamState>>,
+    ) -> Result<Option<Self>> {
+        let device = match Self::open(device_index, param_state) {
+            Err(e) if is_busy(&e) => {
+                debug!("Device is busy: {e}");
+                return Ok(None);
+            }
+            result => result.map_err(access_hint)?,
+        };
+        let inner = device.inner.read().expect("Lock failed");
+        let probe = inner
+            .backend
+            .claim_interface(inner.interface_number)
+            .and_then(|()| inner.backend.release_interface(inner.interface_number));
+        drop(inner);
+        match probe {
+            Err(rusb::Error::Busy) => {
+                debug!("Control interface is busy");
+                Ok(None)
+            }
+            Err(e) => Err(access_hint(e.into())),
+            Ok(()) => Ok(Some(device)),
+        }
+    }
+
+    /// Retries [`Self::open_nonblocking`] every second until the device could be opened, for at most
+    /// `timeout`. Other errors are returned immediately, clearing `running` (Ctrl-C) stops waiting.
+    pub fn wait_for_device_and_control(
+        device_index: Option<usize>,
+        param_state: &Arc<Mutex<ParamState>>,
+        running: &AtomicBool,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let start = Instant::now();
+        let mut warned = false;
+        while running.load(Ordering::SeqCst) {
+            if let Some(device) = Self::open_nonblocking(device_index, param_state.clone())? {
+                return Ok(device);
+            }
+            if start.elapsed() >= timeout {
+                bail!("Device is still busy after {timeout:?}");
+            }
+            if !warned {
+                warn!("Device is busy (e.g. opened by the audio subsystem), retrying every second");
+                warned = true;
+            }
+            thread::sleep(BUSY_RETRY_INTERVAL.min(timeout.saturating_sub(start.elapsed())));
+        }
+        bail!("Interrupted while waiting for the device")
+    }
+
+    /// Opens a simulated device which doesn't need any hardware. See [`MockDevice`].
+    #[must_use]
+    pub fn open_mock(mock: Arc<MockDevice>, param_state: Arc<Mutex<ParamState>>) -> Self {
+        Self::open_mock_model(mock, param_state, DeviceModel::MicArrayV2)
+    }
+
+    /// Like [`Self::open_mock`] but simulates `model`, which hides the parameters it doesn't have.
+    #[must_use]
+    pub fn open_mock_model(
+        mock: Arc<MockDevice>,
+        param_state: Arc<Mutex<ParamState>>,
+        model: DeviceModel,
+    ) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(DeviceInner {
+                index: 0,
+                model,
+                backend: Backend::Mock(mock),
+                interface_number: 0,
+                param_state,
+                timeout: DEFAULT_TIMEOUT,
+            })),
+            metrics: Arc::default(),
+            on_change: Arc::default(),
+        }
+    }
+
+    fn open_inner(
+        device_index: Option<usize>,
+        param_state: Arc<Mutex<ParamState>>,
+    ) -> Result<DeviceInner> {
         fn open_internal(
             index: usize,
-            device: &Device<GlobalContext>,
+            (device, model): &(Device<GlobalContext>, DeviceModel),
             param_state: Arc<Mutex<ParamState>>,
-        ) -> Result<ReSpeakerDevice> {
+        ) -> Result<DeviceInner> {
             let handle = device.open()?;
 
             let config_desc = device.active_config_descriptor()?;
@@ -38,11 +378,13 @@ impl ReSpeakerDevice {
                         && interface_desc.sub_class_code() == 0x01
                     {
                         let interface_number = interface_desc.interface_number();
-                        return Ok(ReSpeakerDevice {
+                        return Ok(DeviceInner {
                             index,
-                            handle,
+                            model: *model,
+                            backend: Backend::Usb(handle),
                             interface_number,
                             param_state,
+                            timeout: DEFAULT_TIMEOUT,
                         });
                     }
                 }
@@ -50,28 +392,7 @@ impl ReSpeakerDevice {
             bail!("Could not find correct interface")
         }
 
-        const VENDOR_ID: u16 = 0x2886;
-        const PRODUCT_ID: u16 = 0x0018;
-
-        info!("Searching for ReSpeaker Mic Array v2.0 device...");
-
-        let mut devices = vec![];
-
-        for device in rusb::devices()?.iter() {
-            let device_desc = device.device_descriptor()?;
-
-            if device_desc.vendor_id() == VENDOR_ID && device_desc.product_id() == PRODUCT_ID {
-                info!(
-                    "Found: Bus {:03} Device {:03} ID {:04x}:{:04x}, speed: {:?}",
-                    device.bus_number(),
-                    device.address(),
-                    device_desc.vendor_id(),
-                    device_desc.product_id(),
-                    device.speed()
-                );
-                devices.push(device);
-            }
-        }
+        let devices = find_devices()?;
         if let Some(i) = device_index {
             if let Some(d) = devices.get(i) {
                 return open_internal(i, d, param_state);
@@ -85,62 +406,231 @@ impl ReSpeakerDevice {
             return open_internal(0, &devices[0], param_state);
         }
         if devices.len() > 1 {
-            bail!("Multiple devices found. Specify the a device index with -i.")
+            bail!("Multiple devices found. Specify the a device index with -i, see --list-devices.")
         }
 
         bail!("No devices found")
     }
 
+    #[must_use]
+    pub fn model(&self) -> DeviceModel {
+        self.inner.read().expect("Lock failed").model
+    }
+
+    /// Whether the detected model has `param`. Reading or writing a missing parameter fails.
+    #[must_use]
+    pub fn has_param(&self, param: &ParamKind) -> bool {
+        self.model().supports(param)
+    }
+
+    /// The parameters of the detected model, in declaration order.
+    #[must_use]
+    pub fn available_params(&self) -> Vec<ParamKind> {
+        let model = self.model();
+        ParamKind::iter().filter(|p| model.supports(p)).collect()
+    }
+
+    /// Index of the device among all detected devices (the `-i` argument).
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.inner.read().expect("Lock failed").index
+    }
+
+    /// Number of the DFU interface used for resets.
+    #[must_use]
+    pub fn interface_number(&self) -> u8 {
+        self.inner.read().expect("Lock failed").interface_number
+    }
+
+    /// USB timeout used for reads and writes, see [`Self::set_timeout`].
+    #[must_use]
+    pub fn usb_timeout(&self) -> Duration {
+        self.inner.read().expect("Lock failed").timeout
+    }
+
+    /// Sets the USB timeout used by all subsequent operations.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.inner.write().expect("Lock failed").timeout = timeout;
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
     pub fn read(&self, param: &ParamKind) -> Result<Value> {
-        let value = self.read_internal(param)?;
-        {
-            let mut params = self.param_state.lock().expect("Lock failed");
-            params.current_params.insert(param.clone(), value.clone());
-        }
-        Ok(value)
+        self.read_with_timeout(param, None)
     }
 
-    fn read_internal(&self, param: &ParamKind) -> Result<Value> {
-        let start = Instant::now();
-        let def = param.def();
+    /// Like [`Self::read`] but with a different USB timeout for this call only. Useful for parameters which
+    /// the firmware computes asynchronously, like RT60 or DOAANGLE.
+    pub fn read_with_timeout_override(
+        &self,
+        param: &ParamKind,
+        timeout: Duration,
+    ) -> Result<Value> {
+        self.read_with_timeout(param, Some(timeout))
+    }
 
-        let mut cmd = 0x80 | def.cmd;
-        if def.param_type.is_int() {
-            cmd |= 0x40;
+    fn read_with_timeout(&self, param: &ParamKind, timeout: Option<Duration>) -> Result<Value> {
+        let inner = self.inner.read().expect("Lock failed");
+        let start = Instant::now();
+        let raw = inner
+            .read_internal(param, timeout.unwrap_or(inner.timeout))
+            .inspect_err(|e| {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+                let since = param.def().firmware_since;
+                if since.is_some() && e.downcast_ref::<rusb::Error>() == Some(&rusb::Error::Pipe) {
+                    warn!("Parameter {param:?} may not be supported by this firmware version (requires {since:?})");
+                }
+            })?;
+        let elapsed_us = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        self.metrics.reads.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .total_read_us
+            .fetch_add(elapsed_us, Ordering::Relaxed);
+        let value = raw.sanitize(&param.def());
+        if value != raw {
+            warn!("Device returned {raw} for {param:?} which is out of range, using {value}");
+        }
+        let old = {
+            let mut params = inner.param_state.lock().expect("Lock failed");
+            let old = params.current_params.get(param).cloned();
+            params.update(param, &value);
+            old
+        };
+        if let Some(old) = old.filter(|old| value.changed_from(old)) {
+            let on_change = self.on_change.read().expect("Lock failed").clone();
+            if let Some(on_change) = on_change {
+                on_change(param.clone(), old, value.clone());
+            }
         }
+        drop(inner);
+        Ok(value)
+    }
 
+    /// Times the control transfer of a read of `param` alone: no decoding, no cache update, no metrics.
+    /// Used by `benchmark --profile usb`.
+    pub fn time_read_control(&self, param: &ParamKind) -> Result<TransferTiming> {
+        let inner = self.inner.read().expect("Lock failed");
         let mut buffer = [0u8; 8];
+        let start = Instant::now();
+        let returned = inner.read_control(param, &mut buffer, inner.timeout)?;
+        let elapsed = start.elapsed();
+        drop(inner);
+        Ok(TransferTiming {
+            elapsed,
+            requested: buffer.len(),
+            returned,
+        })
+    }
 
-        let request_type = rusb::request_type(
-            rusb::Direction::In,
-            rusb::RequestType::Vendor,
-            rusb::Recipient::Device,
-        );
-
-        self.handle
-            .read_control(request_type, 0, cmd, def.index, &mut buffer, TIMEOUT)?;
+    /// Runs `iterations` write/read-back cycles on [`STRESS_TEST_PARAMS`], e.g. to qualify a USB host or
+    /// hub. Each parameter alternates between its current value and another valid one, so a dropped
+    /// write shows up as a mismatch. The audio processing changes during the test, the original values
+    /// are restored at the end. Clearing `running` (Ctrl-C) stops after the current cycle.
+    pub fn stress_test(&self, iterations: usize, running: &AtomicBool) -> Result<StressTestResult> {
+        let originals = STRESS_TEST_PARAMS
+            .iter()
+            .map(|param| self.read(param))
+            .collect::<Result<Vec<_>>>()?;
+        let mut result = StressTestResult::default();
+        let mut latencies = None;
+        let cycles = STRESS_TEST_PARAMS.iter().zip(&originals).cycle();
+        for (cycle, (param, original)) in cycles.take(iterations).enumerate() {
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+            let value = if (cycle / STRESS_TEST_PARAMS.len()) % 2 == 0 {
+                let def = param.def();
+                if *original == def.min() {
+                    def.max()
+                } else {
+                    def.min()
+                }
+            } else {
+                original.clone()
+            };
+            result.total += 1;
+            match self.stress_test_cycle(param, &value, &mut latencies) {
+                Ok(true) => {}
+                Ok(false) => result.mismatches += 1,
+                Err(e) => {
+                    debug!("Stress test cycle of {param:?} failed: {e}");
+                    result.errors += 1;
+                    if e.downcast_ref::<rusb::Error>() == Some(&rusb::Error::Timeout) {
+                        result.timeouts += 1;
+                    }
+                }
+            }
+        }
+        (result.min_latency_us, result.max_latency_us) = latencies.unwrap_or_default();
+        for (param, original) in STRESS_TEST_PARAMS.iter().zip(&originals) {
+            self.write(param, original)?;
+        }
+        Ok(result)
+    }
 
-        let response = (
-            i32::from_le_bytes(buffer[0..4].try_into()?),
-            i32::from_le_bytes(buffer[4..8].try_into()?),
-        );
-        info!("Read parameter {:?} in {:?}", param, start.elapsed());
+    /// One cycle of [`Self::stress_test`], returns whether the value read back matches `value`.
+    /// `latencies` are the minimum and maximum of the transfers so far.
+    fn stress_test_cycle(
+        &self,
+        param: &ParamKind,
+        value: &Value,
+        latencies: &mut Option<(u64, u64)>,
+    ) -> Result<bool> {
+        let mut timed = |f: &mut dyn FnMut() -> Result<Value>| {
+            let start = Instant::now();
+            let value = f()?;
+            let us = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+            *latencies = Some(latencies.map_or((us, us), |(min, max)| (min.min(us), max.max(us))));
+            Ok::<_, eyre::Report>(value)
+        };
+        timed(&mut || self.write(param, value).map(|()| value.clone()))?;
+        let read_back = timed(&mut || self.read(param))?;
+        Ok(read_back == *value)
+    }
 
-        Ok(if def.param_type.is_int() {
-            #[allow(clippy::cast_sign_loss)]
-            Value::Int(response.0 as usize)
-        } else {
-            #[allow(clippy::cast_possible_truncation)]
-            let float = (f64::from(response.0) * f64::from(response.1).exp2()) as f32;
-            Value::Float(float)
+    /// Reads DOAANGLE and AGCONOFF and checks that the raw responses are within their documented ranges.
+    /// Firmware which uses different command indices returns garbage or fails for these reads. Parameters
+    /// the model doesn't have, like DOAANGLE on the linear array, are skipped.
+    ///
+    /// Unlike [`Self::read`], the values are not clamped and the cache is not updated.
+    pub fn check_firmware_compat(&self) -> Result<FirmwareCheck> {
+        let inner = self.inner.read().expect("Lock failed");
+        let mut warnings = vec![];
+        for param in [ParamKind::DOAANGLE, ParamKind::AGCONOFF] {
+            let Some(def) = param.def_for_model(inner.model) else {
+                continue;
+            };
+            match inner.read_internal(&param, inner.timeout) {
+                Ok(raw) => {
+                    if raw.sanitize(&def) != raw {
+                        warnings.push(format!("{param:?} returned {raw} which is out of range"));
+                    }
+                }
+                Err(e) => warnings.push(format!("Could not read {param:?}: {e}")),
+            }
+        }
+        drop(inner);
+        Ok(FirmwareCheck {
+            compatible: warnings.is_empty(),
+            warnings,
         })
     }
 
+    /// Registers a callback which is invoked by every read that returns a value different from the cached
+    /// one, e.g. to switch an LED on `VOICEACTIVITY`. Replaces a previous callback and applies to all clones
+    /// of this device.
+    ///
+    /// The callback runs on the reading thread while the device lock is held, so it must not call
+    /// [`Self::read`] or [`Self::write`], which would deadlock. It should also return quickly.
+    pub fn set_on_change<F: Fn(ParamKind, Value, Value) + Send + Sync + 'static>(&self, f: F) {
+        *self.on_change.write().expect("Lock failed") = Some(Arc::new(f));
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
     fn read_all(&self) -> Result<HashMap<ParamKind, Value>> {
         let start = Instant::now();
         let mut result = HashMap::new();
 
-        for p in ParamKind::iter() {
+        for p in self.available_params() {
             let value = self.read(&p)?;
             result.insert(p, value);
         }
@@ -149,10 +639,31 @@ impl ReSpeakerDevice {
         Ok(result)
     }
 
+    #[tracing::instrument(level = "trace", skip(self))]
     pub fn read_ro(&self) -> Result<HashMap<ParamKind, Value>> {
         let mut result = HashMap::new();
 
-        for p in ParamKind::iter().filter(|p| p.def().access == Access::ReadOnly) {
+        for p in self
+            .available_params()
+            .into_iter()
+            .filter(|p| p.def().access == Access::ReadOnly)
+        {
+            let value = self.read(&p)?;
+            result.insert(p, value);
+        }
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn read_rw(&self) -> Result<HashMap<ParamKind, Value>> {
+        let mut result = HashMap::new();
+
+        for p in self
+            .available_params()
+            .into_iter()
+            .filter(|p| p.def().access == Access::ReadWrite)
+        {
             let value = self.read(&p)?;
             result.insert(p, value);
         }
@@ -160,12 +671,22 @@ impl ReSpeakerDevice {
         Ok(result)
     }
 
+    /// Writes a parameter. Fails for RO parameters, see [`Self::write_checked`] for a type-safe variant.
+    #[tracing::instrument(level = "trace", skip(self))]
     pub fn write(&self, param: &ParamKind, value: &Value) -> Result<()> {
-        let def = param.def();
-
-        if def.access == Access::ReadOnly {
+        let Some(param) = param.as_writeable() else {
             bail!("Parameter {:?} is read-only", param);
-        }
+        };
+        self.write_checked(&param, value)
+    }
+
+    /// Writes a parameter which is known to be RW. The value is still checked against the range.
+    pub fn write_checked(&self, param: &WriteableParam, value: &Value) -> Result<()> {
+        let param = param.param();
+        let inner = self.inner.write().expect("Lock failed");
+        let Some(def) = param.def_for_model(inner.model) else {
+            bail!("Parameter {param:?} is not available on {:?}", inner.model);
+        };
 
         let (value_bytes, type_bytes) = match def.param_type {
             ParamType::IntDiscete { min, max } | ParamType::IntRange { min, max } => match value {
@@ -193,7 +714,7 @@ impl ReSpeakerDevice {
             },
         };
 
-        let cmd_bytes = i32::from(def.cmd).to_le_bytes();
+        let cmd_bytes = i32::from(param.write_usb_cmd()).to_le_bytes();
 
         let mut payload = Vec::with_capacity(12);
         payload.extend_from_slice(&cmd_bytes);
@@ -206,88 +727,656 @@ impl ReSpeakerDevice {
             rusb::Recipient::Device,
         );
 
-        self.handle
-            .write_control(request_type, 0, 0, def.index, &payload, TIMEOUT)?;
+        inner
+            .backend
+            .write_control(request_type, 0, 0, def.index, &payload, inner.timeout)
+            .inspect_err(|_| {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+            })?;
+        self.metrics.writes.fetch_add(1, Ordering::Relaxed);
 
         info!("Wrote value {value} to param {:?} successfully", param);
 
         {
-            let mut params = self.param_state.lock().expect("Lock failed");
-            params.current_params.insert(param.clone(), value.clone());
+            let mut params = inner.param_state.lock().expect("Lock failed");
+            // Only the cached value, the audit log must not cause extra reads
+            let old_value = params.current_params.insert(param.clone(), value.clone());
+            params.log_write(param, old_value, value.clone());
         }
+        drop(inner);
 
         Ok(())
     }
 
-    pub fn reset(&mut self) -> Result<()> {
-        const XMOS_DFU_RESETDEVICE: u8 = 0xF0;
-        //const XMOS_DFU_REVERTFACTORY: u8 = 0xf1;
+    /// Reads `param` every `interval` on a background thread. Failed reads are logged and skipped. At most
+    /// [`SUBSCRIPTION_BUFFER`] values are buffered, after that the thread waits for the consumer.
+    #[must_use]
+    pub fn subscribe_param(&self, param: ParamKind, interval: Duration) -> Subscription {
+        let (tx, rx) = mpsc::sync_channel(SUBSCRIPTION_BUFFER);
+        let stop = Arc::new(AtomicBool::new(false));
+        let device = self.clone();
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let start = Instant::now();
+                match device.read(&param) {
+                    Ok(value) => {
+                        if tx.send(value).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Could not read subscribed parameter {param:?}: {e}"),
+                }
+                thread::sleep(interval.saturating_sub(start.elapsed()));
+            }
+            debug!("Subscription of {param:?} dropped");
+        });
+        Subscription { rx, stop }
+    }
 
+    /// Async variant of [`Self::subscribe_param`]. The reads run on tokio's blocking thread pool, the
+    /// polling task stops once the receiver is dropped. Must be called from within a tokio runtime.
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    pub fn subscribe_param_async(
+        &self,
+        param: ParamKind,
+        interval: Duration,
+    ) -> tokio::sync::mpsc::Receiver<Value> {
+        let (tx, rx) = tokio::sync::mpsc::channel(SUBSCRIPTION_BUFFER);
+        let device = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            while !tx.is_closed() {
+                ticks.tick().await;
+                let device = device.clone();
+                let param_clone = param.clone();
+                match tokio::task::spawn_blocking(move || device.read(&param_clone)).await {
+                    Ok(Ok(value)) => {
+                        if tx.send(value).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Err(e)) => warn!("Could not read subscribed parameter {param:?}: {e}"),
+                    Err(e) => warn!("Read of subscribed parameter {param:?} panicked: {e}"),
+                }
+            }
+            debug!("Subscription of {param:?} dropped");
+        });
+        rx
+    }
+
+    /// Turns the device into an iterator of snapshots of the RO parameters, one every `interval`. Failed
+    /// reads are yielded as errors and the stream continues. Use [`SnapshotStream::into_inner`] to get
+    /// the device back.
+    #[must_use]
+    pub const fn into_snapshot_stream(self, interval: Duration) -> SnapshotStream {
+        SnapshotStream {
+            device: self,
+            interval,
+            all_params: false,
+            next_read: None,
+        }
+    }
+
+    /// Counts of USB transfers since the device was opened.
+    #[must_use]
+    pub fn metrics(&self) -> DeviceMetricsSnapshot {
+        let reads = self.metrics.reads.load(Ordering::Relaxed);
+        let total_read_us = self.metrics.total_read_us.load(Ordering::Relaxed);
+        DeviceMetricsSnapshot {
+            reads,
+            writes: self.metrics.writes.load(Ordering::Relaxed),
+            errors: self.metrics.errors.load(Ordering::Relaxed),
+            mean_read_latency_us: total_read_us.checked_div(reads).unwrap_or(0),
+        }
+    }
+
+    /// Queries the state of the DFU state machine, e.g. to check that it is idle before a reset.
+    pub fn dfu_status(&self) -> Result<DfuStatus> {
+        let request_type = rusb::request_type(
+            rusb::Direction::In,
+            rusb::RequestType::Class,
+            rusb::Recipient::Interface,
+        );
+
+        let inner = self.inner.write().expect("Lock failed");
+        inner.backend.claim_interface(inner.interface_number)?;
+        // bStatus, bwPollTimeout (3 bytes), bState, iString
+        let mut buffer = [0u8; 6];
+        let len = inner.backend.read_control(
+            request_type,
+            DFU_GETSTATUS,
+            0,
+            u16::from(inner.interface_number),
+            &mut buffer,
+            inner.timeout,
+        );
+        inner.backend.release_interface(inner.interface_number)?;
+        drop(inner);
+
+        let len = len?;
+        if len < buffer.len() {
+            bail!("DFU status response is too short ({len} bytes)");
+        }
+        let status = DfuStatus {
+            status: buffer[0],
+            state: buffer[4],
+        };
+        debug!("DFU status: {status:?}");
+        Ok(status)
+    }
+
+    /// Resets the device, waits 2 s and re-opens it.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn reset(&self) -> Result<()> {
+        self.dfu_command(XMOS_DFU_RESETDEVICE, None)
+    }
+
+    /// Like [`Self::reset`] but instead of a fixed delay, tries to re-open the device every 200 ms until
+    /// `timeout` has passed.
+    pub fn reset_wait_ready(&self, timeout: Duration) -> Result<()> {
+        self.dfu_command(XMOS_DFU_RESETDEVICE, Some(timeout))
+    }
+
+    /// Reverts the firmware to the factory image and re-opens the device once it re-enumerated.
+    ///
+    /// **Warning:** This is irreversible without re-flashing the firmware. All custom parameters are lost.
+    pub fn firmware_revert(&self) -> Result<()> {
+        warn!("Reverting the firmware to the factory image");
+        self.dfu_command(XMOS_DFU_REVERTFACTORY, Some(FIRMWARE_REVERT_TIMEOUT))
+    }
+
+    /// Sends a request to the DFU interface, then waits until the device re-enumerated and re-opens it.
+    fn dfu_command(&self, request: u8, wait_ready: Option<Duration>) -> Result<()> {
         let request_type = rusb::request_type(
             rusb::Direction::Out,
             rusb::RequestType::Class,
             rusb::Recipient::Interface,
         );
 
-        self.handle.claim_interface(self.interface_number)?;
+        let mut inner = self.inner.write().expect("Lock failed");
+
+        inner.backend.claim_interface(inner.interface_number)?;
 
-        self.handle.write_control(
+        inner.backend.write_control(
             request_type,
-            XMOS_DFU_RESETDEVICE,
+            request,
             0,
-            u16::from(self.interface_number),
+            u16::from(inner.interface_number),
             &[],
-            TIMEOUT,
+            inner.timeout,
         )?;
 
-        self.handle.release_interface(self.interface_number)?;
+        inner.backend.release_interface(inner.interface_number)?;
 
-        info!("Reset was successfull. Waiting 2 s before re-opening...");
+        if let Backend::Mock(_) = inner.backend {
+            drop(inner);
+            info!("DFU request 0x{request:02X} to mock device was successfull");
+            return Ok(());
+        }
 
-        thread::sleep(Duration::from_secs(2));
+        let new_inner = if let Some(wait_ready) = wait_ready {
+            let start = Instant::now();
+            loop {
+                thread::sleep(Duration::from_millis(200));
+                info!(
+                    "Waiting for device to re-enumerate... ({:.1} s)",
+                    start.elapsed().as_secs_f32()
+                );
+                match Self::open_inner(Some(inner.index), inner.param_state.clone()) {
+                    Ok(new_inner) => break new_inner,
+                    Err(e) if start.elapsed() >= wait_ready => {
+                        bail!("Device did not re-enumerate within {wait_ready:?}: {e}")
+                    }
+                    Err(_) => {}
+                }
+            }
+        } else {
+            info!("DFU request was successfull. Waiting 2 s before re-opening...");
+            thread::sleep(Duration::from_secs(2));
+            Self::open_inner(Some(inner.index), inner.param_state.clone())?
+        };
 
-        *self = Self::open(Some(self.index), self.param_state.clone())?;
+        let timeout = inner.timeout;
+        *inner = new_inner;
+        inner.timeout = timeout;
+        drop(inner);
 
         Ok(())
     }
 
+    #[tracing::instrument(level = "trace", skip(self))]
     pub fn list(&self) -> Result<String> {
-        let param_map = self.read_all()?;
-        let mut rows = vec![];
-        for p in ParamKind::iter() {
-            let def = p.def();
+        self.list_filtered(None)
+    }
 
-            let value = param_map.get(&p).ok_or_eyre("Param not found")?;
+    /// Like [`Self::list`] but only shows parameters with the given access. All parameters are still read.
+    pub fn list_filtered(&self, filter: Option<Access>) -> Result<String> {
+        self.list_sorted(filter, None)
+    }
 
-            let t = if def.param_type.is_int() {
-                "int"
-            } else {
-                "float"
-            };
+    /// Like [`Self::list_filtered`] but in the given order instead of the declaration order.
+    pub fn list_sorted(
+        &self,
+        filter: Option<Access>,
+        order: Option<ParamSortOrder>,
+    ) -> Result<String> {
+        let param_map = self.read_all()?;
+        let rows = self
+            .listed_params(&param_map, filter, order)
+            .map(|(p, value)| TableRow::new(&p, value));
+        Ok(Table::new(rows).to_string())
+    }
 
-            rows.push(TableRow {
-                name: format!("{p:?}"),
-                value: value.clone(),
-                t: t.to_string(),
-                access: if def.access == Access::ReadOnly {
-                    "ro"
-                } else {
-                    "rw"
+    /// Reads all parameters and writes them to `writer` in declaration order, see
+    /// [`Self::export_sorted_to_writer`].
+    pub fn export_to_writer<W: Write>(&self, writer: W, format: ExportFormat) -> Result<()> {
+        self.export_sorted_to_writer(writer, format, None, None)
+    }
+
+    /// Reads all parameters and writes those with the given access to `writer`, in the given order. The
+    /// table is the one of [`Self::list`], CSV and JSON have the same columns (without the discrete value
+    /// descriptions), TOML is a snapshot, see [`crate::config::snapshot_to_toml`], and Markdown a table for
+    /// documentation.
+    pub fn export_sorted_to_writer<W: Write>(
+        &self,
+        mut writer: W,
+        format: ExportFormat,
+        filter: Option<Access>,
+        order: Option<ParamSortOrder>,
+    ) -> Result<()> {
+        let param_map = self.read_all()?;
+        let params = self.listed_params(&param_map, filter, order);
+        match format {
+            ExportFormat::Table => {
+                let rows = params.map(|(p, value)| TableRow::new(&p, value));
+                writeln!(writer, "{}", Table::new(rows))?;
+            }
+            ExportFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(writer);
+                csv.write_record(EXPORT_COLUMNS)?;
+                for (p, value) in params {
+                    let row = TableRow::new(&p, value);
+                    csv.write_record([
+                        &row.name,
+                        &value.to_csv_string(),
+                        &row.t,
+                        &row.access,
+                        &row.range,
+                        &row.since,
+                        &row.description,
+                    ])?;
+                }
+                csv.flush()?;
+            }
+            #[cfg(feature = "serde")]
+            ExportFormat::Json => {
+                let rows = params
+                    .map(|(p, value)| {
+                        let row = TableRow::new(&p, value);
+                        let mut object = serde_json::Map::new();
+                        for (column, field) in EXPORT_COLUMNS.into_iter().zip([
+                            serde_json::Value::from(row.name),
+                            value.to_json(),
+                            row.t.into(),
+                            row.access.into(),
+                            row.range.into(),
+                            row.since.into(),
+                            row.description.into(),
+                        ]) {
+                            object.insert(column.to_string(), field);
+                        }
+                        serde_json::Value::Object(object)
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", serde_json::to_string_pretty(&rows)?)?;
+            }
+            #[cfg(not(feature = "serde"))]
+            ExportFormat::Json => bail!("JSON export needs the serde feature"),
+            ExportFormat::Markdown => {
+                writeln!(writer, "{MARKDOWN_TABLE_HEADER}")?;
+                for (p, value) in params {
+                    writeln!(writer, "{}", p.def().to_markdown_table_row(value))?;
                 }
-                .to_string(),
-                range: format!("{}..{}", def.min(), def.max()),
-                description: def.description.to_string(),
-                values: def.value_descriptions.join("\n"),
-            });
+            }
+            ExportFormat::Compact => {
+                for (p, value) in params {
+                    writeln!(writer, "{}", compact_line(&p, value))?;
+                }
+            }
+            ExportFormat::Toml => {
+                let mut state = ParamState::default();
+                state
+                    .current_params
+                    .extend(params.map(|(p, value)| (p, value.clone())));
+                write!(writer, "{}", crate::config::snapshot_to_toml(&state)?)?;
+            }
         }
-        Ok(Table::new(rows).to_string())
+        Ok(())
+    }
+
+    /// The parameters of `param_map` with the given access, in the given order (declaration order if
+    /// `None`).
+    fn listed_params<'a>(
+        &self,
+        param_map: &'a HashMap<ParamKind, Value>,
+        filter: Option<Access>,
+        order: Option<ParamSortOrder>,
+    ) -> impl Iterator<Item = (ParamKind, &'a Value)> {
+        let params = order.map_or_else(|| self.available_params(), ParamSortOrder::sorted);
+        params.into_iter().filter_map(move |p| {
+            if filter.is_some_and(|access| access != p.def().access) {
+                return None;
+            }
+            // Sorted orders contain all parameters, also those which are not available on this model
+            let value = param_map.get(&p)?;
+            Some((p, value))
+        })
     }
 
+    /// USB location, serial number and firmware version (`bcdDevice`) of the device.
+    #[must_use]
+    pub fn device_info(&self) -> DeviceInfo {
+        let inner = self.inner.read().expect("Lock failed");
+        let handle = match &inner.backend {
+            Backend::Usb(handle) => handle,
+            Backend::Mock(_) => {
+                return DeviceInfo {
+                    bus: 0,
+                    address: 0,
+                    serial: Some("MOCK".to_string()),
+                    firmware: None,
+                };
+            }
+        };
+        let device = handle.device();
+        let device_desc = device.device_descriptor().ok();
+        let serial = device_desc
+            .as_ref()
+            .and_then(|desc| handle.read_serial_number_string_ascii(desc).ok());
+        drop(inner);
+        DeviceInfo {
+            bus: device.bus_number(),
+            address: device.address(),
+            serial,
+            firmware: device_desc.map(|desc| desc.device_version().to_string()),
+        }
+    }
+
+    #[must_use]
     pub fn params(&self) -> Arc<Mutex<ParamState>> {
-        self.param_state.clone()
+        self.inner.read().expect("Lock failed").param_state.clone()
     }
 }
 
+/// Parameters written by [`ReSpeakerDevice::stress_test`].
+pub const STRESS_TEST_PARAMS: [ParamKind; 3] = [
+    ParamKind::HPFONOFF,
+    ParamKind::CNIONOFF,
+    ParamKind::TRANSIENTONOFF,
+];
+
+/// Result of [`ReSpeakerDevice::stress_test`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Tabled)]
+pub struct StressTestResult {
+    /// Write/read-back cycles, fewer than requested if the test was stopped.
+    pub total: usize,
+    /// Cycles in which a transfer failed, including timeouts.
+    pub errors: usize,
+    pub timeouts: usize,
+    /// Cycles which read back a different value than the one written.
+    pub mismatches: usize,
+    /// Fastest single transfer.
+    pub min_latency_us: u64,
+    /// Slowest single transfer.
+    pub max_latency_us: u64,
+}
+
+impl StressTestResult {
+    /// Failed and mismatching cycles in percent of all cycles.
+    #[must_use]
+    pub fn error_rate_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let rate = (self.errors + self.mismatches) as f64 / self.total as f64 * 100.0;
+        rate
+    }
+}
+
+/// A connected device as found on the bus, see [`list_devices`].
+#[derive(Debug, Clone, Tabled)]
+pub struct UsbDeviceSummary {
+    /// The `-i` argument to select this device.
+    pub index: usize,
+    #[tabled(display = "display_model")]
+    pub model: DeviceModel,
+    pub bus: u8,
+    pub address: u8,
+    pub speed: String,
+    #[tabled(display("tabled::derive::display::option", "unknown"))]
+    pub serial: Option<String>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)] // Signature required by tabled
+fn display_model(model: &DeviceModel) -> String {
+    model.name().to_string()
+}
+
+/// Lists all connected devices in `-i` order without opening them. The serial number is read from sysfs
+/// and therefore only available on Linux.
+pub fn list_devices() -> Result<Vec<UsbDeviceSummary>> {
+    Ok(find_devices()?
+        .into_iter()
+        .enumerate()
+        .map(|(index, (device, model))| UsbDeviceSummary {
+            index,
+            model,
+            bus: device.bus_number(),
+            address: device.address(),
+            speed: format!("{:?}", device.speed()),
+            serial: sysfs_serial(&device),
+        })
+        .collect())
+}
+
+fn find_devices() -> Result<Vec<(Device<GlobalContext>, DeviceModel)>> {
+    info!("Searching for ReSpeaker devices...");
+
+    let mut devices = vec![];
+    for device in rusb::devices()?.iter() {
+        let device_desc = device.device_descriptor()?;
+        if device_desc.vendor_id() != DeviceModel::VENDOR_ID {
+            continue;
+        }
+
+        if let Some(model) = DeviceModel::from_product_id(device_desc.product_id()) {
+            info!(
+                "Found {model:?}: Bus {:03} Device {:03} ID {:04x}:{:04x}, speed: {:?}",
+                device.bus_number(),
+                device.address(),
+                device_desc.vendor_id(),
+                device_desc.product_id(),
+                device.speed()
+            );
+            devices.push((device, model));
+        }
+    }
+    Ok(devices)
+}
+
+/// Whether opening the device failed because another process (usually the audio subsystem) holds it.
+fn is_busy(error: &eyre::Report) -> bool {
+    matches!(error.downcast_ref::<rusb::Error>(), Some(rusb::Error::Busy))
+}
+
+/// Explains [`rusb::Error::Access`], which is almost always a permission problem and not a busy device.
+fn access_hint(error: eyre::Report) -> eyre::Report {
+    if matches!(
+        error.downcast_ref::<rusb::Error>(),
+        Some(rusb::Error::Access)
+    ) {
+        error.wrap_err(
+            "No permission to access the device. Add a udev rule, e.g. \
+             SUBSYSTEM==\"usb\", ATTR{idVendor}==\"2886\", MODE=\"0666\" in \
+             /etc/udev/rules.d/60-respeaker.rules, and replug it",
+        )
+    } else {
+        error
+    }
+}
+
+/// Reading the serial string descriptor needs an open handle, the kernel's cached copy doesn't.
+fn sysfs_serial(device: &Device<GlobalContext>) -> Option<String> {
+    let ports = device
+        .port_numbers()
+        .ok()?
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(".");
+    let path = format!(
+        "/sys/bus/usb/devices/{}-{ports}/serial",
+        device.bus_number()
+    );
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|serial| serial.trim().to_string())
+}
+
+/// Either real hardware or a [`MockDevice`] for tests.
+enum Backend {
+    Usb(DeviceHandle<GlobalContext>),
+    Mock(Arc<MockDevice>),
+}
+
+impl Backend {
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> rusb::Result<usize> {
+        match self {
+            Self::Usb(handle) => {
+                handle.read_control(request_type, request, value, index, buf, timeout)
+            }
+            Self::Mock(mock) => mock.read_control(request_type, request, value, index, buf),
+        }
+    }
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> rusb::Result<usize> {
+        match self {
+            Self::Usb(handle) => {
+                handle.write_control(request_type, request, value, index, buf, timeout)
+            }
+            Self::Mock(mock) => mock.write_control(request_type, request, value, index, buf),
+        }
+    }
+
+    fn claim_interface(&self, interface_number: u8) -> rusb::Result<()> {
+        match self {
+            Self::Usb(handle) => handle.claim_interface(interface_number),
+            Self::Mock(_) => Ok(()),
+        }
+    }
+
+    fn release_interface(&self, interface_number: u8) -> rusb::Result<()> {
+        match self {
+            Self::Usb(handle) => handle.release_interface(interface_number),
+            Self::Mock(_) => Ok(()),
+        }
+    }
+}
+
+impl DeviceInner {
+    fn read_internal(&self, param: &ParamKind, timeout: Duration) -> Result<Value> {
+        let start = Instant::now();
+        let mut buffer = [0u8; 8];
+        self.read_control(param, &mut buffer, timeout)?;
+
+        info!("Read parameter {:?} in {:?}", param, start.elapsed());
+
+        Ok(decode_response(&param.def().param_type, buffer))
+    }
+
+    /// Sends the read request of `param`, returns the number of bytes the device answered with.
+    fn read_control(
+        &self,
+        param: &ParamKind,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        let Some(def) = param.def_for_model(self.model) else {
+            bail!("Parameter {param:?} is not available on {:?}", self.model);
+        };
+
+        let request_type = rusb::request_type(
+            rusb::Direction::In,
+            rusb::RequestType::Vendor,
+            rusb::Recipient::Device,
+        );
+
+        Ok(self.backend.read_control(
+            request_type,
+            0,
+            param.read_usb_cmd(),
+            def.index,
+            buffer,
+            timeout,
+        )?)
+    }
+}
+
+/// Decodes the 8 byte response of a read request. Ints are sent as `(value, 0)`, floats as
+/// `(mantissa, exponent)` with `value = mantissa * 2^exponent`.
+///
+/// The device is not trusted: negative ints are clamped to 0 and floats which don't fit into a finite
+/// `f32` are clamped to `f32::MIN..=f32::MAX` (NaN becomes 0), so the result is always finite.
+#[must_use]
+pub fn decode_response(param_type: &ParamType, buffer: [u8; 8]) -> Value {
+    let [a0, a1, a2, a3, b0, b1, b2, b3] = buffer;
+    let response = (
+        i32::from_le_bytes([a0, a1, a2, a3]),
+        i32::from_le_bytes([b0, b1, b2, b3]),
+    );
+
+    if param_type.is_int() {
+        if response.0 < 0 {
+            warn!("Device returned negative int {}, using 0", response.0);
+        }
+        #[allow(clippy::cast_sign_loss)]
+        return Value::Int(response.0.max(0) as usize);
+    }
+
+    let float = f64::from(response.0) * f64::from(response.1).exp2();
+    if !float.is_finite() || float.abs() > f64::from(f32::MAX) {
+        warn!("Device returned out of range float {response:?}, clamping");
+    }
+    // 0 * 2^huge is NaN
+    let float = if float.is_nan() {
+        0.0
+    } else {
+        float.clamp(f64::from(f32::MIN), f64::from(f32::MAX))
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    Value::Float(float as f32)
+}
+
 #[derive(Tabled)]
 struct TableRow {
     name: String,
@@ -295,6 +1384,104 @@ struct TableRow {
     t: String,
     access: String,
     range: String,
+    since: String,
     description: String,
     values: String,
 }
+
+impl TableRow {
+    fn new(param: &ParamKind, value: &Value) -> Self {
+        let def = param.def();
+        Self {
+            name: format!("{param:?}"),
+            value: value.clone(),
+            t: if def.param_type.is_int() {
+                "int"
+            } else {
+                "float"
+            }
+            .to_string(),
+            access: if def.access == Access::ReadOnly {
+                "ro"
+            } else {
+                "rw"
+            }
+            .to_string(),
+            range: format!("{}..{}", def.min(), def.max()),
+            since: def.firmware_since.unwrap_or("-").to_string(),
+            description: def.description.to_string(),
+            values: def.value_descriptions.join("\n"),
+        }
+    }
+}
+
+/// Width of the value in [`ExportFormat::Compact`], longer option names are cut.
+const COMPACT_VALUE_WIDTH: usize = 24;
+
+/// `HPFONOFF             = 1 (ON - 70 Hz cut-off)   [0..3]             rw`, RW discrete parameters with
+/// the name of the current option.
+fn compact_line(param: &ParamKind, value: &Value) -> String {
+    let def = param.def();
+    let value = match (&def.param_type, def.access) {
+        (ParamType::IntDiscete { .. }, Access::ReadWrite) => value.to_display_string(&def),
+        _ => value.to_string(),
+    };
+    let value = if value.chars().count() > COMPACT_VALUE_WIDTH {
+        let cut = value
+            .chars()
+            .take(COMPACT_VALUE_WIDTH - 2)
+            .collect::<String>();
+        format!("{cut}…)")
+    } else {
+        value
+    };
+    let access = match def.access {
+        Access::ReadOnly => "ro",
+        Access::ReadWrite => "rw",
+    };
+    format!(
+        "{:<20} = {value:<COMPACT_VALUE_WIDTH$} {:<18} {access}",
+        format!("{param:?}"),
+        format!("[{}..{}]", def.min(), def.max()),
+    )
+}
+
+/// Columns of the CSV and JSON exports.
+const EXPORT_COLUMNS: [&str; 7] = [
+    "name",
+    "value",
+    "type",
+    "access",
+    "range",
+    "since",
+    "description",
+];
+
+/// Output format of [`ReSpeakerDevice::export_to_writer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// The table of `list`.
+    #[default]
+    Table,
+    Json,
+    Csv,
+    /// A snapshot with `[read_write]` and `[read_only]` sections.
+    Toml,
+    /// A Markdown table for documentation, see [`crate::params::ParamDef::to_markdown_table_row`].
+    Markdown,
+    /// One parameter per line without descriptions, fits into 80 columns.
+    Compact,
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Table => "table",
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Toml => "toml",
+            Self::Markdown => "markdown",
+            Self::Compact => "compact",
+        })
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{IpAddr, Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+use crate::{
+    recorder::{activity_summary, record_respeaker_parameters_async, RecordingStats},
+    respeaker_device::ReSpeakerDevice,
+};
+
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    pub record_path: PathBuf,
+    /// Serve Prometheus metrics on `<bind>:<port>/metrics`.
+    pub serve_port: Option<u16>,
+    /// Address of the metrics server, `127.0.0.1` if `None`.
+    pub bind: Option<IpAddr>,
+    /// Stop after this many seconds, otherwise only on Ctrl-C.
+    pub seconds: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionSummary {
+    pub duration: Duration,
+    pub recording: RecordingStats,
+    /// Answered `/metrics` requests.
+    pub scrapes: u64,
+}
+
+/// Records to CSV and serves Prometheus metrics at the same time until `running` is false or
+/// `options.seconds` have passed. Both tasks are then stopped and the CSV file is flushed.
+///
+/// Must be called from within a tokio runtime.
+pub async fn run_session(
+    device: &ReSpeakerDevice,
+    options: &SessionOptions,
+    running: &Arc<AtomicBool>,
+) -> eyre::Result<SessionSummary> {
+    let start = Instant::now();
+    // Ctrl-C and the duration both end the session through this flag
+    let active = Arc::new(AtomicBool::new(true));
+
+    let server = match options.serve_port {
+        Some(port) => {
+            let address = options.bind.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+            let listener = TcpListener::bind((address, port))?;
+            listener.set_nonblocking(true)?;
+            info!(
+                "Serving metrics on http://{}/metrics",
+                listener.local_addr()?
+            );
+            let (device, active) = (device.clone(), active.clone());
+            // std networking, tokio's `net` feature is not enabled
+            Some(tokio::task::spawn_blocking(move || {
+                serve_metrics(&listener, &device, &active)
+            }))
+        }
+        None => None,
+    };
+
+    let recorder = {
+        let (device, active, path) = (device.clone(), active.clone(), options.record_path.clone());
+        tokio::spawn(async move {
+            record_respeaker_parameters_async(None, &path, &device, &active).await
+        })
+    };
+
+    let mut ticks = tokio::time::interval(SHUTDOWN_POLL_INTERVAL);
+    while running.load(Ordering::SeqCst)
+        && start.elapsed().as_secs_f32() < options.seconds.unwrap_or(f32::INFINITY)
+        && !recorder.is_finished()
+    {
+        ticks.tick().await;
+    }
+    active.store(false, Ordering::SeqCst);
+
+    let recording = recorder.await??;
+    let scrapes = match server {
+        Some(server) => server.await??,
+        None => 0,
+    };
+    Ok(SessionSummary {
+        duration: start.elapsed(),
+        recording,
+        scrapes,
+    })
+}
+
+/// Human readable summary printed at the end of `respeaker session`.
+#[must_use]
+pub fn session_summary(device: &ReSpeakerDevice, summary: &SessionSummary) -> String {
+    format!(
+        "Session ended after {:.1} s: {} rows recorded, {} metrics scrapes. {}",
+        summary.duration.as_secs_f32(),
+        summary.recording.rows,
+        summary.scrapes,
+        activity_summary(device)
+    )
+}
+
+/// Answers `GET /metrics` with [`crate::params::ParamState::to_prometheus_text`] and the USB transfer
+/// metrics of the device until `active` is false. Returns the number of answered scrapes.
+fn serve_metrics(
+    listener: &TcpListener,
+    device: &ReSpeakerDevice,
+    active: &AtomicBool,
+) -> eyre::Result<u64> {
+    let mut scrapes = 0;
+    while active.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => match handle_request(&stream, device) {
+                Ok(true) => scrapes += 1,
+                Ok(false) => {}
+                Err(e) => warn!("Metrics request failed: {e}"),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(SHUTDOWN_POLL_INTERVAL),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(scrapes)
+}
+
+/// Returns whether the request was a metrics scrape.
+fn handle_request(mut stream: &TcpStream, device: &ReSpeakerDevice) -> eyre::Result<bool> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request_line = String::new();
+    BufReader::new(stream).read_line(&mut request_line)?;
+    let is_metrics = request_line.starts_with("GET /metrics ");
+    let (status, body) = if is_metrics {
+        let mut text = device
+            .params()
+            .lock()
+            .expect("Lock failed")
+            .to_prometheus_text();
+        text.push_str(&device.metrics().to_prometheus_text());
+        ("200 OK", text)
+    } else {
+        ("404 Not Found", "Not found, try /metrics\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(is_metrics)
+}
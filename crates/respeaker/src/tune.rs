@@ -0,0 +1,196 @@
+use std::io::{BufRead, Write};
+
+use clap::ValueEnum;
+use eyre::{bail, eyre};
+
+use crate::{
+    params::{ParamKind, Value},
+    respeaker_device::ReSpeakerDevice,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scenario {
+    /// Far-field speech recognition, e.g. a smart speaker or Home Assistant satellite.
+    VoiceAssistant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NoiseSuppressionLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl NoiseSuppressionLevel {
+    /// `(GAMMA_NS, MIN_NS)`. Medium is the firmware default.
+    #[must_use]
+    pub const fn settings(self) -> (f32, f32) {
+        match self {
+            // -10 dB gain floor
+            Self::Low => (1.0, 0.3),
+            // -16 dB
+            Self::Medium => (1.0, 0.15),
+            // -26 dB
+            Self::High => (2.0, 0.05),
+        }
+    }
+}
+
+/// Guides the user through the settings of `scenario`. Questions are read from `input` and everything
+/// else is written to `output`, so the wizard can run on stdin / stderr while stdout stays clean.
+pub fn run_tune(
+    device: &ReSpeakerDevice,
+    scenario: Scenario,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> eyre::Result<()> {
+    match scenario {
+        Scenario::VoiceAssistant => voice_assistant(device, input, output),
+    }
+}
+
+fn voice_assistant(
+    device: &ReSpeakerDevice,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> eyre::Result<()> {
+    writeln!(output, "Step 1/3: Checking the device")?;
+    let check = device.check_firmware_compat()?;
+    if !check.compatible {
+        bail!(
+            "The firmware seems to be incompatible: {}",
+            check.warnings.join(", ")
+        );
+    }
+    let info = device.device_info();
+    writeln!(
+        output,
+        "Found {:?}, firmware {}",
+        device.model(),
+        info.firmware.as_deref().unwrap_or("unknown")
+    )?;
+
+    writeln!(output)?;
+    writeln!(output, "Step 2/3: Noise suppression")?;
+    show_current(
+        device,
+        &[
+            ParamKind::STATNOISEONOFF,
+            ParamKind::GAMMA_NS,
+            ParamKind::MIN_NS,
+        ],
+        output,
+    )?;
+    writeln!(
+        output,
+        "Suppressing stationary noise (fans, air conditioning) helps speech recognition in noisy rooms."
+    )?;
+    writeln!(
+        output,
+        "Stronger suppression removes more noise but also distorts quiet speech."
+    )?;
+    if ask_yes_no(input, output, "Enable noise suppression?")? {
+        let level = ask_choice(input, output, "Aggressiveness")?;
+        let (gamma, min) = NoiseSuppressionLevel::settings(level);
+        write(device, &ParamKind::STATNOISEONOFF, &Value::Int(1), output)?;
+        write(device, &ParamKind::GAMMA_NS, &Value::Float(gamma), output)?;
+        write(device, &ParamKind::MIN_NS, &Value::Float(min), output)?;
+    } else {
+        write(device, &ParamKind::STATNOISEONOFF, &Value::Int(0), output)?;
+    }
+
+    writeln!(output)?;
+    writeln!(output, "Step 3/3: Beamforming")?;
+    show_current(device, &[ParamKind::FREEZEONOFF], output)?;
+    writeln!(
+        output,
+        "The beamformer adapts to where the speaker is. If the speaker is always at the same place,"
+    )?;
+    writeln!(
+        output,
+        "freezing the adaptation keeps it from drifting towards noise sources. Speak from that place"
+    )?;
+    writeln!(output, "for a few seconds before answering.")?;
+    let fixed = ask_yes_no(input, output, "Is there a fixed source direction?")?;
+    write(
+        device,
+        &ParamKind::FREEZEONOFF,
+        &Value::Int(usize::from(fixed)),
+        output,
+    )?;
+
+    writeln!(output)?;
+    writeln!(output, "Done")?;
+    Ok(())
+}
+
+fn show_current(
+    device: &ReSpeakerDevice,
+    params: &[ParamKind],
+    output: &mut impl Write,
+) -> eyre::Result<()> {
+    for param in params {
+        let value = device.read(param)?;
+        writeln!(
+            output,
+            "  Current {param:?} = {}",
+            value.to_display_string(&param.def())
+        )?;
+    }
+    Ok(())
+}
+
+fn write(
+    device: &ReSpeakerDevice,
+    param: &ParamKind,
+    value: &Value,
+    output: &mut impl Write,
+) -> eyre::Result<()> {
+    device.write(param, value)?;
+    writeln!(output, "  Set {param:?} = {value}")?;
+    Ok(())
+}
+
+fn ask_yes_no(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+) -> eyre::Result<bool> {
+    loop {
+        match ask(input, output, &format!("{question} [y/n]"))?.as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => writeln!(output, "Please answer y or n")?,
+        }
+    }
+}
+
+fn ask_choice<T: ValueEnum>(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+) -> eyre::Result<T> {
+    let names = T::value_variants()
+        .iter()
+        .filter_map(ValueEnum::to_possible_value)
+        .map(|v| v.get_name().to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    loop {
+        let answer = ask(input, output, &format!("{question} [{names}]"))?;
+        match T::from_str(&answer, true) {
+            Ok(choice) => return Ok(choice),
+            Err(_) => writeln!(output, "Please answer one of {names}")?,
+        }
+    }
+}
+
+fn ask(input: &mut impl BufRead, output: &mut impl Write, question: &str) -> eyre::Result<String> {
+    write!(output, "{question}: ")?;
+    output.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Err(eyre!("Aborted, no answer to \"{question}\""));
+    }
+    Ok(answer.trim().to_lowercase())
+}
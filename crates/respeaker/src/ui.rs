@@ -1,24 +1,54 @@
 use std::{
+    collections::HashSet,
+    fs,
+    path::PathBuf,
     sync::{mpsc, Arc, Mutex},
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use eframe::egui;
-use eyre::{eyre, Ok, OptionExt};
+#[cfg(feature = "serde")]
+use eyre::OptionExt;
+use eyre::{eyre, Ok};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+#[cfg(feature = "serde")]
+use tracing::warn;
 use tracing::{error, info};
 
 use crate::{
-    params::{Access, ParamKind, ParamType, Value},
+    analysis::DoaSector,
+    config::{load_config, save_config},
+    csv::CsvWriter,
+    params::{
+        Access, ParamCategory, ParamDef, ParamKind, ParamSortOrder, ParamState, ParamType, Value,
+    },
     respeaker_device::ReSpeakerDevice,
 };
 
+const DEFAULT_WINDOW_SIZE: [f32; 2] = [1000.0, 1000.0];
+const DEFAULT_REFRESH_RATE_MS: u64 = 50;
+const DOCUMENTATION_URL: &str = "https://github.com/brookman/respeaker-rs";
+const SHORTCUTS: &[(&str, &str)] = &[
+    ("Ctrl+L", "Open config"),
+    ("Ctrl+S", "Save config as"),
+    ("Ctrl+R", "Reset device (asks for confirmation)"),
+    ("Esc", "Close dialog"),
+];
+
 pub fn run_ui(device: ReSpeakerDevice) -> eyre::Result<()> {
-    let device = Arc::new(Mutex::new(device));
-    let ui_state = UiState::new(device.clone())?;
+    let mut ui_state = UiState::new(device.clone())?;
+    let persisted = PersistedUi::load();
 
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(persisted.inner_size.unwrap_or(DEFAULT_WINDOW_SIZE));
+    if let Some(position) = persisted.position {
+        viewport = viewport.with_position(position);
+    }
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([1000.0, 1000.0]),
+        viewport,
         ..Default::default()
     };
 
@@ -31,20 +61,27 @@ pub fn run_ui(device: ReSpeakerDevice) -> eyre::Result<()> {
         options,
         Box::new(|cc| {
             let ctx = cc.egui_ctx.clone();
+            ctx.memory_mut(|memory| *memory = persisted.memory);
+            ui_state.ctx = ctx.clone();
+            let inner = ui_state.inner.clone();
 
             join_handle = Some(thread::spawn(move || {
                 loop {
+                    let start = Instant::now();
                     if shutdown_rx.try_recv().is_ok() {
                         info!("Refresh thread is shutting down");
                         break;
                     }
-                    {
-                        let device = device.lock().expect("Lock failed");
-                        device.read_ro()?;
-                    }
+                    device.read_ro()?;
                     ctx.request_repaint();
 
-                    thread::sleep(Duration::from_millis(50));
+                    // 0 means as fast as possible, but don't busy loop
+                    let refresh_rate_ms = inner.lock().expect("Lock failed").refresh_rate_ms;
+                    thread::sleep(Duration::from_millis(refresh_rate_ms.max(1)));
+                    inner
+                        .lock()
+                        .expect("Lock failed")
+                        .update_actual_refresh(start.elapsed());
                 }
                 Ok(())
             }));
@@ -72,14 +109,147 @@ pub fn run_ui(device: ReSpeakerDevice) -> eyre::Result<()> {
     result
 }
 
+/// Window geometry and egui memory (e.g. open/closed state of collapsing headers), restored on the next start.
+/// Without the serde feature nothing is persisted.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct PersistedUi {
+    inner_size: Option<[f32; 2]>,
+    position: Option<[f32; 2]>,
+    memory: egui::Memory,
+}
+
+impl PersistedUi {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("respeaker").join("ui_state.json"))
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn load() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "serde")]
+    fn load() -> Self {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path)
+            .map_err(eyre::Report::from)
+            .and_then(|json| Ok(serde_json::from_str(&json)?))
+        {
+            std::result::Result::Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("Could not load UI state from {path:?}: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    #[cfg(not(feature = "serde"))]
+    #[allow(clippy::unnecessary_wraps)]
+    fn save(_ctx: &egui::Context) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(ctx: &egui::Context) -> eyre::Result<()> {
+        let path = Self::path().ok_or_eyre("No config directory found")?;
+        let (inner_size, position) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.inner_rect.map(|r| [r.width(), r.height()]),
+                viewport.outer_rect.map(|r| [r.min.x, r.min.y]),
+            )
+        });
+        let persisted = Self {
+            inner_size,
+            position,
+            memory: ctx.memory(Clone::clone),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(&persisted)?)?;
+        Ok(())
+    }
+
+    fn reset(ctx: &egui::Context) -> eyre::Result<()> {
+        if let Some(path) = Self::path().filter(|path| path.exists()) {
+            fs::remove_file(path)?;
+        }
+        ctx.memory_mut(|memory| *memory = egui::Memory::default());
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(DEFAULT_WINDOW_SIZE.into()));
+        Ok(())
+    }
+}
+
 struct UiState {
-    device: Arc<Mutex<ReSpeakerDevice>>,
+    device: ReSpeakerDevice,
+    scroll_to: Option<ParamKind>,
+    ctx: egui::Context,
+    inner: Arc<Mutex<InnerUiState>>,
+    confirm_reset: bool,
+    show_about: bool,
+    view: ViewOptions,
+    /// Open file dialog, answered with the picked path or `None` if it was cancelled.
+    file_dialog: Option<(FileAction, mpsc::Receiver<Option<PathBuf>>)>,
+    /// Previous sector for the hysteresis of the status bar's DOA display.
+    doa_sector: Option<DoaSector>,
+}
+
+#[derive(Default)]
+struct ViewOptions {
+    hidden_categories: HashSet<ParamCategory>,
+    show_raw_ids: bool,
+    compact: bool,
+    sort: ParamSortOrder,
+}
+
+#[derive(Clone, Copy)]
+enum FileAction {
+    OpenConfig,
+    SaveConfig,
+    ExportCsv,
+}
+
+/// State shared between the UI and the refresh thread.
+struct InnerUiState {
+    /// Pause between two reads of the RO parameters.
+    refresh_rate_ms: u64,
+    /// Smoothed duration of one refresh iteration, including the USB reads.
+    actual_refresh: Duration,
+}
+
+impl Default for InnerUiState {
+    fn default() -> Self {
+        Self {
+            refresh_rate_ms: DEFAULT_REFRESH_RATE_MS,
+            actual_refresh: Duration::from_millis(DEFAULT_REFRESH_RATE_MS),
+        }
+    }
+}
+
+impl InnerUiState {
+    fn update_actual_refresh(&mut self, elapsed: Duration) {
+        self.actual_refresh = self.actual_refresh.mul_f32(0.9) + elapsed.mul_f32(0.1);
+    }
 }
 
 impl UiState {
-    fn new(device: Arc<Mutex<ReSpeakerDevice>>) -> eyre::Result<Self> {
-        device.lock().expect("Lock failed").list()?;
-        Ok(Self { device })
+    fn new(device: ReSpeakerDevice) -> eyre::Result<Self> {
+        device.list()?;
+        Ok(Self {
+            device,
+            scroll_to: None,
+            ctx: egui::Context::default(),
+            inner: Arc::default(),
+            confirm_reset: false,
+            show_about: false,
+            view: ViewOptions::default(),
+            file_dialog: None,
+            doa_sector: None,
+        })
     }
 }
 
@@ -89,88 +259,105 @@ impl eframe::App for UiState {
             error!("Error during UI update: {e:?}");
         }
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Err(e) = PersistedUi::save(&self.ctx) {
+            error!("Could not save UI state: {e:?}");
+        }
+    }
 }
 
-fn update_internal(ui_state: &UiState, ctx: &egui::Context) -> eyre::Result<()> {
+fn update_internal(ui_state: &mut UiState, ctx: &egui::Context) -> eyre::Result<()> {
     let mut params = {
         ui_state
             .device
-            .lock()
-            .expect("Lock failed")
             .params()
             .lock()
             .expect("Lock failed")
             .clone()
     };
     let params_cloned = params.clone();
+    let scroll_to = ui_state.scroll_to.take();
+
+    let (reset_pressed, escape_pressed, file_shortcut) = ctx.input(|i| {
+        let file_shortcut = if i.modifiers.command && i.key_pressed(egui::Key::L) {
+            Some(FileAction::OpenConfig)
+        } else if i.modifiers.command && i.key_pressed(egui::Key::S) {
+            Some(FileAction::SaveConfig)
+        } else {
+            None
+        };
+        (
+            i.modifiers.command && i.key_pressed(egui::Key::R),
+            i.key_pressed(egui::Key::Escape),
+            file_shortcut,
+        )
+    });
+    if reset_pressed {
+        ui_state.confirm_reset = true;
+    }
+    // Only one file dialog at a time
+    if let Some(action) = file_shortcut.filter(|_| ui_state.file_dialog.is_none()) {
+        ui_state.file_dialog = Some((action, open_file_dialog(action)));
+    }
+    if escape_pressed {
+        ui_state.confirm_reset = false;
+    }
+
+    menu_bar(ui_state, ctx)?;
+    if ui_state.confirm_reset {
+        reset_dialog(ui_state, ctx)?;
+    }
+    if ui_state.show_about {
+        about_dialog(ui_state, ctx);
+    }
+    handle_file_dialog(ui_state, &params)?;
+
+    let doa = match params.current_params.get(&ParamKind::DOAANGLE) {
+        Some(Value::Int(angle)) => {
+            let angle = u16::try_from(*angle).unwrap_or(u16::MAX);
+            let sector = DoaSector::with_hysteresis(angle, ui_state.doa_sector);
+            ui_state.doa_sector = Some(sector);
+            format!(" | DOA: {} {angle}°", sector.icon())
+        }
+        _ => String::new(),
+    };
+    egui::TopBottomPanel::bottom("Status bar").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            let (read_write, read_only) = params.count_by_access();
+            ui.label(format!(
+                "RW: {read_write} | RO: {read_only} | Speech: {} events | VAD: {} events{doa}",
+                params.speech_detection_count, params.voice_activity_count
+            ));
+            if ui.small_button("Reset counters").clicked() {
+                ui_state
+                    .device
+                    .params()
+                    .lock()
+                    .expect("Lock failed")
+                    .reset_counters();
+            }
+        });
+    });
 
     egui::CentralPanel::default()
         .show(ctx, |ui| {
             ui.heading("Unofficial CLI & UI for the ReSpeaker Mic Array v2.0");
-            egui::Grid::new("Parameter grid")
+            settings(ui, &ui_state.inner, &mut ui_state.view.sort);
+            egui::ScrollArea::vertical()
                 .show(ui, |ui| {
-                    for param in ParamKind::sorted() {
-                        let def = param.def();
-                        let value = params
-                            .current_params
-                            .get_mut(&param)
-                            .ok_or_eyre("Param not found")?;
-
-                        ui.label(format!("{param:?}"));
-                        match value {
-                            Value::Int(i) => {
-                                ui.horizontal(|ui| match def.param_type {
-                                    ParamType::IntRange { min, max } => {
-                                        ui.add_enabled(
-                                            def.access == Access::ReadWrite,
-                                            egui::Slider::new(i, min..=max)
-                                                .text(format!("{min}..={max}")),
-                                        );
-                                    }
-                                    ParamType::IntDiscete { min: _, max: _ } => {
-                                        if def.access == Access::ReadWrite {
-                                            egui::ComboBox::from_id_salt(param)
-                                                .selected_text(def.value_descriptions[*i])
-                                                .show_ui(ui, |ui| {
-                                                    for (e, v) in
-                                                        def.value_descriptions.iter().enumerate()
-                                                    {
-                                                        ui.selectable_value(i, e, *v);
-                                                    }
-                                                });
-                                        } else {
-                                            ui.label(def.value_descriptions[*i]);
-                                        }
-                                    }
-                                    ParamType::FloatRange { min: _, max: _ } => unreachable!(),
-                                });
-                                ui.label(def.description);
-                            }
-                            Value::Float(f) => match def.param_type {
-                                ParamType::FloatRange { min, max } => {
-                                    ui.horizontal(|ui| {
-                                        ui.add_enabled(
-                                            def.access == Access::ReadWrite,
-                                            egui::Slider::new(f, min..=max)
-                                                .text(format!("{min}..={max}")),
-                                        );
-                                    });
-                                    ui.label(def.description);
-                                }
-                                _ => unreachable!(),
-                            },
-                        }
-                        ui.end_row();
-                    }
-                    Ok(())
+                    param_grid(
+                        ui,
+                        &mut params,
+                        &ui_state.view,
+                        scroll_to.as_ref(),
+                        &mut ui_state.scroll_to,
+                    )
                 })
                 .inner?;
             if ui.button("Reset device").clicked() {
-                {
-                    let mut device = ui_state.device.lock().expect("Lock failed");
-                    device.reset()?;
-                    device.list()?;
-                }
+                ui_state.device.reset()?;
+                ui_state.device.list()?;
             }
 
             // if ui.button("Record CSV").clicked() {
@@ -191,11 +378,372 @@ fn update_internal(ui_state: &UiState, ctx: &egui::Context) -> eyre::Result<()>
         if new != old {
             info!("Value has changed: {p:?}, old={}, new={}", old, new);
 
-            {
-                let device = ui_state.device.lock().expect("Lock failed");
-                device.write(p, new)?;
+            ui_state.device.write(p, new)?;
+        }
+    }
+    Ok(())
+}
+
+fn menu_bar(ui_state: &mut UiState, ctx: &egui::Context) -> eyre::Result<()> {
+    let mut reset_layout = false;
+    egui::TopBottomPanel::top("Menu bar").show(ctx, |ui| {
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                for (label, action) in [
+                    ("Open config...", FileAction::OpenConfig),
+                    ("Save config as...", FileAction::SaveConfig),
+                    ("Export CSV...", FileAction::ExportCsv),
+                ] {
+                    if ui.button(label).clicked() {
+                        ui_state.file_dialog = Some((action, open_file_dialog(action)));
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                if ui.button("Exit").clicked() {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+            ui.menu_button("View", |ui| {
+                for category in ParamCategory::iter() {
+                    let mut visible = !ui_state.view.hidden_categories.contains(&category);
+                    if ui.checkbox(&mut visible, category.name()).changed() {
+                        if visible {
+                            ui_state.view.hidden_categories.remove(&category);
+                        } else {
+                            ui_state.view.hidden_categories.insert(category);
+                        }
+                    }
+                }
+                ui.separator();
+                ui.checkbox(&mut ui_state.view.show_raw_ids, "Show raw IDs");
+                ui.checkbox(&mut ui_state.view.compact, "Compact view");
+                ui.separator();
+                if ui.button("Reset UI layout").clicked() {
+                    reset_layout = true;
+                    ui.close_menu();
+                }
+            });
+            ui.menu_button("Help", |ui| {
+                if ui.button("About").clicked() {
+                    ui_state.show_about = true;
+                    ui.close_menu();
+                }
+                ui.menu_button("Keyboard shortcuts", |ui| {
+                    egui::Grid::new("Shortcuts").show(ui, |ui| {
+                        for (keys, action) in SHORTCUTS {
+                            ui.strong(*keys);
+                            ui.label(*action);
+                            ui.end_row();
+                        }
+                    });
+                });
+                ui.hyperlink_to("Open documentation", DOCUMENTATION_URL);
+            });
+        });
+    });
+    if reset_layout {
+        PersistedUi::reset(ctx)?;
+    }
+    Ok(())
+}
+
+/// Shows the native file dialog without blocking the UI thread.
+fn open_file_dialog(action: FileAction) -> mpsc::Receiver<Option<PathBuf>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let dialog = rfd::AsyncFileDialog::new();
+        let file = match action {
+            FileAction::OpenConfig => {
+                pollster::block_on(dialog.add_filter("TOML", &["toml"]).pick_file())
             }
+            FileAction::SaveConfig => pollster::block_on(
+                dialog
+                    .add_filter("TOML", &["toml"])
+                    .set_file_name("respeaker.toml")
+                    .save_file(),
+            ),
+            FileAction::ExportCsv => pollster::block_on(
+                dialog
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name("respeaker.csv")
+                    .save_file(),
+            ),
+        };
+        // The UI may already be closed
+        let _ = tx.send(file.map(|file| file.path().to_path_buf()));
+    });
+    rx
+}
+
+fn handle_file_dialog(ui_state: &mut UiState, params: &ParamState) -> eyre::Result<()> {
+    let Some((action, rx)) = &ui_state.file_dialog else {
+        return Ok(());
+    };
+    let path = match rx.try_recv() {
+        std::result::Result::Ok(path) => path,
+        Err(mpsc::TryRecvError::Empty) => return Ok(()),
+        Err(mpsc::TryRecvError::Disconnected) => None,
+    };
+    let action = *action;
+    ui_state.file_dialog = None;
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    match action {
+        FileAction::OpenConfig => {
+            for (param, value) in load_config(&path)? {
+                ui_state.device.write(&param, &value)?;
+            }
+            ui_state.device.list()?;
+            info!("Loaded config from {path:?}");
+        }
+        FileAction::SaveConfig => {
+            save_config(&path, params)?;
+            info!("Saved config to {path:?}");
+        }
+        FileAction::ExportCsv => {
+            let timestamp = chrono::Local::now().format("%+").to_string();
+            let mut writer = CsvWriter::new(&path)?;
+            writer.write_row(&timestamp, &timestamp, &params.current_params)?;
+            writer.finish()?;
+            info!("Exported current values to {path:?}");
         }
     }
     Ok(())
 }
+
+fn about_dialog(ui_state: &mut UiState, ctx: &egui::Context) {
+    let info = ui_state.device.device_info();
+    let mut open = true;
+    egui::Window::new("About")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!("respeaker-rs {}", env!("CARGO_PKG_VERSION")));
+            ui.separator();
+            egui::Grid::new("Device info").show(ui, |ui| {
+                for (key, value) in [
+                    ("Model", ui_state.device.model().name().to_string()),
+                    (
+                        "Bus / address",
+                        format!("{:03}/{:03}", info.bus, info.address),
+                    ),
+                    ("Serial", info.serial.unwrap_or_else(|| "unknown".into())),
+                    (
+                        "Firmware",
+                        info.firmware.unwrap_or_else(|| "unknown".into()),
+                    ),
+                ] {
+                    ui.strong(key);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+        });
+    ui_state.show_about = open;
+}
+
+fn reset_dialog(ui_state: &mut UiState, ctx: &egui::Context) -> eyre::Result<()> {
+    let mut reset = false;
+    egui::Window::new("Reset device?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label("All parameters will be set to their defaults.");
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    reset = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    ui_state.confirm_reset = false;
+                }
+            });
+        });
+    if reset {
+        ui_state.confirm_reset = false;
+        ui_state.device.reset()?;
+        ui_state.device.list()?;
+    }
+    Ok(())
+}
+
+fn settings(ui: &mut egui::Ui, inner: &Mutex<InnerUiState>, sort: &mut ParamSortOrder) {
+    egui::CollapsingHeader::new("Settings").show(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Sort by:");
+            egui::ComboBox::from_id_salt("Sort by")
+                .selected_text(sort.name())
+                .show_ui(ui, |ui| {
+                    for order in ParamSortOrder::iter() {
+                        ui.selectable_value(sort, order, order.name());
+                    }
+                });
+        });
+        let mut inner = inner.lock().expect("Lock failed");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut inner.refresh_rate_ms, 0..=1000)
+                    .text("Refresh rate of RO parameters (ms)"),
+            );
+            ui.label(format!(
+                "Actual refresh: ~{} ms",
+                inner.actual_refresh.as_millis()
+            ));
+        });
+    });
+}
+
+/// Shows one row per parameter. `scroll_to` scrolls to a parameter, clicked related-parameter links are
+/// stored in `clicked_link`.
+fn param_grid(
+    ui: &mut egui::Ui,
+    params: &mut ParamState,
+    view: &ViewOptions,
+    scroll_to: Option<&ParamKind>,
+    clicked_link: &mut Option<ParamKind>,
+) -> eyre::Result<()> {
+    if view.compact {
+        ui.spacing_mut().item_spacing.y = 1.0;
+        ui.spacing_mut().interact_size.y = 14.0;
+    }
+    egui::Grid::new("Parameter grid")
+        .show(ui, |ui| {
+            for param in view.sort.sorted() {
+                if view.hidden_categories.contains(&param.category()) {
+                    continue;
+                }
+                let def = param.def();
+                // Not available on this device model
+                let Some(value) = params.current_params.get_mut(&param) else {
+                    continue;
+                };
+
+                let name = ui
+                    .horizontal(|ui| {
+                        let name = ui.label(format!("{param:?}"));
+                        if param.is_related_to_asr() {
+                            ui.small("ASR").on_hover_text(
+                                "Only affects the processed channel for speech recognition",
+                            );
+                        }
+                        name
+                    })
+                    .inner;
+                if scroll_to == Some(&param) {
+                    name.scroll_to_me(Some(egui::Align::Center));
+                }
+                if view.show_raw_ids {
+                    ui.monospace(format!("({}, {})", def.index, def.cmd));
+                }
+                match value {
+                    Value::Int(i) => {
+                        ui.horizontal(|ui| {
+                            int_widget(ui, &param, &def, i);
+                            if let Some(Value::Int(default)) =
+                                reset_button(ui, &def, &Value::Int(*i))
+                            {
+                                *i = default;
+                            }
+                        });
+                        ui.label(def.description);
+                    }
+                    Value::Float(f) => match def.param_type {
+                        ParamType::FloatRange { min, max } => {
+                            ui.horizontal(|ui| {
+                                ui.add_enabled(
+                                    def.access == Access::ReadWrite,
+                                    egui::Slider::new(f, min..=max).text(format!("{min}..={max}")),
+                                );
+                                if let Some(Value::Float(default)) =
+                                    reset_button(ui, &def, &Value::Float(*f))
+                                {
+                                    *f = default;
+                                }
+                            });
+                            ui.label(def.description);
+                        }
+                        _ => unreachable!(),
+                    },
+                }
+                ui.horizontal(|ui| {
+                    for related in def.related_params {
+                        if ui.link(format!("{related:?}")).clicked() {
+                            *clicked_link = Some(related.clone());
+                        }
+                    }
+                });
+                ui.end_row();
+            }
+            Ok(())
+        })
+        .inner
+}
+
+fn int_widget(ui: &mut egui::Ui, param: &ParamKind, def: &ParamDef, i: &mut usize) {
+    match def.param_type {
+        ParamType::IntRange { min, max } => {
+            ui.add_enabled(
+                def.access == Access::ReadWrite,
+                egui::Slider::new(i, min..=max).text(format!("{min}..={max}")),
+            );
+        }
+        ParamType::IntDiscete { min: _, max: _ }
+            if def.access == Access::ReadWrite && def.param_type.is_binary() =>
+        {
+            let mut checked = *i == 1;
+            if ui
+                .checkbox(&mut checked, def.value_descriptions[*i])
+                .changed()
+            {
+                *i = usize::from(checked);
+            }
+        }
+        ParamType::IntDiscete { min: _, max: _ } => {
+            if def.access == Access::ReadWrite {
+                egui::ComboBox::from_id_salt(param)
+                    .selected_text(def.value_descriptions[*i])
+                    .show_ui(ui, |ui| {
+                        for (e, v) in def.value_descriptions.iter().enumerate() {
+                            ui.selectable_value(i, e, *v);
+                        }
+                    });
+            } else {
+                ui.label(def.value_descriptions[*i]);
+            }
+        }
+        ParamType::FloatRange { min: _, max: _ } => {
+            unreachable!()
+        }
+    }
+}
+
+/// Small `↺` button next to a RW parameter, disabled if the value already is the documented default (or
+/// there is none). Returns the default if it was clicked, the grid's change detection then writes it.
+fn reset_button(ui: &mut egui::Ui, def: &ParamDef, value: &Value) -> Option<Value> {
+    if def.access != Access::ReadWrite {
+        return None;
+    }
+    let default = def.default_value();
+    let enabled = default
+        .as_ref()
+        .is_some_and(|default| value.changed_from(default));
+    let mut text = egui::RichText::new("↺");
+    if !enabled {
+        text = text.color(ui.visuals().weak_text_color());
+    }
+    let response = ui.add_enabled(enabled, egui::Button::new(text).small());
+    let response = match &default {
+        Some(default) => {
+            let text = format!("Reset to default ({default})");
+            response
+                .on_hover_text(text.clone())
+                .on_disabled_hover_text(text)
+        }
+        None => response.on_disabled_hover_text("No documented default"),
+    };
+    response.clicked().then_some(default).flatten()
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use respeaker::analysis::{extract_segments, write_segments_csv};
+use respeaker::csv::{CsvReader, CsvWriter};
+use respeaker::params::{ParamKind, Value};
+
+fn row(vad: usize, doa: usize, rt60: f32, speech: usize, aec: usize) -> HashMap<ParamKind, Value> {
+    HashMap::from([
+        (ParamKind::VOICEACTIVITY, Value::Int(vad)),
+        (ParamKind::DOAANGLE, Value::Int(doa)),
+        (ParamKind::RT60, Value::Float(rt60)),
+        (ParamKind::SPEECHDETECTED, Value::Int(speech)),
+        (ParamKind::AECPATHCHANGE, Value::Int(aec)),
+    ])
+}
+
+#[test]
+fn segments_are_voice_activity_spans() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.csv");
+    let mut writer = CsvWriter::new(&path).expect("CSV writer");
+    let rows = [
+        row(0, 90, 0.3, 0, 0),
+        row(1, 350, 0.4, 0, 1),
+        row(1, 10, 0.5, 1, 1),
+        row(1, 0, 0.6, 0, 0),
+        row(1, 0, 0.3, 0, 1),
+        row(0, 90, 0.3, 0, 0),
+        row(1, 180, 0.2, 0, 0),
+    ];
+    for (second, values) in rows.iter().enumerate() {
+        let timestamp = format!("2024-01-01T12:00:0{second}.000000000+01:00");
+        writer
+            .write_row(&timestamp, &timestamp, values)
+            .expect("Failed to write row");
+    }
+    // Refresh rows only have RW parameters and must not close the open segment
+    writer
+        .write_row(
+            "RW_REFRESH",
+            "2024-01-01T12:00:07.000000000+01:00",
+            &HashMap::from([(ParamKind::AGCONOFF, Value::Int(1))]),
+        )
+        .expect("Failed to write row");
+    drop(writer);
+
+    let segments =
+        extract_segments(&mut CsvReader::new(&path).expect("Recording")).expect("Segments");
+
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].duration().num_seconds(), 3);
+    let mean_doa = segments[0].mean_doa.expect("DOA column");
+    assert!(mean_doa.min(360.0 - mean_doa) < 0.01, "{mean_doa}");
+    assert!((segments[0].mean_rt60.expect("RT60 column") - 0.45).abs() < 1e-6);
+    assert!(segments[0].speech_detected);
+    assert_eq!(segments[0].aec_path_changes, 2);
+    assert_eq!(segments[1].duration().num_seconds(), 0);
+    assert!(!segments[1].speech_detected);
+
+    let output = dir.path().join("segments.csv");
+    write_segments_csv(&output, &segments).expect("Failed to write segments");
+    let csv = std::fs::read_to_string(&output).expect("Segments file");
+    assert!(csv
+        .starts_with("start,end,duration_s,mean_doa,mean_rt60,speech_detected,aec_path_changes\n"));
+    assert!(csv.contains(",3.000,"));
+    assert_eq!(csv.lines().count(), 3);
+}
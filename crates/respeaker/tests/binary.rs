@@ -0,0 +1,171 @@
+#![cfg(feature = "bincode")]
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+
+use respeaker::binary::{BinaryReader, BinaryRowWriter};
+use respeaker::csv::{CsvReader, CsvWriter};
+use respeaker::export::binary_to_csv;
+use respeaker::params::{ParamKind, Value};
+use rstest::rstest;
+use strum::IntoEnumIterator;
+
+fn all_values() -> HashMap<ParamKind, Value> {
+    ParamKind::iter()
+        .map(|p| {
+            let value = if p.def().param_type.is_int() {
+                Value::Int(1)
+            } else {
+                Value::Float(0.45)
+            };
+            (p, value)
+        })
+        .collect()
+}
+
+#[test]
+fn binary_round_trip() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.bin");
+    let values = all_values();
+
+    let mut writer = BinaryRowWriter::new(&path).expect("Failed to create recording");
+    writer.write_row(1, &values).expect("Failed to write row");
+    writer
+        .write_row(2, &HashMap::from([(ParamKind::DOAANGLE, Value::Int(359))]))
+        .expect("Failed to write row");
+    drop(writer);
+
+    let rows = BinaryReader::new(&path)
+        .expect("Not a binary recording")
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].timestamp_unix_ns, 1);
+    assert_eq!(rows[0].values, values);
+    assert_eq!(rows[1].values[&ParamKind::DOAANGLE], Value::Int(359));
+}
+
+#[test]
+fn binary_detects_corruption() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.bin");
+    let mut writer = BinaryRowWriter::new(&path).expect("Failed to create recording");
+    writer
+        .write_row(1, &all_values())
+        .expect("Failed to write row");
+    drop(writer);
+
+    let mut bytes = fs::read(&path).expect("Recording exists");
+    // First byte of the row after the magic, the version and the length
+    bytes[9] ^= 0xFF;
+    fs::write(&path, bytes).expect("Failed to write recording");
+
+    let mut reader = BinaryReader::new(&path).expect("Header is intact");
+    let error = reader.next().expect("One row").expect_err("Corrupt row");
+    assert!(error.to_string().contains("Checksum mismatch"));
+}
+
+/// `kept` is the number of bytes left of the last row, given its complete length.
+#[rstest]
+#[case::in_length(|_| 2)]
+#[case::in_row(|_| 20)]
+#[case::in_checksum(|len| len - 1)]
+fn binary_rejects_truncated_row(#[case] kept: fn(usize) -> usize) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.bin");
+    let mut writer = BinaryRowWriter::new(&path).expect("Failed to create recording");
+    for timestamp in [1, 2] {
+        writer
+            .write_row(timestamp, &all_values())
+            .expect("Failed to write row");
+    }
+    drop(writer);
+    let mut bytes = fs::read(&path).expect("Recording exists");
+    // Both rows have the same length, after the magic and the version
+    let row_len = (bytes.len() - 5) / 2;
+    bytes.truncate(bytes.len() - row_len + kept(row_len));
+    fs::write(&path, bytes).expect("Failed to write recording");
+
+    let rows = BinaryReader::new(&path)
+        .expect("Header is intact")
+        .collect::<Vec<_>>();
+
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].is_ok());
+    let error = rows[1].as_ref().expect_err("Truncated row");
+    assert!(error.to_string().contains("truncated"), "{error}");
+}
+
+#[test]
+fn binary_rejects_other_files() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.csv");
+    fs::write(&path, "timestamp_before_read,timestamp_after_read\n").expect("Failed to write");
+
+    assert!(BinaryReader::new(&path).is_err());
+}
+
+#[test]
+fn binary_converts_to_csv() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let bin_path = dir.path().join("recording.bin");
+    let csv_path = dir.path().join("recording.csv");
+    let values = all_values();
+    let mut writer = BinaryRowWriter::new(&bin_path).expect("Failed to create recording");
+    writer
+        .write_row(1_700_000_000_000_000_000, &values)
+        .expect("Failed to write row");
+    drop(writer);
+
+    assert_eq!(binary_to_csv(&bin_path, &csv_path).expect("Conversion"), 1);
+
+    let rows = CsvReader::new(&csv_path)
+        .expect("Recording is not readable")
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].values, values);
+}
+
+/// Run with `cargo test --release --features bincode -- --ignored --nocapture` to compare the formats.
+#[test]
+#[ignore = "benchmark"]
+fn binary_write_throughput() {
+    const ROWS: u32 = 100_000;
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let values = all_values();
+    let timestamp = "2024-01-01T12:00:00.000000000+01:00";
+
+    let start = Instant::now();
+    let mut csv = CsvWriter::new(&dir.path().join("recording.csv")).expect("CSV writer");
+    for _ in 0..ROWS {
+        csv.write_row(timestamp, timestamp, &values)
+            .expect("Failed to write row");
+    }
+    let csv_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let mut binary =
+        BinaryRowWriter::new(&dir.path().join("recording.bin")).expect("Binary writer");
+    for i in 0..ROWS {
+        binary
+            .write_row(u64::from(i), &values)
+            .expect("Failed to write row");
+    }
+    let binary_elapsed = start.elapsed();
+
+    let size = |name: &str| {
+        fs::metadata(dir.path().join(name))
+            .expect("Recording exists")
+            .len()
+    };
+    println!(
+        "CSV: {csv_elapsed:?}, {} bytes | binary: {binary_elapsed:?}, {} bytes",
+        size("recording.csv"),
+        size("recording.bin")
+    );
+    assert!(binary_elapsed < csv_elapsed);
+}
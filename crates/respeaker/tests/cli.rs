@@ -0,0 +1,1390 @@
+// The binary only simulates a device (RESPEAKER_MOCK) in debug builds
+#![cfg(debug_assertions)]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use respeaker::csv::CsvReader;
+use respeaker::mock::ControlTransfer;
+#[cfg(feature = "serde")]
+use respeaker::ndjson::NdjsonReader;
+use respeaker::params::{ParamKind, Value};
+use rstest::rstest;
+
+fn respeaker(mock_seed: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(args)
+        .env("RESPEAKER_MOCK", mock_seed)
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("Failed to run respeaker binary")
+}
+
+fn respeaker_with_stdin(mock_seed: &str, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(args)
+        .env("RESPEAKER_MOCK", mock_seed)
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run respeaker binary");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())
+        .expect("Failed to write to stdin");
+    child
+        .wait_with_output()
+        .expect("Failed to wait for respeaker")
+}
+
+/// Like [`respeaker_with_stdin`], also returns the transfers the mock device received.
+fn respeaker_transfers(
+    mock_seed: &str,
+    args: &[&str],
+    input: &str,
+) -> (Output, Vec<ControlTransfer>) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let log_path = dir.path().join("transfers.log");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(args)
+        .env("RESPEAKER_MOCK", mock_seed)
+        .env("RESPEAKER_MOCK_TRANSFERS", &log_path)
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run respeaker binary");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())
+        .expect("Failed to write to stdin");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for respeaker");
+    let transfers = std::fs::read_to_string(&log_path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.parse().expect("Invalid transfer log line"))
+        .collect();
+    (output, transfers)
+}
+
+/// The parameter writes among `transfers`, decoded like the firmware does.
+fn param_writes(transfers: &[ControlTransfer]) -> Vec<(ParamKind, Value)> {
+    transfers
+        .iter()
+        .filter(|t| t.request_type == 0x40)
+        .map(|t| {
+            let word = |i: usize| -> [u8; 4] {
+                t.data[i * 4..i * 4 + 4]
+                    .try_into()
+                    .expect("Write has three words")
+            };
+            let cmd = u16::try_from(i32::from_le_bytes(word(0))).expect("Invalid command");
+            let param = ParamKind::from_index(t.index, cmd).expect("Unknown parameter");
+            let value = if i32::from_le_bytes(word(2)) == 1 {
+                Value::Int(usize::try_from(i32::from_le_bytes(word(1))).expect("Negative int"))
+            } else {
+                Value::Float(f32::from_le_bytes(word(1)))
+            };
+            (param, value)
+        })
+        .collect()
+}
+
+/// The requests of the DFU class requests among `transfers`.
+fn dfu_requests(transfers: &[ControlTransfer]) -> Vec<u8> {
+    transfers
+        .iter()
+        .filter(|t| t.request_type == 0x21)
+        .map(|t| t.request)
+        .collect()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn list_prints_all_parameters() {
+    let output = respeaker("", &["list"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("AGCMAXGAIN"));
+    assert!(stdout.contains("DOAANGLE"));
+}
+
+#[test]
+fn read_returns_seeded_value() {
+    let output = respeaker("DOAANGLE=42", &["read", "--continuous=false", "DOAANGLE"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "DOAANGLE=42\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn read_continuous_stops_on_ctrl_c() {
+    let child = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(["read", "--continuous", "DOAANGLE"])
+        .env("RESPEAKER_MOCK", "DOAANGLE=42")
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run respeaker binary");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    let kill = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for respeaker");
+
+    assert!(kill.success());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("DOAANGLE=42\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn watch_without_params_shows_real_time_params() {
+    let child = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(["watch"])
+        .env("RESPEAKER_MOCK", "DOAANGLE=42")
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run respeaker binary");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    let kill = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for respeaker");
+
+    assert!(kill.success());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(" DOAANGLE=42\n"), "{stdout}");
+    for param in ["VOICEACTIVITY", "SPEECHDETECTED", "AGCGAIN", "RT60"] {
+        assert!(stdout.contains(&format!(" {param}=")), "{stdout}");
+    }
+    assert!(!stdout.contains(" AGCONOFF="), "{stdout}");
+}
+
+#[test]
+fn write_valid_value() {
+    let (output, transfers) = respeaker_transfers("", &["write", "AGCMAXGAIN", "500.0"], "");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        param_writes(&transfers),
+        [(ParamKind::AGCMAXGAIN, Value::Float(500.0))]
+    );
+}
+
+#[test]
+fn stress_test_passes_on_mock() {
+    let output = respeaker("", &["stress-test", "--iterations", "30"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("| total | errors | timeouts | mismatches |"));
+    assert!(stdout(&output).contains("Error rate 0.00%, passed"));
+}
+
+#[rstest]
+#[case::no_iterations(&["stress-test", "--iterations", "0"])]
+#[case::negative_rate(&["stress-test", "--max-error-rate-percent=-1"])]
+#[case::nan_rate(&["stress-test", "--max-error-rate-percent", "NaN"])]
+fn stress_test_rejects_invalid_arguments(#[case] args: &[&str]) {
+    let output = respeaker("", args);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("invalid value"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[rstest]
+#[case::zero("0", "invalid value")]
+#[case::overflow("18446744073709551615", "too large")]
+fn record_rejects_invalid_max_file_size(#[case] size: &str, #[case] expected_error: &str) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--max-file-size-mb",
+            size,
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains(expected_error),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!csv_path.exists());
+}
+
+#[test]
+fn write_log_change_appends_old_and_new_value() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let log_path = dir.path().join("changes.log");
+    let log_arg = log_path.to_str().expect("UTF-8 path");
+
+    for value in ["500", "31.6"] {
+        let output = respeaker(
+            "AGCMAXGAIN=31.6",
+            &[
+                "write",
+                "AGCMAXGAIN",
+                value,
+                &format!("--log-change={log_arg}"),
+            ],
+        );
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    let log = std::fs::read_to_string(&log_path).expect("Change log exists");
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{log}");
+    assert!(
+        lines[0].starts_with('[') && lines[0].contains("Z] "),
+        "{log}"
+    );
+    // Each run starts with a fresh mock
+    assert!(lines[0].ends_with("] AGCMAXGAIN: 31.6 -> 500"), "{log}");
+    assert!(lines[1].ends_with("] AGCMAXGAIN: 31.6 -> 31.6"), "{log}");
+}
+
+#[test]
+fn write_log_change_uses_configured_path() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let log_path = dir.path().join("changes.log");
+    let config_dir = dir.path().join("respeaker");
+    std::fs::create_dir(&config_dir).expect("Failed to create config dir");
+    std::fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "[cli]\nchange_log = {:?}\n",
+            log_path.to_str().expect("UTF-8 path")
+        ),
+    )
+    .expect("Failed to write config");
+
+    // Without `=` the flag doesn't take the parameter name as its path
+    let output = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(["write", "--log-change", "AGCMAXGAIN", "500"])
+        .env("RESPEAKER_MOCK", "AGCMAXGAIN=31.6")
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("Failed to run respeaker binary");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = std::fs::read_to_string(&log_path).expect("Change log exists");
+    assert!(log.ends_with("] AGCMAXGAIN: 31.6 -> 500\n"), "{log}");
+}
+
+#[test]
+fn write_throttle_spaces_out_writes() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let start = std::time::Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .args(["write", "--from-stdin", "--throttle-write-ms", "100"])
+        .env("RESPEAKER_MOCK", "")
+        .env("XDG_RUNTIME_DIR", dir.path())
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run respeaker binary");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(b"AGCONOFF=1\nAGCONOFF=0\nAGCONOFF=1\n")
+        .expect("Failed to write to stdin");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for respeaker");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+}
+
+#[test]
+fn write_throttle_spans_separate_runs() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let write = |value: &str| {
+        Command::new(env!("CARGO_BIN_EXE_respeaker"))
+            .args(["write", "AGCONOFF", value, "--throttle-write-ms", "500"])
+            .env("RESPEAKER_MOCK", "")
+            .env("XDG_RUNTIME_DIR", dir.path())
+            .output()
+            .expect("Failed to run respeaker binary")
+    };
+
+    let first = write("1");
+    let start = std::time::Instant::now();
+    let second = write("0");
+
+    assert!(first.status.success(), "{}", stderr(&first));
+    assert!(second.status.success(), "{}", stderr(&second));
+    assert!(dir.path().join("respeaker").join("last_write").exists());
+    assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+}
+
+#[rstest]
+#[case::out_of_range(&["write", "AGCMAXGAIN", "5000"], "not in range")]
+#[case::read_only(&["write", "DOAANGLE", "5"], "read-only")]
+#[case::not_an_int(&["write", "AGCONOFF", "on"], "must be a usize")]
+fn write_invalid_value(#[case] args: &[&str], #[case] expected_error: &str) {
+    let output = respeaker("", args);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains(expected_error),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn write_from_stdin_applies_all_lines() {
+    let (output, transfers) = respeaker_transfers(
+        "",
+        &["write", "--from-stdin"],
+        "# Preset\nAGCMAXGAIN=500.0\n\nAGCONOFF = 1\n",
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        param_writes(&transfers),
+        [
+            (ParamKind::AGCMAXGAIN, Value::Float(500.0)),
+            (ParamKind::AGCONOFF, Value::Int(1))
+        ]
+    );
+}
+
+#[rstest]
+#[case::out_of_range(
+    "AGCMAXGAIN=500\nAGCONOFF=2\n",
+    "Line 2: Value 2 for AGCONOFF is not in range"
+)]
+#[case::read_only("# Preset\nDOAANGLE=5\n", "Line 2: Parameter DOAANGLE is read-only")]
+#[case::no_assignment("AGCONOFF\n", "Line 1: Expected PARAM=value")]
+#[case::unknown("FOO=1\n", "Line 1: Unknown parameter FOO")]
+fn write_from_stdin_validates_before_writing(#[case] input: &str, #[case] expected_error: &str) {
+    let (output, transfers) = respeaker_transfers("", &["write", "--from-stdin"], input);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains(expected_error),
+        "{}",
+        stderr(&output)
+    );
+    assert!(param_writes(&transfers).is_empty());
+}
+
+#[test]
+fn record_writes_readable_csv() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        "VOICEACTIVITY=1",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut reader = CsvReader::new(&csv_path).expect("Recording is not readable");
+    assert_eq!(reader.metadata().serial.as_deref(), Some("MOCK"));
+    let rows = reader
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert!(!rows.is_empty());
+    assert_eq!(
+        rows[0].values.get(&ParamKind::VOICEACTIVITY),
+        Some(&Value::Int(1))
+    );
+}
+
+#[test]
+fn record_interleave_audio_needs_aligned_start() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let wav_path = dir.path().join("audio.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 16000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut wav = hound::WavWriter::create(&wav_path, spec).expect("WAV writer");
+    for _ in 0..1600 {
+        wav.write_sample(0i16).expect("Failed to write sample");
+    }
+    wav.finalize().expect("Failed to finalize WAV");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--interleave-audio",
+            wav_path.to_str().expect("UTF-8 path"),
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("away from the audio"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn record_per_category_files() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let session = dir.path().join("session");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--output-dir",
+            session.to_str().expect("UTF-8 path"),
+            "--output-dir-format",
+            "per-category",
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let read = |name: &str| {
+        CsvReader::new(&session.join(name))
+            .expect("Recording is readable")
+            .rows()
+            .collect::<eyre::Result<Vec<_>>>()
+            .expect("Invalid row")
+    };
+    let aec = read("aec.csv");
+    assert!(!aec.is_empty());
+    assert!(aec[0].values.contains_key(&ParamKind::AECNORM));
+    assert!(!aec[0].values.contains_key(&ParamKind::AGCONOFF));
+    for name in ["agc.csv", "noise.csv", "doa.csv", "vad.csv", "filter.csv"] {
+        let rows = read(name);
+        assert_eq!(rows.len(), aec.len(), "{name}");
+        assert_eq!(rows[0].timestamp_before, aec[0].timestamp_before, "{name}");
+    }
+}
+
+#[test]
+fn record_per_category_keeps_previous_session() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let session = dir.path().join("session");
+    let record = |extra: &[&str]| {
+        let mut args = vec![
+            "record",
+            "-s",
+            "0.1",
+            "--output-dir",
+            session.to_str().expect("UTF-8 path"),
+            "--output-dir-format",
+            "per-category",
+        ];
+        args.extend_from_slice(extra);
+        respeaker("", &args)
+    };
+    let output = record(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let first = std::fs::read_to_string(session.join("aec.csv")).expect("Recording exists");
+
+    let output = record(&[]);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("already exists"),
+        "{}",
+        stderr(&output)
+    );
+    let aec = std::fs::read_to_string(session.join("aec.csv")).expect("Recording exists");
+    assert_eq!(aec, first);
+
+    let output = record(&["--append", "--no-header"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let aec = std::fs::read_to_string(session.join("aec.csv")).expect("Recording exists");
+    assert!(aec.starts_with(&first) && aec.len() > first.len());
+}
+
+#[test]
+fn record_append_without_header() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rows_before = CsvReader::new(&csv_path)
+        .expect("Recording is not readable")
+        .rows()
+        .count();
+
+    let output = respeaker(
+        "",
+        &["record", "-s", "0.1", "--append", "--no-header", csv_arg],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rows = CsvReader::new(&csv_path)
+        .expect("Recording is not readable")
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert!(rows.len() > rows_before);
+}
+
+#[test]
+fn record_append_keeps_column_order_of_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("DOAANGLE=42", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = respeaker(
+        "DOAANGLE=42",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--append",
+            "--no-header",
+            "--column-order",
+            "alphabetical",
+            csv_arg,
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rows = CsvReader::new(&csv_path)
+        .expect("Recording is not readable")
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert!(rows
+        .iter()
+        .all(|row| row.values.get(&ParamKind::DOAANGLE) == Some(&Value::Int(42))));
+}
+
+#[test]
+fn record_append_rejects_other_columns() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let before = std::fs::read_to_string(&csv_path).expect("Recording exists");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--append",
+            "--no-header",
+            "--exclude",
+            "DOAANGLE",
+            csv_arg,
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("has other columns"),
+        "{}",
+        stderr(&output)
+    );
+    let after = std::fs::read_to_string(&csv_path).expect("Recording exists");
+    assert_eq!(after, before);
+}
+
+#[test]
+fn record_no_header_warns_without_append() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--no-header",
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("without a header"));
+    let csv = std::fs::read_to_string(&csv_path).expect("Recording exists");
+    assert!(!csv.contains("timestamp_before_read"));
+}
+
+#[test]
+fn record_excludes_params() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--exclude",
+            "GAMMA_NS,DOAANGLE",
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let csv = std::fs::read_to_string(&csv_path).expect("Recording exists");
+    let header = csv
+        .lines()
+        .find(|line| line.starts_with("timestamp_before_read"))
+        .expect("Header row");
+    assert!(!header.contains("DOAANGLE"));
+    assert!(!header.contains("GAMMA_NS,"));
+    assert!(header.contains("GAMMA_NN"));
+}
+
+#[test]
+fn record_refreshes_rw_params() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        "AGCONOFF=0",
+        &[
+            "record",
+            "-s",
+            "0.2",
+            "--rw-refresh-interval-secs",
+            "0.05",
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut reader = CsvReader::new(&csv_path).expect("Recording is not readable");
+    let refresh_rows = reader
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row")
+        .into_iter()
+        .filter(|row| row.timestamp_before == "RW_REFRESH")
+        .collect::<Vec<_>>();
+    assert!(!refresh_rows.is_empty());
+    assert_eq!(
+        refresh_rows[0].values.get(&ParamKind::AGCONOFF),
+        Some(&Value::Int(0))
+    );
+    assert!(!refresh_rows[0]
+        .values
+        .contains_key(&ParamKind::VOICEACTIVITY));
+
+    let comments = reader
+        .comments()
+        .iter()
+        .map(|(_, comment)| comment.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        comments.first(),
+        Some(&"DEVICE model=MicArrayV2 serial=MOCK firmware=unknown")
+    );
+    assert_eq!(
+        comments
+            .iter()
+            .filter(|c| **c == "RW_PARAMS_REFRESHED")
+            .count(),
+        refresh_rows.len()
+    );
+    assert_eq!(comments.last(), Some(&"RECORDING_ENDED reason=duration"));
+    // Line numbers are 1-based and count the metadata rows and the header
+    assert!(reader.comments()[0].0 > 2);
+}
+
+#[cfg(feature = "serde")]
+#[rstest]
+#[case("jsonl")]
+#[case("ndjson")]
+fn record_writes_readable_ndjson(#[case] format: &str) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.jsonl");
+
+    let output = respeaker(
+        "VOICEACTIVITY=1,RT60=0.45",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--output",
+            format,
+            path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rows = NdjsonReader::new(&path)
+        .expect("Recording is not readable")
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert!(!rows.is_empty());
+    assert_eq!(rows[0].get(&ParamKind::VOICEACTIVITY), Some(&Value::Int(1)));
+    assert_eq!(rows[0].get(&ParamKind::RT60), Some(&Value::Float(0.45)));
+}
+
+#[rstest]
+#[case::above("RT60=0.8", "--trigger-above")]
+#[case::below("RT60=0.5", "--trigger-below")]
+fn record_starts_on_trigger(#[case] mock_seed: &str, #[case] direction: &str) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+
+    let output = respeaker(
+        mock_seed,
+        &[
+            "record",
+            "-s",
+            "0.1",
+            "--trigger-param",
+            "RT60",
+            "--trigger-threshold",
+            "0.7",
+            direction,
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut reader = CsvReader::new(&csv_path).expect("Recording is not readable");
+    assert!(reader.rows().next().is_some());
+}
+
+#[rstest]
+#[case::confirmed("REVERT\n", true)]
+#[case::aborted("revert\n", false)]
+fn revert_factory_needs_confirmation(#[case] input: &str, #[case] success: bool) {
+    let (output, transfers) = respeaker_transfers("", &["revert-factory"], input);
+
+    assert_eq!(output.status.success(), success, "{}", stderr(&output));
+    assert_eq!(dfu_requests(&transfers).contains(&0xF1), success);
+}
+
+#[rstest]
+#[case::confirmed("y\n", true)]
+#[case::aborted("\n", false)]
+fn write_interactive_needs_confirmation(#[case] input: &str, #[case] success: bool) {
+    let (output, transfers) = respeaker_transfers(
+        "AGCMAXGAIN=31.6",
+        &["write", "AGCMAXGAIN", "1000", "--interactive"],
+        input,
+    );
+
+    assert_eq!(output.status.success(), success, "{}", stderr(&output));
+    assert!(stderr(&output).contains(
+        "Current value: 31.6 (30.0 dB). About to write: 1000 (60.0 dB) (max: 1000). Confirm? [y/N]:"
+    ));
+    assert_eq!(
+        param_writes(&transfers) == [(ParamKind::AGCMAXGAIN, Value::Float(1000.0))],
+        success
+    );
+}
+
+#[test]
+fn tune_voice_assistant_writes_settings() {
+    let output = respeaker_with_stdin("", &["tune"], "y\nmaximum\nhigh\nn\n");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Please answer one of low/medium/high"));
+    assert!(stderr.contains("Set STATNOISEONOFF = 1"));
+    assert!(stderr.contains("Set GAMMA_NS = 2"));
+    assert!(stderr.contains("Set MIN_NS = 0.05"));
+    assert!(stderr.contains("Set FREEZEONOFF = 0"));
+}
+
+#[test]
+fn tune_aborts_without_answer() {
+    let output = respeaker_with_stdin("", &["tune"], "");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Aborted"));
+}
+
+#[test]
+fn invalid_mock_seed_is_rejected() {
+    let output = respeaker("NOTAPARAM=1", &["list"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Unknown parameter in mock seed"));
+}
+
+#[test]
+fn record_to_stdout() {
+    let output = respeaker("", &["record", "-s", "0.1", "-"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    let mut lines = stdout.lines().skip_while(|line| line.starts_with('#'));
+    assert!(lines
+        .next()
+        .is_some_and(|header| header.starts_with("timestamp_before_read,timestamp_after_read")));
+    assert!(lines.next().is_some());
+    assert!(stdout.contains("# device_serial=MOCK"));
+}
+
+#[test]
+fn doctor_reads_all_parameters() {
+    let output = respeaker("", &["doctor"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("OK: all"));
+}
+
+#[test]
+fn export_python_replays_rw_params() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("AGCONOFF=1,AGCTIME=0.45", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = respeaker("", &["export", "--format", "python", csv_arg]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let script = stdout(&output);
+    assert!(script.starts_with("#!/usr/bin/env python3"));
+    assert!(script.contains("Device serial: MOCK"));
+    assert!(script.contains("--dry-run"));
+    assert!(script.contains("    # AGCONOFF = 1\n    write(dev, \"AGCONOFF\", 19, 0, 1, True)"));
+    assert!(
+        script.contains("    # AGCTIME = 0.45\n    write(dev, \"AGCTIME\", 19, 4, 0.45, False)")
+    );
+    // The values don't change during the recording, so every parameter is written once
+    assert_eq!(script.matches("# AGCONOFF").count(), 1);
+}
+
+#[test]
+fn export_python_escapes_metadata() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    // A bare carriage return ends a line in Python source
+    std::fs::write(
+        &csv_path,
+        "# device_serial=MOCK\"\"\"\rimport os; print(\"PWNED\")\r\"\"\"\n\
+         timestamp_before_read,timestamp_after_read,AGCONOFF\n\
+         2025-03-01T12:00:00+01:00,2025-03-01T12:00:00+01:00,1\n",
+    )
+    .expect("Failed to write recording");
+
+    let output = respeaker(
+        "",
+        &[
+            "export",
+            "--format",
+            "python",
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let script = stdout(&output);
+    assert!(script.contains(r#"Device serial: MOCK\"\"\"\rimport os; print(\"PWNED\")\r\"\"\"\n"#));
+    assert!(!script.contains('\r'));
+}
+
+#[test]
+fn export_python_rejects_nan() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    std::fs::write(
+        &csv_path,
+        "timestamp_before_read,timestamp_after_read,AGCTIME\n\
+         2025-03-01T12:00:00+01:00,2025-03-01T12:00:00+01:00,NaN\n",
+    )
+    .expect("Failed to write recording");
+
+    let output = respeaker(
+        "",
+        &[
+            "export",
+            "--format",
+            "python",
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(!output.status.success());
+    // NaN isn't valid Python, the reader already rejects it
+    assert!(
+        stderr(&output).contains("must be a finite f32"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn export_python_needs_recording() {
+    let output = respeaker("", &["export", "--format", "python"]);
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn snapshot_diff_shows_changed_params() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let a = dir.path().join("a.toml");
+    let b = dir.path().join("b.toml");
+    let a_arg = a.to_str().expect("UTF-8 path");
+    let b_arg = b.to_str().expect("UTF-8 path");
+
+    let output = respeaker("DOAANGLE=42", &["snapshot", "--output", a_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output).trim(), a_arg);
+    let output = respeaker("DOAANGLE=90", &["snapshot", "--output", b_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = respeaker("", &["snapshot", "diff", a_arg, b_arg]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "DOAANGLE: 42 -> 90\n");
+}
+
+#[test]
+fn audit_log_is_not_available() {
+    let output = respeaker("", &["audit-log"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("audit log not available in this session"));
+}
+
+#[test]
+fn write_appends_to_audit_log_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let log_path = dir.path().join("writes.log");
+    let log_arg = log_path.to_str().expect("UTF-8 path");
+
+    for value in ["500", "30"] {
+        let output = respeaker(
+            "AGCMAXGAIN=1",
+            &["--audit-log-file", log_arg, "write", "AGCMAXGAIN", value],
+        );
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    let log = std::fs::read_to_string(&log_path).expect("Audit log was not written");
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{log}");
+    // Every run is a new session, so the second write starts from the mock seed again
+    assert!(lines[0].ends_with(" AGCMAXGAIN: 1 -> 500"), "{log}");
+    assert!(lines[1].ends_with(" AGCMAXGAIN: 1 -> 30"), "{log}");
+}
+
+#[test]
+fn check_firmware_accepts_mock() {
+    let output = respeaker(
+        "",
+        &["--check-firmware", "read", "--continuous=false", "DOAANGLE"],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("DOAANGLE="));
+}
+
+#[test]
+fn json_logs_to_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let log_path = dir.path().join("respeaker.log");
+
+    let output = respeaker(
+        "",
+        &[
+            "--log-format",
+            "json",
+            "--log-file",
+            log_path.to_str().expect("UTF-8 path"),
+            "write",
+            "AGCONOFF",
+            "1",
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let logs = std::fs::read_to_string(&log_path).expect("Log file must exist");
+    let line = logs
+        .lines()
+        .find(|line| line.contains("Wrote value"))
+        .expect("Write must be logged");
+    let json: serde_json::Value = serde_json::from_str(line).expect("Log line must be JSON");
+    assert_eq!(json["level"], "INFO");
+    assert!(json["fields"]["message"].is_string());
+    assert!(json["timestamp"].is_string());
+}
+
+#[test]
+fn read_without_params_shows_real_time_values() {
+    let output = respeaker("DOAANGLE=90", &["read", "--continuous=false"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert_eq!(stdout.lines().count(), 5);
+    assert!(stdout.contains("DOAANGLE=90"));
+}
+
+#[test]
+fn read_rejects_zero_rate_limit() {
+    let output = respeaker("", &["read", "-c", "--rate-limit-hz", "0", "DOAANGLE"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--rate-limit-hz"));
+}
+
+#[rstest]
+#[case::human("human", "DOA=42° | VAD=ON | Speech=OFF | RT60=0.45s | AGC=+6dB\n")]
+#[case::machine("machine", "42 1 0 0.45 2.0\n")]
+fn status_prints_one_line(#[case] format: &str, #[case] expected: &str) {
+    let output = respeaker(
+        "DOAANGLE=42,VOICEACTIVITY=1,SPEECHDETECTED=0,RT60=0.45,AGCGAIN=2.0",
+        &["status", "--format", format],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), expected);
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn reset_idle_device() {
+    let (output, transfers) = respeaker_transfers("", &["reset"], "");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(dfu_requests(&transfers), [0xF0]);
+}
+
+#[rstest]
+#[case::bash("bash", "export RESPEAKER_AGCMAXGAIN=31.6\n")]
+#[case::fish("fish", "set -gx RESPEAKER_AGCMAXGAIN 31.6\n")]
+fn env_export_prints_rw_params(#[case] shell: &str, #[case] expected_line: &str) {
+    let output = respeaker("AGCMAXGAIN=31.6", &["env-export", "--shell", shell]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(expected_line), "{stdout}");
+    assert!(!stdout.contains("DOAANGLE"));
+}
+
+#[test]
+fn identify_prints_one_line() {
+    let output = respeaker("", &["identify"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "ReSpeaker Mic Array v2.0 | Bus 000 Device 000 | Serial: MOCK | Firmware: N/A | Uptime: N/A\n"
+    );
+    assert_eq!(stderr(&output), "");
+}
+
+#[rstest]
+#[case::by_flag(&["--compress"], "recording.csv", "recording.csv.gz")]
+#[case::by_extension(&[], "recording.csv.gz", "recording.csv.gz")]
+fn record_compressed(#[case] flags: &[&str], #[case] name: &str, #[case] expected_name: &str) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join(name);
+
+    let mut args = vec!["record", "-s", "0.1"];
+    args.extend_from_slice(flags);
+    args.push(csv_path.to_str().expect("UTF-8 path"));
+    let output = respeaker("", &args);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let compressed = std::fs::read(dir.path().join(expected_name)).expect("Recording must exist");
+    assert_eq!(compressed[..2], [0x1f, 0x8b], "gzip magic bytes");
+    let mut reader =
+        CsvReader::new(&dir.path().join(expected_name)).expect("Recording is not readable");
+    assert_eq!(reader.metadata().serial.as_deref(), Some("MOCK"));
+    assert!(reader.rows().next().is_some());
+}
+
+#[rstest]
+#[case("-1")]
+#[case("0")]
+#[case("NaN")]
+#[case("inf")]
+fn record_rejects_invalid_refresh_interval(#[case] secs: &str) {
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "-s",
+            "0.1",
+            &format!("--rw-refresh-interval-secs={secs}"),
+            "-",
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("invalid value"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn segment_without_speech_prints_empty_table() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = respeaker("", &["segment", csv_arg]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("aec_path_changes"));
+}
+
+#[test]
+fn benchmark_usb_profile_prints_histogram() {
+    let output = respeaker("", &["benchmark", "--profile", "usb", "-n", "100"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.starts_with("100 control transfers reading DOAANGLE"),
+        "{out}"
+    );
+    assert!(out.contains("short transfers: 0 of 100"), "{out}");
+    let buckets = out.lines().filter(|line| line.contains(" .. ")).count();
+    assert_eq!(buckets, 10, "{out}");
+}
+
+#[test]
+fn benchmark_prints_device_metrics() {
+    let output = respeaker("", &["benchmark", "-n", "20"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.lines()
+            .last()
+            .is_some_and(|line| line.starts_with("USB transfers: 20 reads, 0 writes, 0 errors")),
+        "{out}"
+    );
+}
+
+#[test]
+fn param_info_prints_documentation() {
+    let output = respeaker("", &["param-info", "AGCMAXGAIN"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.starts_with("AGCMAXGAIN\n"), "{out}");
+    assert!(out.contains("Default:   30dB"), "{out}");
+    assert!(out.contains("Category:  Automatic gain control"), "{out}");
+}
+
+#[test]
+fn export_matlab_writes_mat_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let mat_path = dir.path().join("recording.mat");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("DOAANGLE=42", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let mat_arg = mat_path.to_str().expect("UTF-8 path");
+    let output = respeaker("", &["export", "--format", "matlab", csv_arg, mat_arg]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let bytes = std::fs::read(&mat_path).expect("MAT file exists");
+    assert!(bytes.starts_with(b"MATLAB 5.0 MAT-file"));
+    assert_eq!(&bytes[124..128], &[0x00, 0x01, b'I', b'M']);
+    // Walk the top level miMATRIX elements, the name is the third sub element
+    let u32_at = |offset: usize| {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4 bytes")) as usize
+    };
+    let mut names = vec![];
+    let mut offset = 128;
+    while offset < bytes.len() {
+        assert_eq!(u32_at(offset), 14);
+        let name_len = u32_at(offset + 8 + 32 + 4);
+        let name = &bytes[offset + 8 + 40..offset + 8 + 40 + name_len];
+        names.push(String::from_utf8(name.to_vec()).expect("ASCII name"));
+        offset += 8 + u32_at(offset + 4);
+    }
+    assert_eq!(offset, bytes.len());
+    assert_eq!(names[..2], ["ts_before", "ts_after"]);
+    assert!(names.contains(&"DOAANGLE".to_string()));
+    assert_eq!(names.last().map(String::as_str), Some("metadata"));
+}
+
+#[test]
+fn export_parquet_writes_parquet_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let parquet_path = dir.path().join("recording.parquet");
+    let csv_arg = csv_path.to_str().expect("UTF-8 path");
+    let output = respeaker("DOAANGLE=42", &["record", "-s", "0.1", csv_arg]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let parquet_arg = parquet_path.to_str().expect("UTF-8 path");
+    let output = respeaker("", &["export", "--format", "parquet", csv_arg, parquet_arg]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let bytes = std::fs::read(&parquet_path).expect("Parquet file exists");
+    assert!(bytes.starts_with(b"PAR1"));
+    assert!(bytes.ends_with(b"PAR1"));
+    let footer_len = u32::from_le_bytes(
+        bytes[bytes.len() - 8..bytes.len() - 4]
+            .try_into()
+            .expect("4 bytes"),
+    ) as usize;
+    let footer = &bytes[bytes.len() - 8 - footer_len..bytes.len() - 8];
+    let contains = |text: &str| footer.windows(text.len()).any(|w| w == text.as_bytes());
+    for text in [
+        "ts_before",
+        "ts_after",
+        "DOAANGLE",
+        "device_serial",
+        "description:DOAANGLE",
+    ] {
+        assert!(contains(text), "{text} is missing in the footer");
+    }
+}
+
+#[test]
+fn init_creates_config_dir_without_overwriting() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let init = || {
+        Command::new(env!("CARGO_BIN_EXE_respeaker"))
+            .arg("init")
+            .env("RESPEAKER_MOCK", "AGCONOFF=0")
+            .env("XDG_CONFIG_HOME", dir.path())
+            .env_remove("RUST_LOG")
+            .output()
+            .expect("Failed to run respeaker binary")
+    };
+    let config_dir = dir.path().join("respeaker");
+    let current = config_dir.join("presets").join("current.toml");
+
+    let output = init();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output).matches("Created: ").count(), 4);
+    assert!(config_dir.join("config.toml").exists());
+    let preset = std::fs::read_to_string(&current).expect("Preset exists");
+    assert!(preset.contains("AGCONOFF = 0\n"), "{preset}");
+
+    std::fs::write(&current, "AGCONOFF = 1\n").expect("Preset is writable");
+    let output = init();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output).matches("Skipped: ").count(), 2);
+    assert!(stderr(&output).contains("already exists"));
+    assert_eq!(
+        std::fs::read_to_string(&current).expect("Preset exists"),
+        "AGCONOFF = 1\n"
+    );
+}
+
+#[test]
+fn record_starts_on_trigger_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let trigger_path = dir.path().join("record.trigger");
+    std::fs::write(&trigger_path, "seconds=0.2\n").expect("Failed to write trigger file");
+
+    let output = respeaker(
+        "",
+        &[
+            "record",
+            "--trigger-file",
+            trigger_path.to_str().expect("UTF-8 path"),
+            csv_path.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut reader = CsvReader::new(&csv_path).expect("Recording is not readable");
+    assert!(reader.rows().count() > 0);
+    assert!(reader
+        .comments()
+        .iter()
+        .any(|(_, c)| c == "RECORDING_ENDED reason=duration"));
+}
+
+#[test]
+fn init_without_device_creates_config() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = Command::new(env!("CARGO_BIN_EXE_respeaker"))
+        .arg("init")
+        // A mock which fails to open stands in for a missing device
+        .env("RESPEAKER_MOCK", "NOT_A_PARAM=1")
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("Failed to run respeaker binary");
+    let config_dir = dir.path().join("respeaker");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = std::fs::read_to_string(config_dir.join("config.toml")).expect("Config exists");
+    assert!(config.contains("[cli]"), "{config}");
+    assert!(config_dir.join("presets").is_dir());
+    assert!(!config_dir.join("presets").join("current.toml").exists());
+    assert!(stdout(&output).contains("(no device)"));
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use respeaker::config::{
+    config_from_toml, config_to_toml, default_config_toml, diff_snapshots, snapshot_from_toml,
+    snapshot_to_toml, CliSettings,
+};
+use respeaker::params::{Access, ParamKind, ParamState, Value};
+use rstest::rstest;
+use strum::IntoEnumIterator;
+
+#[test]
+fn config_toml_round_trip() {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::AGCONOFF, &Value::Int(1));
+    state.update(&ParamKind::AGCMAXGAIN, &Value::Float(31.6));
+    // RO parameters are not part of a config
+    state.update(&ParamKind::DOAANGLE, &Value::Int(42));
+
+    let toml = config_to_toml(&state).expect("Valid state");
+    assert_eq!(toml, "AGCMAXGAIN = 31.6\nAGCONOFF = 1\n");
+
+    let params = config_from_toml(&toml).expect("Valid TOML");
+    assert_eq!(params.len(), 2);
+    assert_eq!(params[&ParamKind::AGCMAXGAIN], Value::Float(31.6));
+    assert_eq!(params[&ParamKind::AGCONOFF], Value::Int(1));
+}
+
+#[test]
+fn default_config_is_a_valid_config() {
+    let toml = default_config_toml().expect("Defaults are valid");
+
+    let params = config_from_toml(&toml).expect("Valid TOML");
+    assert_eq!(params[&ParamKind::AGCMAXGAIN], Value::Float(31.6));
+    assert_eq!(
+        CliSettings::from_toml(&toml).expect("Valid settings"),
+        CliSettings::default()
+    );
+    assert!(toml.contains("\n[cli]\n") && toml.contains("# change_log = "));
+    // No documented default
+    assert!(!params.contains_key(&ParamKind::AGCONOFF));
+    for param in ParamKind::iter().filter(|p| p.def().access == Access::ReadWrite) {
+        assert!(
+            toml.contains(&format!("\n{param:?} =")) || toml.contains(&format!("# {param:?} =")),
+            "{param:?} is missing"
+        );
+    }
+}
+
+#[rstest]
+#[case("NOTAPARAM = 1")]
+#[case("DOAANGLE = 1")]
+#[case("AGCONOFF = 1.5")]
+#[case("AGCONOFF = \"on\"")]
+#[case("AGCONOFF = 2")]
+fn config_rejects_invalid_toml(#[case] toml: &str) {
+    assert!(config_from_toml(toml).is_err());
+}
+
+#[test]
+fn snapshot_toml_round_trip() {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::AGCONOFF, &Value::Int(1));
+    state.update(&ParamKind::DOAANGLE, &Value::Int(42));
+
+    let toml = snapshot_to_toml(&state).expect("Valid state");
+    assert_eq!(
+        toml,
+        "[read_only]\nDOAANGLE = 42\n\n[read_write]\nAGCONOFF = 1\n"
+    );
+
+    let params = snapshot_from_toml(&toml).expect("Valid TOML");
+    assert_eq!(params, state.current_params);
+    // A RO parameter in the RW section
+    assert!(snapshot_from_toml("[read_write]\nDOAANGLE = 42\n").is_err());
+    assert!(snapshot_from_toml("[read_only]\nDOAANGLE = 400\n").is_err());
+}
+
+#[test]
+fn snapshot_diff() {
+    let a = HashMap::from([
+        (ParamKind::AGCONOFF, Value::Int(1)),
+        (ParamKind::DOAANGLE, Value::Int(42)),
+    ]);
+    let b = HashMap::from([
+        (ParamKind::AGCONOFF, Value::Int(1)),
+        (ParamKind::DOAANGLE, Value::Int(90)),
+        (ParamKind::RT60, Value::Float(0.45)),
+    ]);
+
+    assert_eq!(
+        diff_snapshots(&a, &b),
+        vec![
+            (
+                ParamKind::DOAANGLE,
+                Some(Value::Int(42)),
+                Some(Value::Int(90))
+            ),
+            (ParamKind::RT60, None, Some(Value::Float(0.45))),
+        ]
+    );
+}
+
+#[test]
+fn cli_settings_from_toml() {
+    let settings =
+        CliSettings::from_toml("AGCONOFF = 1\n[cli]\nchange_log = \"/tmp/changes.log\"\n")
+            .expect("Valid settings");
+
+    assert_eq!(
+        settings.change_log,
+        Some(std::path::PathBuf::from("/tmp/changes.log"))
+    );
+    assert_eq!(
+        CliSettings::from_toml("").expect("Valid settings"),
+        CliSettings::default()
+    );
+    assert!(CliSettings::from_toml("[cli]\nchange_log = 1").is_err());
+    assert!(CliSettings::from_toml("[cli]\nAGCONOFF = 1").is_err());
+    assert!(CliSettings::from_toml("cli = 1").is_err());
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use respeaker::csv::{AudioTimeline, CsvReader, CsvWriter, CsvWriterOptions, ParamColumnOrder};
+use respeaker::params::{ParamKind, Value};
+use rstest::rstest;
+use strum::IntoEnumIterator;
+
+#[rstest]
+#[case::default(
+    ParamColumnOrder::SortedDefault,
+    &["AECFREEZEONOFF", "AGCONOFF", "CNIONOFF", "ECHOONOFF"]
+)]
+#[case::alphabetical(
+    ParamColumnOrder::Alphabetical,
+    &["AECFREEZEONOFF", "AECNORM", "AECPATHCHANGE", "AECSILENCELEVEL"]
+)]
+#[case::category(
+    ParamColumnOrder::ByCategory,
+    &["RT60", "RT60ONOFF", "AGCDESIREDLEVEL", "AGCGAIN"]
+)]
+#[case::firmware_id(
+    ParamColumnOrder::ByFirmwareId,
+    &["AECFREEZEONOFF", "AECNORM", "AECPATHCHANGE", "RT60", "HPFONOFF"]
+)]
+fn csv_header_follows_column_order(#[case] order: ParamColumnOrder, #[case] expected: &[&str]) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    drop(CsvWriter::new_with_order(&csv_path, order).expect("CSV writer"));
+
+    let csv = std::fs::read_to_string(&csv_path).expect("Recording exists");
+    let header = csv.lines().next().expect("Header row");
+    let columns = header.split(',').skip(2).collect::<Vec<_>>();
+    assert_eq!(columns.len(), ParamKind::iter().count());
+    let expected = expected.join(",");
+    assert!(columns.join(",").contains(&expected), "{header}");
+    let expected_columns = order
+        .columns()
+        .iter()
+        .map(|p| format!("{p:?}"))
+        .collect::<Vec<_>>();
+    assert_eq!(columns, expected_columns);
+}
+
+#[test]
+fn csv_writer_float_precision() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let mut writer = CsvWriter::new(&csv_path).expect("CSV writer");
+    let values = HashMap::from([(ParamKind::RT60, Value::Float(0.5))]);
+    writer
+        .write_row("a", "b", &values)
+        .expect("Failed to write row");
+    writer.set_float_precision(2);
+    writer
+        .write_row("a", "b", &values)
+        .expect("Failed to write row");
+    drop(writer);
+
+    let csv = std::fs::read_to_string(&csv_path).expect("Recording exists");
+    let rows = csv.lines().skip(1).collect::<Vec<_>>();
+    assert!(rows[0].split(',').any(|cell| cell == "0.5"), "{}", rows[0]);
+    assert!(
+        rows[1].split(',').any(|cell| cell == "5.0e-1"),
+        "{}",
+        rows[1]
+    );
+}
+
+#[test]
+fn csv_writer_compresses_rows_in_one_stream() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv.gz");
+    let mut writer = CsvWriter::with_options(
+        &csv_path,
+        &CsvWriterOptions {
+            compress: true,
+            ..Default::default()
+        },
+    )
+    .expect("CSV writer");
+    let values = HashMap::from([(ParamKind::RT60, Value::Float(0.5))]);
+    for _ in 0..200 {
+        writer
+            .write_row("a", "b", &values)
+            .expect("Failed to write row");
+    }
+    writer.finish().expect("Failed to finish the recording");
+
+    let compressed = std::fs::read(&csv_path).expect("Recording exists");
+    let mut csv = String::new();
+    flate2::read::GzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut csv)
+        .expect("Complete gzip stream");
+    assert_eq!(csv.lines().count(), 201);
+    // Flushing the encoder after every row would add a sync marker per row
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder
+        .write_all(csv.as_bytes())
+        .expect("Failed to compress");
+    let expected = encoder.finish().expect("Failed to compress");
+    assert_eq!(compressed.len(), expected.len());
+}
+
+#[test]
+fn csv_writer_rotates_files() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("session.csv");
+    // Each row has all parameters, so every row exceeds the limit
+    let mut writer = CsvWriter::with_max_file_size(&csv_path, Some(100)).expect("CSV writer");
+    let values = ParamKind::iter()
+        .map(|p| (p, Value::Int(1)))
+        .collect::<HashMap<_, _>>();
+    for _ in 0..2 {
+        writer
+            .write_row("a", "b", &values)
+            .expect("Failed to write row");
+    }
+    assert_eq!(writer.part(), 3);
+    drop(writer);
+
+    assert!(!csv_path.exists());
+    for part in ["session_001.csv", "session_002.csv"] {
+        let rows = CsvReader::new(&dir.path().join(part))
+            .expect("Part is a recording")
+            .rows()
+            .collect::<eyre::Result<Vec<_>>>()
+            .expect("Invalid row");
+        assert_eq!(rows.len(), 1, "{part}");
+    }
+    // The last part only has the header
+    let last = CsvReader::new(&dir.path().join("session_003.csv"))
+        .expect("Part is a recording")
+        .rows()
+        .count();
+    assert_eq!(last, 0);
+}
+
+#[test]
+fn csv_writer_audio_sample_offset() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let audio = AudioTimeline {
+        started_at: "2025-03-01T12:00:00+01:00"
+            .parse::<chrono::DateTime<chrono::FixedOffset>>()
+            .expect("Valid timestamp")
+            .into(),
+        sample_rate: 16000,
+        duration: Duration::from_secs(10),
+    };
+    let mut writer = CsvWriter::with_options(
+        &csv_path,
+        &CsvWriterOptions {
+            audio: Some(audio.clone()),
+            ..Default::default()
+        },
+    )
+    .expect("CSV writer");
+    let values = HashMap::from([(ParamKind::AGCONOFF, Value::Int(1))]);
+    for before in ["2025-03-01T12:00:00.5+01:00", "RW_REFRESH"] {
+        writer
+            .write_row(before, "2025-03-01T12:00:00.6+01:00", &values)
+            .expect("Failed to write row");
+    }
+    drop(writer);
+
+    let csv = std::fs::read_to_string(&csv_path).expect("Recording exists");
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert!(lines[0].ends_with(",audio_sample_offset"), "{}", lines[0]);
+    assert!(lines[1].ends_with(",8000"), "{}", lines[1]);
+    assert!(lines[2].ends_with(','), "{}", lines[2]);
+    // The extra column doesn't disturb the reader
+    let rows = CsvReader::new(&csv_path)
+        .expect("Recording is readable")
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert_eq!(
+        rows[0].values.get(&ParamKind::AGCONOFF),
+        Some(&Value::Int(1))
+    );
+
+    assert!(audio.check_aligned("2025-03-01T12:00:00.09+01:00").is_ok());
+    assert!(audio.check_aligned("2025-03-01T11:59:59.8+01:00").is_err());
+}
+
+#[test]
+fn csv_writer_rotation_needs_a_file() {
+    assert!(CsvWriter::with_max_file_size(std::path::Path::new("-"), Some(100)).is_err());
+}
@@ -0,0 +1,404 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use respeaker::mock::MockDevice;
+use respeaker::params::{Access, DeviceModel, ParamKind, ParamSortOrder, ParamState, Value};
+use respeaker::respeaker_device::{
+    ExportFormat, ReSpeakerDevice, StressTestResult, DEFAULT_TIMEOUT,
+};
+use rstest::rstest;
+use strum::IntoEnumIterator;
+
+fn mock_device() -> (Arc<MockDevice>, ReSpeakerDevice) {
+    let mock = Arc::new(MockDevice::new());
+    let device =
+        ReSpeakerDevice::open_mock(mock.clone(), Arc::new(Mutex::new(ParamState::default())));
+    (mock, device)
+}
+
+#[test]
+fn metrics_count_transfers() {
+    let (_mock, device) = mock_device();
+    device
+        .read(&ParamKind::DOAANGLE)
+        .expect("Read must succeed");
+    device.read(&ParamKind::AGCGAIN).expect("Read must succeed");
+    device
+        .write(&ParamKind::AGCONOFF, &Value::Int(1))
+        .expect("Write must succeed");
+    // Rejected before any USB transfer, so not an error
+    assert!(device.write(&ParamKind::AGCONOFF, &Value::Int(2)).is_err());
+
+    let metrics = device.metrics();
+    assert_eq!(metrics.reads, 2);
+    assert_eq!(metrics.writes, 1);
+    assert_eq!(metrics.errors, 0);
+    let text = metrics.to_prometheus_text();
+    assert!(text.contains(
+        "# HELP respeaker_usb_reads_total Successful parameter reads\n\
+         # TYPE respeaker_usb_reads_total counter\n\
+         respeaker_usb_reads_total 2\n"
+    ));
+    assert!(text.contains("respeaker_usb_writes_total 1\n"));
+    assert!(text.contains("respeaker_usb_errors_total 0\n"));
+    assert!(text.contains("# TYPE respeaker_usb_mean_read_latency_seconds gauge\n"));
+    assert!(metrics
+        .to_string()
+        .starts_with("USB transfers: 2 reads, 1 writes, 0 errors"));
+}
+
+#[test]
+fn writes_are_audited() {
+    let (mock, device) = mock_device();
+    mock.set(&ParamKind::AGCONOFF, Value::Int(1));
+
+    device
+        .write(&ParamKind::AGCONOFF, &Value::Int(0))
+        .expect("Write must succeed");
+    // The uncached old value is not read from the device
+    assert!(mock.transfers().iter().all(|t| t.request_type & 0x80 == 0));
+    device
+        .write(&ParamKind::AGCONOFF, &Value::Int(1))
+        .expect("Write must succeed");
+    assert!(device.write(&ParamKind::AGCONOFF, &Value::Int(2)).is_err());
+
+    let log = device
+        .params()
+        .lock()
+        .expect("Lock failed")
+        .audit_log()
+        .to_vec();
+    assert!(log[0].0 <= log[1].0);
+    let log = log
+        .into_iter()
+        .map(|(_, param, old, new)| (param, old, new))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        log,
+        [
+            (ParamKind::AGCONOFF, None, Value::Int(0)),
+            (ParamKind::AGCONOFF, Some(Value::Int(0)), Value::Int(1)),
+        ]
+    );
+}
+
+#[test]
+fn subscription_receives_values() {
+    let (mock, device) = mock_device();
+    mock.set(&ParamKind::DOAANGLE, Value::Int(42));
+
+    let values = device
+        .subscribe_param(ParamKind::DOAANGLE, Duration::from_millis(1))
+        .take(3)
+        .collect::<Vec<_>>();
+
+    assert_eq!(values, vec![Value::Int(42); 3]);
+}
+
+#[test]
+fn subscription_stops_while_reads_fail() {
+    let mock = Arc::new(MockDevice::new());
+    let device =
+        ReSpeakerDevice::open_mock_model(mock.clone(), Arc::default(), DeviceModel::MicLinear4);
+
+    // The linear array has no DOAANGLE, so every read fails and nothing is ever sent
+    let subscription = device.subscribe_param(ParamKind::DOAANGLE, Duration::from_millis(1));
+    drop(device);
+    drop(subscription);
+    std::thread::sleep(Duration::from_millis(100));
+
+    // The polling thread held the last clone of the device
+    assert_eq!(Arc::strong_count(&mock), 1);
+}
+
+#[test]
+fn on_change_is_called_for_changed_values() {
+    let (mock, device) = mock_device();
+    let changes = Arc::new(Mutex::new(vec![]));
+    {
+        let changes = changes.clone();
+        device.set_on_change(move |param, old, new| {
+            changes.lock().expect("Lock failed").push((param, old, new));
+        });
+    }
+
+    mock.set(&ParamKind::VOICEACTIVITY, Value::Int(0));
+    device.read(&ParamKind::VOICEACTIVITY).expect("Read failed");
+    device.read(&ParamKind::VOICEACTIVITY).expect("Read failed");
+    mock.set(&ParamKind::VOICEACTIVITY, Value::Int(1));
+    device.read(&ParamKind::VOICEACTIVITY).expect("Read failed");
+
+    assert_eq!(
+        *changes.lock().expect("Lock failed"),
+        vec![(ParamKind::VOICEACTIVITY, Value::Int(0), Value::Int(1))]
+    );
+}
+
+#[rstest]
+#[case::in_range(ParamKind::DOAANGLE, Value::Int(180), true)]
+#[case::angle_out_of_range(ParamKind::DOAANGLE, Value::Int(400), false)]
+#[case::flag_out_of_range(ParamKind::AGCONOFF, Value::Int(7), false)]
+fn firmware_check_validates_ranges(
+    #[case] param: ParamKind,
+    #[case] value: Value,
+    #[case] compatible: bool,
+) {
+    let (mock, device) = mock_device();
+    mock.set(&param, value);
+
+    let check = device.check_firmware_compat().expect("Check failed");
+
+    assert_eq!(check.compatible, compatible);
+    assert_eq!(check.warnings.len(), usize::from(!compatible));
+}
+
+#[test]
+fn firmware_check_skips_missing_params() {
+    let mock = Arc::new(MockDevice::new());
+    // Out of range, but the linear array has no DOAANGLE
+    mock.set(&ParamKind::DOAANGLE, Value::Int(400));
+    let device = ReSpeakerDevice::open_mock_model(
+        mock.clone(),
+        Arc::new(Mutex::new(ParamState::default())),
+        DeviceModel::MicLinear4,
+    );
+
+    let check = device.check_firmware_compat().expect("Check failed");
+
+    assert!(check.compatible, "{:?}", check.warnings);
+    assert_eq!(mock.transfers().len(), 1);
+}
+
+#[test]
+fn firmware_revert_sends_dfu_request() {
+    let (mock, device) = mock_device();
+
+    device.firmware_revert().expect("Revert failed");
+
+    let transfers = mock.transfers();
+    assert_eq!(transfers.len(), 1);
+    // Class request to the DFU interface
+    assert_eq!(transfers[0].request_type, 0x21);
+    assert_eq!(transfers[0].request, 0xF1);
+    assert_eq!(transfers[0].index, u16::from(device.interface_number()));
+}
+
+#[test]
+fn dfu_status_reports_state() {
+    let (mock, device) = mock_device();
+
+    let status = device.dfu_status().expect("DFU status failed");
+    assert!(status.is_idle());
+    assert_eq!(status.status, 0);
+
+    // dfuERROR
+    mock.set_dfu_state(10);
+    let status = device.dfu_status().expect("DFU status failed");
+    assert!(!status.is_idle());
+    assert_eq!(status.state, 10);
+
+    let transfers = mock.transfers();
+    assert_eq!(transfers[0].request_type, 0xA1);
+    assert_eq!(transfers[0].request, 3);
+}
+
+#[test]
+fn device_accessors() {
+    let (_mock, device) = mock_device();
+    assert_eq!(device.usb_timeout(), DEFAULT_TIMEOUT);
+
+    device.set_timeout(Duration::from_millis(250));
+    assert_eq!(device.usb_timeout(), Duration::from_millis(250));
+    assert_eq!(device.index(), 0);
+    assert_eq!(device.model(), DeviceModel::MicArrayV2);
+}
+
+#[test]
+fn mic_array_has_all_params() {
+    let (_mock, device) = mock_device();
+
+    assert!(device.has_param(&ParamKind::DOAANGLE));
+    assert_eq!(
+        device.available_params(),
+        ParamKind::iter().collect::<Vec<_>>()
+    );
+}
+
+#[rstest]
+#[case::table(
+    ExportFormat::Table,
+    "| AGCONOFF             | 1           | int   | rw     | 0..1"
+)]
+#[case::csv(
+    ExportFormat::Csv,
+    "\nAGCONOFF,1,int,rw,0..1,-,Automatic Gain Control. \n"
+)]
+#[cfg_attr(
+    feature = "serde",
+    case::json(
+        ExportFormat::Json,
+        r#""name": "AGCONOFF",
+    "range": "0..1",
+    "since": "-",
+    "type": "int",
+    "value": 1"#
+    )
+)]
+#[case::toml(ExportFormat::Toml, "\nAGCONOFF = 1\n")]
+#[case::compact(
+    ExportFormat::Compact,
+    "\nAGCONOFF             = 1 (ON)                   [0..1]             rw\n"
+)]
+#[case::markdown(
+    ExportFormat::Markdown,
+    "| Name | Value | Type | Access | Range | Description | Values |\n|------|"
+)]
+fn export_to_writer(#[case] format: ExportFormat, #[case] expected: &str) {
+    let (mock, device) = mock_device();
+    mock.set(&ParamKind::AGCONOFF, Value::Int(1));
+    let mut out = vec![];
+
+    device
+        .export_to_writer(&mut out, format)
+        .expect("Export succeeds");
+
+    let out = String::from_utf8(out).expect("UTF-8 output");
+    assert!(out.contains(expected), "{out}");
+}
+
+#[test]
+fn export_compact_fits_80_columns() {
+    let (mock, device) = mock_device();
+    mock.set(&ParamKind::AECFREEZEONOFF, Value::Int(1));
+    mock.set(&ParamKind::AECSILENCELEVEL, Value::Float(1e-9));
+    let mut out = vec![];
+
+    device
+        .export_to_writer(&mut out, ExportFormat::Compact)
+        .expect("Export succeeds");
+
+    let out = String::from_utf8(out).expect("UTF-8 output");
+    assert_eq!(out.lines().count(), ParamKind::iter().count());
+    for line in out.lines() {
+        assert!(line.chars().count() <= 80, "{line}");
+    }
+    assert!(out.contains("= 0                        [0..359]           ro\n"));
+}
+
+#[test]
+fn export_csv_header_and_filter() {
+    let (_, device) = mock_device();
+    let mut out = vec![];
+
+    device
+        .export_sorted_to_writer(
+            &mut out,
+            ExportFormat::Csv,
+            Some(Access::ReadOnly),
+            Some(ParamSortOrder::Name),
+        )
+        .expect("Export succeeds");
+
+    let out = String::from_utf8(out).expect("UTF-8 output");
+    let mut lines = out.lines();
+    assert_eq!(
+        lines.next(),
+        Some("name,value,type,access,range,since,description")
+    );
+    let names = lines
+        .map(|line| line.split(',').next().unwrap_or_default())
+        .collect::<Vec<_>>();
+    let mut sorted = names.clone();
+    sorted.sort_unstable();
+    assert_eq!(names, sorted);
+    assert!(names.contains(&"DOAANGLE"));
+    assert!(!names.contains(&"AGCONOFF"));
+}
+
+#[test]
+fn export_format_display() {
+    assert_eq!(ExportFormat::Toml.to_string(), "toml");
+}
+
+#[test]
+fn snapshot_stream() {
+    let (mock, device) = mock_device();
+    mock.set(&ParamKind::DOAANGLE, Value::Int(42));
+    let start = std::time::Instant::now();
+
+    let mut stream = device.into_snapshot_stream(Duration::from_millis(20));
+    let snapshots = stream
+        .by_ref()
+        .take(3)
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Reads succeed");
+
+    assert!(start.elapsed() >= Duration::from_millis(40));
+    for snapshot in &snapshots {
+        assert_eq!(
+            snapshot.current_params.get(&ParamKind::DOAANGLE),
+            Some(&Value::Int(42))
+        );
+        assert!(!snapshot.current_params.contains_key(&ParamKind::AGCONOFF));
+    }
+    let mut stream = stream
+        .into_inner()
+        .into_snapshot_stream(Duration::ZERO)
+        .all_params();
+    let snapshot = stream.next().expect("Endless").expect("Reads succeed");
+    assert_eq!(snapshot.current_params.len(), ParamKind::iter().count());
+    assert_eq!(
+        stream
+            .into_inner()
+            .read(&ParamKind::DOAANGLE)
+            .expect("Read succeeds"),
+        Value::Int(42)
+    );
+}
+
+#[test]
+fn stress_test_on_mock() {
+    let (mock, device) = mock_device();
+    mock.set(&ParamKind::HPFONOFF, Value::Int(2));
+
+    let result = device
+        .stress_test(10, &AtomicBool::new(true))
+        .expect("Stress test runs");
+
+    assert_eq!(
+        StressTestResult {
+            min_latency_us: 0,
+            max_latency_us: 0,
+            ..result
+        },
+        StressTestResult {
+            total: 10,
+            ..Default::default()
+        }
+    );
+    assert!(result.min_latency_us <= result.max_latency_us);
+    assert!(result.error_rate_percent().abs() < f64::EPSILON);
+    // 10 cycles and restoring the 3 parameters
+    assert_eq!(device.metrics().writes, 13);
+    // HPFONOFF alternates with its minimum, then its original value is restored
+    let written = mock
+        .transfers()
+        .iter()
+        .filter(|t| t.request_type == 0x40 && t.index == 18 && t.data[0] == 27)
+        .map(|t| t.data[4])
+        .collect::<Vec<_>>();
+    assert_eq!(written, [0, 2, 0, 2, 2]);
+    assert_eq!(mock.get(&ParamKind::HPFONOFF), Some(Value::Int(2)));
+}
+
+#[test]
+fn stress_test_stops_when_not_running() {
+    let (_, device) = mock_device();
+
+    let result = device
+        .stress_test(1000, &AtomicBool::new(false))
+        .expect("Stress test runs");
+
+    assert_eq!(result.total, 0);
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "debug")]
+
+use respeaker::packet_dump::{format_transfer, parse_usbmon_line, Direction, UsbmonEvent};
+
+#[test]
+fn parses_control_in_submission() {
+    let event =
+        parse_usbmon_line("ffff8b2d4c1e0a80 3575914555 S Ci:1:002:0 s c0 00 00c0 0015 0008 8 <");
+
+    assert_eq!(
+        event,
+        Some(UsbmonEvent::Submit {
+            tag: "ffff8b2d4c1e0a80".to_string(),
+            direction: Direction::In,
+            device: 2,
+            request_type: 0xC0,
+            value: 0xC0,
+            index: 21,
+            data: vec![],
+        })
+    );
+}
+
+#[test]
+fn parses_completion_data() {
+    let event =
+        parse_usbmon_line("ffff8b2d4c1e0a80 3575914600 C Ci:1:002:0 0 8 = 0a000000 00000000");
+
+    assert_eq!(
+        event,
+        Some(UsbmonEvent::Complete {
+            tag: "ffff8b2d4c1e0a80".to_string(),
+            device: 2,
+            data: vec![0x0A, 0, 0, 0, 0, 0, 0, 0],
+        })
+    );
+}
+
+#[test]
+fn ignores_non_control_transfers() {
+    assert_eq!(
+        parse_usbmon_line("ffff8b2d4c1e0a80 3575914600 C Ii:1:002:1 0 4 = 01020304"),
+        None
+    );
+}
+
+#[test]
+fn formats_read_response() {
+    let line = format_transfer(Direction::In, 0xC0, 21, &[0x0A, 0, 0, 0, 0, 0, 0, 0]);
+
+    assert_eq!(
+        line,
+        "[IN] cmd=0xC0 id=21 -> bytes=[0A 00 00 00 00 00 00 00] = DOAANGLE: 10°"
+    );
+}
+
+#[test]
+fn formats_write_payload() {
+    let payload = [0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0];
+    let line = format_transfer(Direction::Out, 0, 19, &payload);
+
+    assert_eq!(
+        line,
+        "[OUT] cmd=0x00 id=19 -> bytes=[00 00 00 00 01 00 00 00 01 00 00 00] = AGCONOFF: 1"
+    );
+}
@@ -0,0 +1,441 @@
+use std::sync::{Arc, Mutex};
+
+use proptest::prelude::*;
+use respeaker::mock::MockDevice;
+use respeaker::params::{
+    Access, DeviceModel, ParamCategory, ParamKind, ParamState, ParamType, Value,
+};
+use respeaker::respeaker_device::ReSpeakerDevice;
+use rstest::rstest;
+use strum::IntoEnumIterator;
+
+fn any_param() -> impl Strategy<Value = ParamKind> {
+    prop::sample::select(ParamKind::iter().collect::<Vec<_>>())
+}
+
+/// A parameter together with the string representation of an in-range value.
+fn param_with_valid_value() -> impl Strategy<Value = (ParamKind, String)> {
+    any_param().prop_flat_map(|param| match param.def().param_type {
+        ParamType::IntDiscete { min, max } | ParamType::IntRange { min, max } => (min..=max)
+            .prop_map(move |v| (param.clone(), v.to_string()))
+            .boxed(),
+        ParamType::FloatRange { min, max } => (min..=max)
+            .prop_map(move |v| (param.clone(), v.to_string()))
+            .boxed(),
+    })
+}
+
+fn mock_device() -> (Arc<MockDevice>, ReSpeakerDevice) {
+    let mock = Arc::new(MockDevice::new());
+    let device =
+        ReSpeakerDevice::open_mock(mock.clone(), Arc::new(Mutex::new(ParamState::default())));
+    (mock, device)
+}
+
+fn assert_same_value(expected: &Value, actual: &Value) {
+    match (expected, actual) {
+        (Value::Int(e), Value::Int(a)) => assert_eq!(e, a),
+        (Value::Float(e), Value::Float(a)) => {
+            assert!((e - a).abs() <= e.abs() * 1e-6, "expected {e}, got {a}");
+        }
+        _ => panic!("Type mismatch: expected {expected:?}, got {actual:?}"),
+    }
+}
+
+proptest! {
+    #[test]
+    fn parse_value_never_panics(param in any_param(), input in any::<String>()) {
+        if let Ok(Value::Float(f)) = param.parse_value(&input) {
+            prop_assert!(f.is_finite());
+            prop_assert!(!f.is_sign_negative() || f != 0.0);
+        }
+    }
+
+    #[test]
+    fn parse_value_accepts_numeric_strings(param in any_param(), input in "-?[0-9]{1,12}(\\.[0-9]{0,6})?") {
+        let result = param.parse_value(&input);
+        if !param.def().param_type.is_int() {
+            prop_assert!(result.is_ok(), "{input} rejected: {result:?}");
+        }
+    }
+
+    #[test]
+    fn write_read_round_trip((param, input) in param_with_valid_value()) {
+        let (mock, device) = mock_device();
+        let value = param.parse_value(&input).expect("In-range value must parse");
+
+        if param.def().access == Access::ReadWrite {
+            device.write(&param, &value).expect("In-range write must succeed");
+        } else {
+            mock.set(&param, value.clone());
+        }
+
+        let read_back = device.read(&param).expect("Read must succeed");
+        assert_same_value(&value, &read_back);
+    }
+}
+
+#[rstest]
+#[case("inf")]
+#[case("-inf")]
+#[case("infinity")]
+#[case("NaN")]
+fn parse_value_rejects_non_finite_floats(#[case] input: &str) {
+    assert!(ParamKind::AGCMAXGAIN.parse_value(input).is_err());
+}
+
+#[test]
+fn parse_value_normalizes_negative_zero() {
+    let value = ParamKind::AGCGAIN.parse_value("-0").expect("-0 must parse");
+    assert!(matches!(value, Value::Float(f) if f == 0.0 && f.is_sign_positive()));
+}
+
+#[rstest]
+#[case(ParamKind::AGCGAIN, Value::Float(5000.0), Value::Float(1000.0))]
+#[case(ParamKind::AGCGAIN, Value::Float(0.5), Value::Float(1.0))]
+#[case(ParamKind::AGCGAIN, Value::Float(42.0), Value::Float(42.0))]
+#[case(ParamKind::DOAANGLE, Value::Int(400), Value::Int(359))]
+#[case(ParamKind::AGCONOFF, Value::Int(7), Value::Int(1))]
+fn read_sanitizes_out_of_range_values(
+    #[case] param: ParamKind,
+    #[case] raw: Value,
+    #[case] expected: Value,
+) {
+    let (mock, device) = mock_device();
+    mock.set(&param, raw);
+
+    let value = device.read(&param).expect("Read must succeed");
+    assert_same_value(&expected, &value);
+}
+
+#[test]
+fn from_index_finds_every_param() {
+    for param in ParamKind::iter() {
+        let def = param.def();
+        assert_eq!(ParamKind::from_index(def.index, def.cmd), Some(param));
+    }
+    assert_eq!(ParamKind::from_index(0, 0), None);
+}
+
+#[rstest]
+#[case(ParamKind::DOAANGLE, 0xC0, 0)]
+#[case(ParamKind::AGCMAXGAIN, 0x81, 1)]
+#[case(ParamKind::RT60, 0x9A, 26)]
+fn usb_cmd_encoding(#[case] param: ParamKind, #[case] read: u16, #[case] write: u16) {
+    assert_eq!(param.read_usb_cmd(), read);
+    assert_eq!(param.write_usb_cmd(), write);
+    assert_eq!(
+        ParamKind::from_index(param.def().index, read & !0xC0),
+        Some(param)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn param_state_json_round_trip() {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::DOAANGLE, &Value::Int(42));
+    state.update(&ParamKind::RT60, &Value::Float(0.45));
+
+    let json = state.to_json_string();
+    assert_eq!(json, r#"{"DOAANGLE":42,"RT60":0.45}"#);
+
+    let parsed = ParamState::from_json_str(&json).expect("Valid JSON");
+    assert_eq!(parsed.current_params, state.current_params);
+}
+
+#[cfg(feature = "serde")]
+#[rstest]
+#[case("[1, 2]")]
+#[case(r#"{"NOTAPARAM": 1}"#)]
+#[case(r#"{"DOAANGLE": 1.5}"#)]
+#[case(r#"{"RT60": "fast"}"#)]
+#[case(r#"{"AGCONOFF": 2}"#)]
+fn param_state_rejects_invalid_json(#[case] json: &str) {
+    assert!(ParamState::from_json_str(json).is_err());
+}
+
+#[rstest]
+#[case::overwrite(true, Value::Int(180))]
+#[case::fill_missing(false, Value::Int(90))]
+fn param_state_merge_from(#[case] overwrite: bool, #[case] expected_doa: Value) {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::DOAANGLE, &Value::Int(90));
+    let mut other = ParamState::default();
+    other.update(&ParamKind::DOAANGLE, &Value::Int(180));
+    other.update(&ParamKind::RT60, &Value::Float(0.45));
+
+    state.merge_from(&other, overwrite);
+
+    assert_eq!(state.keys().count(), 2);
+    assert_eq!(state.current_params[&ParamKind::DOAANGLE], expected_doa);
+    assert_eq!(state.current_params[&ParamKind::RT60], Value::Float(0.45));
+    assert!(state.values().any(|v| *v == Value::Float(0.45)));
+}
+
+#[test]
+fn param_state_count_by_access() {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::AGCONOFF, &Value::Int(1));
+    state.update(&ParamKind::DOAANGLE, &Value::Int(42));
+    state.update(&ParamKind::RT60, &Value::Float(0.45));
+
+    assert_eq!(state.count_by_access(), (1, 2));
+    assert!(!state.is_complete());
+
+    for p in ParamKind::iter() {
+        let value = if p.def().param_type.is_int() {
+            Value::Int(0)
+        } else {
+            Value::Float(0.0)
+        };
+        state.update(&p, &value);
+    }
+    assert!(state.is_complete());
+}
+
+#[rstest]
+#[case(ParamKind::AGCMAXGAIN, Value::Float(31.6), Some(30.0))]
+#[case(ParamKind::MIN_NS, Value::Float(0.15), Some(-16.5))]
+#[case(ParamKind::AGCDESIREDLEVEL, Value::Float(0.005), Some(-23.0))]
+#[case(ParamKind::GAMMA_NS, Value::Float(1.0), None)]
+#[case(ParamKind::AGCONOFF, Value::Int(1), None)]
+fn db_interpretation(
+    #[case] param: ParamKind,
+    #[case] value: Value,
+    #[case] expected: Option<f32>,
+) {
+    let db = param.to_db(&value);
+    assert_eq!(db.is_some(), expected.is_some());
+    if let (Some(db), Some(expected)) = (db, expected) {
+        assert!((db - expected).abs() < 0.1, "{db} != {expected}");
+    }
+}
+
+#[rstest]
+#[case(ParamKind::AGCONOFF, Some(2))]
+#[case(ParamKind::HPFONOFF, Some(4))]
+#[case(ParamKind::DOAANGLE, Some(360))]
+#[case(ParamKind::RT60, None)]
+fn param_type_cardinality(#[case] param: ParamKind, #[case] expected: Option<usize>) {
+    let def = param.def();
+    assert_eq!(def.param_type.cardinality(), expected);
+    assert_eq!(def.param_type.is_binary(), expected == Some(2));
+    if let ParamType::IntDiscete { .. } = def.param_type {
+        assert_eq!(
+            def.param_type.cardinality(),
+            Some(def.value_descriptions.len())
+        );
+    }
+}
+
+#[test]
+fn param_state_env_vars_round_trip() {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::AGCMAXGAIN, &Value::Float(31.6));
+    state.update(&ParamKind::AGCONOFF, &Value::Int(1));
+
+    let mut env = state.to_env_vars();
+    assert_eq!(
+        env,
+        [
+            ("RESPEAKER_AGCMAXGAIN".to_string(), "31.6".to_string()),
+            ("RESPEAKER_AGCONOFF".to_string(), "1".to_string()),
+        ]
+    );
+
+    env.push(("RESPEAKER_MOCK".to_string(), "DOAANGLE=1".to_string()));
+    env.push(("HOME".to_string(), "/root".to_string()));
+    let parsed = ParamState::from_env_vars(env).expect("Valid environment");
+    assert_eq!(parsed.current_params, state.current_params);
+
+    let invalid = [("RESPEAKER_AGCONOFF".to_string(), "on".to_string())];
+    assert!(ParamState::from_env_vars(invalid).is_err());
+}
+
+#[test]
+fn prometheus_metric_names_are_valid() {
+    for p in ParamKind::iter() {
+        let name = p.as_prometheus_metric_name();
+        let mut chars = name.chars();
+        assert!(chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':'));
+        assert!(
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':'),
+            "{name}"
+        );
+        assert!(!p.as_prometheus_help().contains('\n'));
+    }
+    assert_eq!(
+        ParamKind::GAMMA_NN_SR.as_prometheus_metric_name(),
+        "respeaker_gamma_nn_sr"
+    );
+    assert!(ParamKind::RT60.as_prometheus_help().ends_with(" [s]"));
+}
+
+#[test]
+fn every_category_has_params() {
+    for category in ParamCategory::iter() {
+        assert!(!category.params().is_empty(), "{category:?}");
+    }
+}
+
+#[test]
+fn alternative_sort_orders() {
+    let by_name = ParamKind::sorted_by_name();
+    assert_eq!(by_name.first(), Some(&ParamKind::AECFREEZEONOFF));
+    assert_eq!(by_name.last(), Some(&ParamKind::VOICEACTIVITY));
+
+    let by_category = ParamKind::sorted_by_category();
+    assert!(by_category.is_sorted_by_key(ParamKind::category));
+    assert_eq!(by_category.len(), ParamKind::iter().count());
+
+    let by_access = ParamKind::sorted_by_access();
+    let first_ro = by_access
+        .iter()
+        .position(|p| p.def().access == Access::ReadOnly)
+        .expect("There are RO parameters");
+    assert!(by_access[first_ro..]
+        .iter()
+        .all(|p| p.def().access == Access::ReadOnly));
+    let rw_by_name = by_name
+        .iter()
+        .filter(|p| p.def().access == Access::ReadWrite)
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(by_access[..first_ro], rw_by_name);
+}
+
+#[rstest]
+#[case(ParamKind::HPFONOFF, Value::Int(1), "1 (ON - 70 Hz cut-off)")]
+#[case(ParamKind::RT60, Value::Float(0.45), "0.45 s")]
+#[case(ParamKind::AGCMAXGAIN, Value::Float(31.6), "31.6")]
+#[case(ParamKind::DOAANGLE, Value::Int(42), "42")]
+fn display_string_has_context(
+    #[case] param: ParamKind,
+    #[case] value: Value,
+    #[case] expected: &str,
+) {
+    assert_eq!(value.to_display_string(&param.def()), expected);
+}
+
+#[test]
+fn only_rw_params_are_writeable() {
+    for param in ParamKind::iter() {
+        let writeable = param.as_writeable();
+        assert_eq!(
+            writeable.is_some(),
+            param.def().access == Access::ReadWrite,
+            "{param:?}"
+        );
+        if let Some(writeable) = writeable {
+            assert_eq!(writeable.param(), &param);
+        }
+    }
+}
+
+#[test]
+fn linear_array_has_no_doa() {
+    assert!(DeviceModel::MicArrayV2.supports(&ParamKind::DOAANGLE));
+    assert!(!DeviceModel::MicLinear4.supports(&ParamKind::DOAANGLE));
+    assert_eq!(
+        DeviceModel::from_product_id(DeviceModel::MicLinear4.product_id()),
+        Some(DeviceModel::MicLinear4)
+    );
+}
+
+#[test]
+fn param_state_prometheus_text() {
+    let mut state = ParamState::default();
+    state.update(&ParamKind::VOICEACTIVITY, &Value::Int(0));
+    state.update(&ParamKind::VOICEACTIVITY, &Value::Int(1));
+    state.update(&ParamKind::RT60, &Value::Float(0.45));
+
+    let text = state.to_prometheus_text();
+
+    assert!(text.contains(
+        "# HELP respeaker_rt60 Current RT60 estimate in seconds [s]\n\
+         # TYPE respeaker_rt60 gauge\n\
+         respeaker_rt60 0.45\n"
+    ));
+    assert!(text.contains("respeaker_voiceactivity 1\n"));
+    assert!(text.contains("respeaker_voice_activity_events_total 1\n"));
+    assert!(text.contains("respeaker_speech_detection_events_total 0\n"));
+    assert!(!text.contains("respeaker_doaangle"));
+}
+
+#[rstest]
+#[case(ParamKind::AGCDESIREDLEVEL, Some("-23dBov = 10log10(0.005)"))]
+#[case(ParamKind::AGCMAXGAIN, Some("30dB = 20log10(31.6)"))]
+#[case(ParamKind::HPFONOFF, None)]
+fn default_hint_from_description(#[case] param: ParamKind, #[case] expected: Option<&str>) {
+    assert_eq!(param.def().default_hint(), expected);
+}
+
+#[rstest]
+#[case(ParamKind::AGCMAXGAIN, Some(Value::Float(31.6)))]
+#[case(ParamKind::AGCDESIREDLEVEL, Some(Value::Float(0.005)))]
+#[case(ParamKind::AECSILENCELEVEL, Some(Value::Float(1e-8)))]
+#[case(ParamKind::GAMMA_NN_SR, Some(Value::Float(1.1)))]
+#[case(ParamKind::GAMMAVAD_SR, Some(Value::Float(1.5)))]
+#[case(ParamKind::HPFONOFF, None)]
+fn default_value_from_description(#[case] param: ParamKind, #[case] expected: Option<Value>) {
+    assert_eq!(param.def().default_value(), expected);
+}
+
+#[rstest]
+#[case::discrete(
+    ParamKind::HPFONOFF,
+    Value::Int(1),
+    "| HPFONOFF | 1 | int | rw | 0..3 | High-pass Filter on microphone signals. | 0 = OFF, 1 = ON - 70 Hz cut-off, 2 = ON - 125 Hz cut-off, 3 = ON - 180 Hz cut-off |"
+)]
+#[case::float(
+    ParamKind::RT60,
+    Value::Float(0.45),
+    "| RT60 | 0.45 | float | ro | 0.25..0.9 | Current RT60 estimate in seconds |  |"
+)]
+fn markdown_table_row(#[case] param: ParamKind, #[case] value: Value, #[case] expected: &str) {
+    assert_eq!(param.def().to_markdown_table_row(&value), expected);
+}
+
+proptest! {
+    #[test]
+    fn csv_string_round_trip((param, input) in param_with_valid_value()) {
+        let value = param.parse_value(&input).expect("In-range value must parse");
+        let parsed = param
+            .parse_value(&value.to_csv_string())
+            .expect("CSV string must parse");
+
+        match (&value, &parsed) {
+            (Value::Int(v), Value::Int(p)) => prop_assert_eq!(v, p),
+            (Value::Float(v), Value::Float(p)) => {
+                prop_assert!((v - p).abs() <= v.abs() * f32::EPSILON, "{} became {}", v, p);
+            }
+            _ => prop_assert!(false, "Type changed: {:?} became {:?}", value, parsed),
+        }
+    }
+}
+
+#[rstest]
+#[case(Value::Float(0.45), 6, "4.50000e-1")]
+#[case(Value::Float(0.45), 2, "4.5e-1")]
+#[case(Value::Float(1.0 / 3.0), 10, "3.333333433e-1")]
+#[case(Value::Float(1e-8), 2, "1.0e-8")]
+#[case(Value::Int(42), 2, "42")]
+fn csv_string_precision(#[case] value: Value, #[case] precision: usize, #[case] expected: &str) {
+    assert_eq!(value.to_csv_string_with_precision(precision), expected);
+}
+
+#[test]
+fn csv_string_keeps_tiny_levels() {
+    let param = ParamKind::AECSILENCELEVEL;
+    let value = Value::Float(1e-8);
+
+    let parsed = param
+        .parse_value(&value.to_csv_string())
+        .expect("CSV string must parse");
+
+    assert_eq!(parsed, value);
+    assert_eq!(parsed.sanitize(&param.def()), parsed, "In range");
+}
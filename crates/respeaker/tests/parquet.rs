@@ -0,0 +1,108 @@
+//! Reads the files of `write_parquet` back with the reference implementation of the `parquet` crate,
+//! the unit tests in `src/parquet.rs` only check them against their own Thrift decoder.
+
+use std::path::Path;
+
+use parquet::basic::{LogicalType, TimeUnit, Type as PhysicalType};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::format::NanoSeconds;
+use parquet::record::Field;
+use respeaker::parquet::{write_parquet, ParquetColumn};
+
+fn read_rows(path: &Path) -> Vec<Vec<(String, Field)>> {
+    SerializedFileReader::try_from(path)
+        .expect("Readable Parquet file")
+        .get_row_iter(None)
+        .expect("Row iterator")
+        .map(|row| row.expect("Valid row").into_columns())
+        .collect()
+}
+
+#[test]
+fn reference_reader_reads_columns_and_nulls() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("recording.parquet");
+    // Long enough for several RLE runs of the definition levels
+    let timestamps = (0..100)
+        .map(|i| (i % 7 != 3).then_some(1_740_826_800_000_000_000 + i * 100_000_000))
+        .collect::<Vec<_>>();
+    let angles = (0..100)
+        .map(|i| (i < 50 || i % 2 == 0).then_some(i))
+        .collect::<Vec<_>>();
+    let rt60 = (0..100_u8)
+        .map(|i| (i >= 90).then_some(0.25 + f32::from(i) / 1000.0))
+        .collect::<Vec<_>>();
+    let columns = vec![
+        (
+            "timestamp".to_string(),
+            ParquetColumn::TimestampNanos(timestamps.clone()),
+        ),
+        ("DOAANGLE".to_string(), ParquetColumn::Int32(angles.clone())),
+        ("RT60".to_string(), ParquetColumn::Float(rt60.clone())),
+    ];
+    let metadata = vec![("serial".to_string(), "MOCK".to_string())];
+
+    write_parquet(&path, &columns, &metadata).expect("Failed to write Parquet file");
+
+    let reader = SerializedFileReader::try_from(path.as_path()).expect("Readable Parquet file");
+    let file = reader.metadata().file_metadata();
+    assert_eq!(file.num_rows(), 100);
+    assert!(file
+        .created_by()
+        .is_some_and(|c| c.starts_with("respeaker-rs version ")));
+    let key_values = file
+        .key_value_metadata()
+        .expect("Key-value metadata")
+        .iter()
+        .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    assert_eq!(key_values, metadata);
+    let schema = file.schema_descr();
+    let types = schema
+        .columns()
+        .iter()
+        .map(|c| (c.name().to_string(), c.physical_type(), c.logical_type()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            (
+                "timestamp".to_string(),
+                PhysicalType::INT64,
+                Some(LogicalType::Timestamp {
+                    is_adjusted_to_u_t_c: true,
+                    unit: TimeUnit::NANOS(NanoSeconds::default()),
+                })
+            ),
+            ("DOAANGLE".to_string(), PhysicalType::INT32, None),
+            ("RT60".to_string(), PhysicalType::FLOAT, None),
+        ]
+    );
+
+    let rows = read_rows(&path);
+    assert_eq!(rows.len(), 100);
+    for (i, row) in rows.iter().enumerate() {
+        let expected = [
+            timestamps[i].map_or(Field::Null, Field::Long),
+            angles[i].map_or(Field::Null, Field::Int),
+            rt60[i].map_or(Field::Null, Field::Float),
+        ];
+        let values = row.iter().map(|(_, v)| v.clone()).collect::<Vec<_>>();
+        assert_eq!(values, expected, "row {i}");
+    }
+}
+
+#[test]
+fn reference_reader_reads_empty_file() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("empty.parquet");
+
+    write_parquet(
+        &path,
+        &[("DOAANGLE".to_string(), ParquetColumn::Int32(vec![]))],
+        &[],
+    )
+    .expect("Failed to write Parquet file");
+
+    assert!(read_rows(&path).is_empty());
+}
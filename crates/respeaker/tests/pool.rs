@@ -0,0 +1,81 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use respeaker::mock::MockDevice;
+use respeaker::params::{ParamKind, ParamState, Value};
+use respeaker::pool::DevicePool;
+use respeaker::recorder::RecordingOptions;
+use respeaker::respeaker_device::ReSpeakerDevice;
+
+fn mock_pool(count: usize) -> (Vec<Arc<MockDevice>>, DevicePool) {
+    let mocks = (0..count)
+        .map(|_| Arc::new(MockDevice::new()))
+        .collect::<Vec<_>>();
+    let devices = mocks
+        .iter()
+        .map(|mock| {
+            ReSpeakerDevice::open_mock(mock.clone(), Arc::new(Mutex::new(ParamState::default())))
+        })
+        .collect();
+    (mocks, DevicePool::from_devices(devices))
+}
+
+#[test]
+fn read_all_devices_in_device_order() {
+    let (mocks, pool) = mock_pool(3);
+    for (angle, mock) in [10, 20, 30].into_iter().zip(&mocks) {
+        mock.set(&ParamKind::DOAANGLE, Value::Int(angle));
+    }
+
+    let values = pool
+        .read_all_devices(&ParamKind::DOAANGLE)
+        .into_iter()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Reads succeed");
+
+    assert_eq!(
+        values,
+        vec![
+            (0, Value::Int(10)),
+            (1, Value::Int(20)),
+            (2, Value::Int(30))
+        ]
+    );
+}
+
+#[test]
+fn write_all_devices() {
+    let (mocks, pool) = mock_pool(2);
+
+    pool.write_all_devices(&ParamKind::AGCONOFF, &Value::Int(1))
+        .expect("Writes succeed");
+
+    for mock in &mocks {
+        assert_eq!(mock.get(&ParamKind::AGCONOFF), Some(Value::Int(1)));
+    }
+    assert!(pool
+        .write_all_devices(&ParamKind::DOAANGLE, &Value::Int(1))
+        .is_err());
+}
+
+#[test]
+fn record_synchronized_writes_one_file_per_device() {
+    let (_, pool) = mock_pool(2);
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+
+    let stats = pool
+        .record_synchronized(
+            Some(0.05),
+            &dir.path().join("recording.csv"),
+            &Arc::new(AtomicBool::new(true)),
+            &RecordingOptions::default(),
+        )
+        .expect("Recordings succeed");
+
+    assert_eq!(stats.len(), 2);
+    for (index, stats) in stats.iter().enumerate() {
+        let path = dir.path().join(format!("recording_device{index}.csv"));
+        assert!(path.exists(), "{path:?} is missing");
+        assert!(stats.rows > 0);
+    }
+}
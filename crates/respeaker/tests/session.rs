@@ -0,0 +1,141 @@
+#![cfg(feature = "tokio")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use respeaker::csv::CsvReader;
+use respeaker::mock::MockDevice;
+use respeaker::params::{Access, ParamKind, ParamState};
+use respeaker::recorder::record_respeaker_parameters_async;
+use respeaker::respeaker_device::ReSpeakerDevice;
+use respeaker::session::{run_session, SessionOptions};
+
+fn mock_device() -> (Arc<MockDevice>, ReSpeakerDevice) {
+    let mock = Arc::new(MockDevice::new());
+    let device =
+        ReSpeakerDevice::open_mock(mock.clone(), Arc::new(Mutex::new(ParamState::default())));
+    (mock, device)
+}
+
+#[test]
+fn async_recording_writes_rows() {
+    let (_mock, device) = mock_device();
+    // Cached RW values must not end up in the recording
+    device.read_rw().expect("Read succeeds");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let csv_path = dir.path().join("recording.csv");
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("Failed to build runtime");
+
+    let stats = runtime
+        .block_on(record_respeaker_parameters_async(
+            Some(0.1),
+            &csv_path,
+            &device,
+            &Arc::new(AtomicBool::new(true)),
+        ))
+        .expect("Recording failed");
+
+    let mut reader = CsvReader::new(&csv_path).expect("Recording is not readable");
+    assert_eq!(reader.metadata().serial.as_deref(), Some("MOCK"));
+    let rows = reader
+        .rows()
+        .collect::<eyre::Result<Vec<_>>>()
+        .expect("Invalid row");
+    assert!(stats.rows > 0);
+    assert_eq!(rows.len() as u64, stats.rows);
+    assert!(rows[0]
+        .values
+        .keys()
+        .all(|p| p.def().access == Access::ReadOnly));
+    assert!(rows[0].values.contains_key(&ParamKind::DOAANGLE));
+}
+
+#[test]
+fn session_stops_after_seconds() {
+    let (_mock, device) = mock_device();
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let options = SessionOptions {
+        record_path: dir.path().join("session.csv"),
+        serve_port: None,
+        bind: None,
+        seconds: Some(0.1),
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("Failed to build runtime");
+
+    let summary = runtime
+        .block_on(run_session(
+            &device,
+            &options,
+            &Arc::new(AtomicBool::new(true)),
+        ))
+        .expect("Session failed");
+
+    assert!(summary.recording.rows > 0);
+    assert_eq!(summary.scrapes, 0);
+    let rows = CsvReader::new(&options.record_path)
+        .expect("Recording is not readable")
+        .rows()
+        .count();
+    assert_eq!(rows as u64, summary.recording.rows);
+}
+
+#[test]
+fn session_serves_device_metrics() {
+    let (_mock, device) = mock_device();
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let port = TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .expect("Free port")
+        .port();
+    let options = SessionOptions {
+        record_path: dir.path().join("session.csv"),
+        serve_port: Some(port),
+        bind: None,
+        seconds: Some(1.0),
+    };
+    let scrape = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("Server is listening");
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .expect("Request is sent");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Response is read");
+        response
+    });
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("Failed to build runtime");
+
+    let summary = runtime
+        .block_on(run_session(
+            &device,
+            &options,
+            &Arc::new(AtomicBool::new(true)),
+        ))
+        .expect("Session failed");
+
+    let response = scrape.join().expect("Scrape thread panicked");
+    assert_eq!(summary.scrapes, 1);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    for metric in [
+        "respeaker_usb_reads_total ",
+        "respeaker_usb_writes_total ",
+        "respeaker_usb_errors_total ",
+        "respeaker_usb_mean_read_latency_seconds ",
+    ] {
+        assert!(response.contains(metric), "{metric} is missing");
+    }
+}